@@ -1,27 +1,155 @@
+use crate::merkle::{self, Hash};
+use crate::transaction::Transaction;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::fmt;
+
+/// 交易无法执行的原因，模拟执行时随回执返回
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecError {
+    UnknownSender,
+    BadNonce { expected: u64, got: u64 },
+    AmountOverflow,
+    InsufficientBalance { balance: u64, required: u64 },
+}
+
+impl ExecError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExecError::UnknownSender => "unknown_sender",
+            ExecError::BadNonce { .. } => "bad_nonce",
+            ExecError::AmountOverflow => "amount_overflow",
+            ExecError::InsufficientBalance { .. } => "insufficient_balance",
+        }
+    }
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::UnknownSender => write!(f, "unknown sender account"),
+            ExecError::BadNonce { expected, got } => {
+                write!(f, "bad nonce: expected {}, got {}", expected, got)
+            }
+            ExecError::AmountOverflow => write!(f, "amount plus fee overflows"),
+            ExecError::InsufficientBalance { balance, required } => {
+                write!(
+                    f,
+                    "insufficient balance: have {}, need {}",
+                    balance, required
+                )
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct AccountState {
     pub balances: HashMap<String, u64>,
+    pub nonces: HashMap<String, u64>,
 }
 
 impl AccountState {
     pub fn new() -> Self {
         AccountState {
             balances: HashMap::new(),
+            nonces: HashMap::new(),
         }
     }
 
-    pub fn apply_transaction(&mut self, from: &str, to: &str, amount: u64) -> bool {
-        let from_balance = self.balances.entry(from.to_string()).or_insert(100); // 初始100
-        if *from_balance < amount {
+    pub fn exists(&self, address: &str) -> bool {
+        self.balances.contains_key(address)
+    }
+
+    pub fn balance_of(&self, address: &str) -> u64 {
+        self.balances.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn nonce_of(&self, address: &str) -> u64 {
+        self.nonces.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn credit(&mut self, address: &str, amount: u64) {
+        *self.balances.entry(address.to_string()).or_insert(0) += amount;
+    }
+
+    /// 执行一笔转账：扣除金额与手续费并递增发送方 nonce，手续费由调用方计入提议者
+    pub fn apply_transaction(&mut self, tx: &Transaction) -> bool {
+        if tx.nonce != self.nonce_of(&tx.from) {
             return false;
         }
-        *from_balance -= amount;
-        let to_balance = self.balances.entry(to.to_string()).or_insert(0);
-        *to_balance += amount;
+        let total = match tx.amount.checked_add(tx.fee) {
+            Some(total) => total,
+            None => return false,
+        };
+        let from_balance = match self.balances.get_mut(&tx.from) {
+            Some(balance) if *balance >= total => balance,
+            _ => return false,
+        };
+        *from_balance -= total;
+        self.credit(&tx.to, tx.amount);
+        *self.nonces.entry(tx.from.clone()).or_insert(0) += 1;
         true
     }
+
+    /// 按与 `apply_transaction` 相同的规则执行转账，失败时返回原因且不修改状态；供模拟执行使用
+    pub fn execute(&mut self, tx: &Transaction) -> Result<(), ExecError> {
+        let expected = self.nonce_of(&tx.from);
+        if tx.nonce != expected {
+            return Err(ExecError::BadNonce {
+                expected,
+                got: tx.nonce,
+            });
+        }
+        let total = tx
+            .amount
+            .checked_add(tx.fee)
+            .ok_or(ExecError::AmountOverflow)?;
+        let from_balance = self
+            .balances
+            .get_mut(&tx.from)
+            .ok_or(ExecError::UnknownSender)?;
+        if *from_balance < total {
+            return Err(ExecError::InsufficientBalance {
+                balance: *from_balance,
+                required: total,
+            });
+        }
+        *from_balance -= total;
+        self.credit(&tx.to, tx.amount);
+        *self.nonces.entry(tx.from.clone()).or_insert(0) += 1;
+        Ok(())
+    }
+
+    /// 按地址排序的账户列表，状态根与账户证明都以此为叶子顺序
+    fn sorted_accounts(&self) -> Vec<(&String, u64)> {
+        let mut accounts: Vec<(&String, u64)> =
+            self.balances.iter().map(|(a, b)| (a, *b)).collect();
+        accounts.sort();
+        accounts
+    }
+
+    fn leaves(&self) -> Vec<Hash> {
+        self.sorted_accounts()
+            .into_iter()
+            .map(|(address, balance)| account_leaf(address, balance, self.nonce_of(address)))
+            .collect()
+    }
+
+    /// 全部账户余额与 nonce 的 Merkle 根，写入区块头供轻客户端验证余额
+    pub fn state_root(&self) -> String {
+        hex::encode(merkle::merkle_root(&self.leaves()))
+    }
+
+    /// 账户在状态树中的位置与 Merkle 路径，账户不存在时返回 None
+    pub fn proof(&self, address: &str) -> Option<(usize, Vec<Hash>)> {
+        let index = self
+            .sorted_accounts()
+            .iter()
+            .position(|(a, _)| a.as_str() == address)?;
+        merkle::merkle_proof(&self.leaves(), index).map(|branch| (index, branch))
+    }
+
     #[allow(dead_code)]
     pub fn show(&self) {
         println!("📊 账户余额：");
@@ -30,3 +158,55 @@ impl AccountState {
         }
     }
 }
+
+/// 状态树的叶子：地址、余额与 nonce 的哈希
+pub fn account_leaf(address: &str, balance: u64, nonce: u64) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}:{}:{}", address, balance, nonce).as_bytes());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn execute_leaves_state_untouched_on_failure() {
+        let mut state = AccountState::new();
+        state.credit("a", 5);
+        let root = state.state_root();
+        let err = state
+            .execute(&Transaction::new("a", "b", 5, 1, 0))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ExecError::InsufficientBalance {
+                balance: 5,
+                required: 6
+            }
+        );
+        assert_eq!(state.state_root(), root);
+        state.execute(&Transaction::new("a", "b", 4, 1, 0)).unwrap();
+        assert_eq!((state.balance_of("a"), state.nonce_of("a")), (0, 1));
+        assert_eq!(state.balance_of("b"), 4);
+    }
+
+    #[test]
+    fn execute_agrees_with_apply_transaction() {
+        let mut state = AccountState::new();
+        state.credit("a", 10);
+        let txs = [
+            Transaction::new("a", "b", 3, 1, 1),
+            Transaction::new("a", "b", 3, 1, 0),
+            Transaction::new("a", "b", u64::MAX, 1, 1),
+            Transaction::new("a", "b", 6, 1, 1),
+            Transaction::new("c", "b", 0, 0, 0),
+            Transaction::new("a", "b", 5, 1, 1),
+        ];
+        let mut executed = state.clone();
+        for tx in &txs {
+            assert_eq!(executed.execute(tx).is_ok(), state.apply_transaction(tx));
+            assert_eq!(executed.state_root(), state.state_root());
+        }
+    }
+}
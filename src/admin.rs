@@ -0,0 +1,392 @@
+use crate::http::{HttpConfig, HttpState};
+use crate::network::NetworkContext;
+use crate::openrpc::{Field, Param, Schema};
+use crate::peers::{self, PeerSource};
+use crate::rpc::{self, Method, Params, RpcError};
+use crate::scoring;
+use rusqlite::{Connection, OpenFlags};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+pub const DEFAULT_TOKEN_FILE: &str = "admin.token";
+/// 管理方法名的前缀，只有认证过的调用方可以调用
+pub const PREFIX: &str = "admin_";
+const SNAPSHOT_DIR: &str = "snapshots";
+
+/// 管理接口的启动参数
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    /// 直接指定的管理凭据，优先于凭据文件
+    pub token: Option<String>,
+    /// 凭据文件，不存在时生成随机凭据写入该文件
+    pub token_file: String,
+    /// 单独的管理端口，只监听 127.0.0.1；指定后 JSON-RPC 主端口不再接受管理方法
+    pub port: Option<u16>,
+}
+
+/// 管理凭据，只保存其哈希，比较时不因前缀匹配而提前返回
+pub struct AdminAuth {
+    digest: [u8; 32],
+}
+
+impl AdminAuth {
+    pub fn load_or_create(config: &AdminConfig) -> io::Result<Self> {
+        let token = match &config.token {
+            Some(token) => token.clone(),
+            None => match fs::read_to_string(&config.token_file) {
+                Ok(token) => token.trim().to_string(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    let token = hex::encode(rand::random::<[u8; 32]>());
+                    write_private(&config.token_file, &token)?;
+                    println!("🔑 已生成管理凭据: {}", config.token_file);
+                    token
+                }
+                Err(e) => return Err(e),
+            },
+        };
+        if token.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "admin token is empty",
+            ));
+        }
+        Ok(AdminAuth {
+            digest: Sha256::digest(token.as_bytes()).into(),
+        })
+    }
+
+    pub fn verify(&self, token: &str) -> bool {
+        let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        digest
+            .iter()
+            .zip(self.digest.iter())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+/// 凭据文件只允许当前用户读写
+fn write_private(path: &str, token: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", token)
+}
+
+/// 在 127.0.0.1 上单独提供带管理方法的 JSON-RPC 服务
+pub async fn start_admin_server(
+    port: u16,
+    ctx: NetworkContext,
+    config: HttpConfig,
+    auth: Arc<AdminAuth>,
+) {
+    println!("🔐 启动管理 RPC 服务，监听 127.0.0.1:{}", port);
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let state = HttpState {
+        node: Some(ctx),
+        config,
+        admin: Some(auth),
+    };
+    rpc::serve(listener, state).await;
+}
+
+pub const METHODS: &[Method] = &[
+    Method {
+        name: "admin_add_peer",
+        summary: "Add a peer to the peer store; the node dials it",
+        params: &[Param::required("addr", Schema::String)],
+        result: Schema::Object(&[Field::required("added", Schema::Boolean)]),
+        handler: handle_add_peer,
+    },
+    Method {
+        name: "admin_peers",
+        summary: "Connected sessions, known peers and active bans",
+        params: &[],
+        result: Schema::Object(&[
+            Field::required("connected", Schema::Array(&Schema::Ref("PeerInfo"))),
+            Field::required("known", Schema::Array(&Schema::Ref("PeerRecord"))),
+            Field::required("bans", Schema::Array(&Schema::Ref("Ban"))),
+        ]),
+        handler: handle_peers,
+    },
+    Method {
+        name: "admin_ban",
+        summary: "Ban a node id or address and disconnect it; duration in seconds, or permanent",
+        params: &[
+            Param::required("target", Schema::String),
+            Param::optional("duration", Schema::Integer),
+            Param::optional("permanent", Schema::Boolean),
+        ],
+        result: Schema::Ref("Ban"),
+        handler: handle_ban,
+    },
+    Method {
+        name: "admin_unban",
+        summary: "Remove bans matching a node id or address",
+        params: &[Param::required("target", Schema::String)],
+        result: Schema::Object(&[Field::required("removed", Schema::Integer)]),
+        handler: handle_unban,
+    },
+    Method {
+        name: "admin_snapshot",
+        summary: "Start writing a consistent copy of chain.db to the snapshots directory",
+        params: &[],
+        result: Schema::Object(&[Field::required("path", Schema::String)]),
+        handler: handle_snapshot,
+    },
+    Method {
+        name: "admin_shutdown",
+        summary: "Save the peer store and stop the node",
+        params: &[],
+        result: Schema::Object(&[Field::required("shutting_down", Schema::Boolean)]),
+        handler: handle_shutdown,
+    },
+];
+
+fn live(node: Option<&NetworkContext>) -> Result<&NetworkContext, RpcError> {
+    node.ok_or_else(RpcError::node_not_running)
+}
+
+fn internal(e: impl ToString) -> RpcError {
+    RpcError::new(rpc::INTERNAL_ERROR, e.to_string())
+}
+
+/// 加入已知节点并写入 peers.db，连接管理任务随后会尝试拨号；参数为 [addr]
+fn handle_add_peer(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let addr = params.str(0)?;
+    if !peers::is_valid_peer_addr(addr) {
+        return Err(RpcError::invalid_params(format!(
+            "invalid peer address `{}`",
+            addr
+        )));
+    }
+    let (added, record) = {
+        let mut peers = ctx.peers.lock().unwrap();
+        let added = peers.add_peer(addr.to_string(), PeerSource::Manual);
+        (added, peers.get(addr).cloned())
+    };
+    if let Some(record) = record {
+        peers::save_records(&ctx.peer_db.lock().unwrap(), &[record]).map_err(internal)?;
+    }
+    println!("[管理] 添加节点: {}", addr);
+    Ok(json!({ "added": added }))
+}
+
+fn handle_peers(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let sessions = ctx.sessions.lock().unwrap().list();
+    let peers = ctx.peers.lock().unwrap();
+    Ok(json!({
+        "connected": sessions,
+        "known": peers.records(),
+        "bans": scoring::active_bans(ctx),
+    }))
+}
+
+/// 封禁并断开节点，参数为 [node_id 或地址, 秒数?, 永久?]；
+/// 已连接的目标按会话的实际 IP 封禁，未连接的目标按地址或已知节点记录查找 node id，
+/// 都找不到时只按 node id 封禁
+fn handle_ban(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let target = params.str(0)?;
+    let permanent = params.opt_bool(2)?.unwrap_or(false);
+    let duration = match params.opt_u64(1)? {
+        Some(_) if permanent => {
+            return Err(RpcError::invalid_params(
+                "`duration` and `permanent` are mutually exclusive".to_string(),
+            ))
+        }
+        _ if permanent => None,
+        Some(secs) => Some(secs),
+        None => Some(scoring::BAN_DURATION_SECS),
+    };
+    let session = ctx
+        .sessions
+        .lock()
+        .unwrap()
+        .list()
+        .into_iter()
+        .find(|p| p.node_id == target || p.addr == target);
+    let (node_id, addr) = match session {
+        Some(peer) => (peer.node_id, peer.remote_addr),
+        None => match ctx.peers.lock().unwrap().get(target) {
+            Some(record) if !record.node_id.is_empty() => {
+                (record.node_id.clone(), target.to_string())
+            }
+            // 没有 node id 时以地址作为封禁记录的键
+            _ if peers::is_valid_peer_addr(target) => (target.to_string(), target.to_string()),
+            _ => (target.to_string(), String::new()),
+        },
+    };
+    let ban = scoring::ban_peer(ctx, &node_id, &addr, "admin", duration).map_err(internal)?;
+    ctx.sessions
+        .lock()
+        .unwrap()
+        .disconnect(&node_id, "banned: admin");
+    println!("[管理] ⛔ 封禁节点 {} ({})", ban.node_id, ban.addr);
+    Ok(json!(ban))
+}
+
+/// 参数为 [node_id 或地址]
+fn handle_unban(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let target = params.str(0)?;
+    let removed = ctx
+        .bans
+        .lock()
+        .unwrap()
+        .remove(&ctx.peer_db.lock().unwrap(), target)
+        .map_err(internal)?;
+    println!("[管理] 解除封禁 {}: {} 条", target, removed);
+    Ok(json!({ "removed": removed }))
+}
+
+/// 在后台线程用独立的只读连接以 `VACUUM INTO` 复制 chain.db，不持有链锁，也不占用节点的数据库连接；
+/// 立即返回快照路径，该文件出现时快照即已完整
+fn handle_snapshot(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let source = ctx
+        .db
+        .lock()
+        .unwrap()
+        .path()
+        .unwrap_or_default()
+        .to_string();
+    if source.is_empty() {
+        return Err(internal("chain database is not backed by a file"));
+    }
+    // 快照目录与 chain.db 位于同一目录下
+    let dir = Path::new(&source).with_file_name(SNAPSHOT_DIR);
+    fs::create_dir_all(&dir).map_err(internal)?;
+    let path = dir
+        .join(format!(
+            "chain-{}.db",
+            chrono::Utc::now().timestamp_millis()
+        ))
+        .to_string_lossy()
+        .into_owned();
+    let target = path.clone();
+    tokio::task::spawn_blocking(move || match write_snapshot(&source, &target) {
+        Ok((height, hash)) => {
+            println!(
+                "[管理] 📦 已写入快照 {} (高度 {}, {})",
+                target, height, hash
+            )
+        }
+        Err(e) => println!("[管理] ❌ 写入快照 {} 失败: {}", target, e),
+    });
+    println!("[管理] 📦 开始写入快照 {}", path);
+    Ok(json!({ "path": path }))
+}
+
+/// 先写入临时文件再改名，返回快照中链顶的高度与哈希
+fn write_snapshot(source: &str, path: &str) -> Result<(u64, String), String> {
+    let partial = format!("{}.partial", path);
+    let conn = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.to_string())?;
+    conn.execute("VACUUM INTO ?1", (&partial,))
+        .map_err(|e| e.to_string())?;
+    let tip = Connection::open_with_flags(&partial, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|snapshot| {
+            snapshot.query_row(
+                "SELECT idx, hash FROM blocks ORDER BY idx DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+        })
+        .map_err(|e| e.to_string())?;
+    fs::rename(&partial, path).map_err(|e| e.to_string())?;
+    Ok(tip)
+}
+
+/// 通知节点退出，响应在退出前发出
+fn handle_shutdown(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    println!("[管理] 🛑 收到关闭请求");
+    ctx.shutdown.notify_one();
+    Ok(json!({ "shutting_down": true }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_token_is_private_and_reused() {
+        let dir = std::env::temp_dir().join(format!("admin-token-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = AdminConfig {
+            token: None,
+            token_file: dir.join(DEFAULT_TOKEN_FILE).to_string_lossy().into_owned(),
+            port: None,
+        };
+        let first = AdminAuth::load_or_create(&config).unwrap();
+        let token = fs::read_to_string(&config.token_file).unwrap();
+        assert!(first.verify(token.trim()));
+        assert!(!first.verify(&token.trim()[1..]));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&config.token_file)
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let again = AdminAuth::load_or_create(&config).unwrap();
+        assert!(again.verify(token.trim()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot_copies_chain_db_through_a_read_only_connection() {
+        let dir = std::env::temp_dir().join(format!("admin-snapshot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("chain.db").to_string_lossy().into_owned();
+        let path = dir.join("snapshot.db").to_string_lossy().into_owned();
+        let conn = Connection::open(&source).unwrap();
+        conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+        crate::node::init_chain_db(&conn);
+        let mut chain = crate::blockchain::Blockchain::genesis();
+        chain.add_dev_block(vec![], 1);
+        for block in &chain.chain {
+            crate::storage::save_block(&conn, block).unwrap();
+        }
+        // 节点的连接仍持有写事务时也能复制已提交的内容
+        conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+        let (height, hash) = write_snapshot(&source, &path).unwrap();
+        conn.execute_batch("COMMIT").unwrap();
+        assert_eq!(height, 1);
+        assert_eq!(hash, chain.chain[1].hash);
+        assert!(!std::path::Path::new(&format!("{}.partial", path)).exists());
+        let snapshot = Connection::open(&path).unwrap();
+        assert!(crate::storage::check_version(&snapshot).unwrap().is_ok());
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn explicit_token_wins_and_empty_is_rejected() {
+        let mut config = AdminConfig {
+            token: Some("secret".into()),
+            token_file: "/nonexistent/admin.token".into(),
+            port: None,
+        };
+        let auth = AdminAuth::load_or_create(&config).unwrap();
+        assert!(auth.verify("secret"));
+        assert!(!auth.verify("secret "));
+        config.token = Some(String::new());
+        assert!(AdminAuth::load_or_create(&config).is_err());
+    }
+}
@@ -1,4 +1,7 @@
+use crate::keys;
+use crate::merkle;
 use crate::transaction::Transaction;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
@@ -10,6 +13,89 @@ pub struct Block {
     pub transactions: Vec<Transaction>,
     pub proposer: String,
     pub hash: String,
+    /// 执行本区块后的账户状态根，创世区块为空
+    #[serde(default)]
+    pub state_root: String,
+    /// 提议者的 ed25519 公钥与对区块哈希的签名，十六进制；创世区块为空
+    #[serde(default)]
+    pub proposer_key: String,
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// 不含交易列表的区块头，交易通过 `tx_root` 承诺到区块哈希中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub index: u64,
+    pub previous_hash: String,
+    pub timestamp: u64,
+    pub tx_root: String,
+    pub proposer: String,
+    pub hash: String,
+    #[serde(default)]
+    pub state_root: String,
+    #[serde(default)]
+    pub proposer_key: String,
+    #[serde(default)]
+    pub signature: String,
+}
+
+fn header_hash(
+    index: u64,
+    previous_hash: &str,
+    timestamp: u64,
+    tx_root: &str,
+    proposer: &str,
+    state_root: &str,
+) -> String {
+    let input = format!(
+        "{}{}{}{}{}{}",
+        index, previous_hash, timestamp, tx_root, proposer, state_root
+    );
+    let mut hasher = Sha256::new();
+    hasher.update(input.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// 签名只覆盖区块哈希，公钥的地址必须等于区块头中的提议者
+fn verify_proposer_signature(proposer: &str, key: &str, signature: &str, hash: &str) -> bool {
+    let Some(key) = hex::decode(key)
+        .ok()
+        .and_then(|b| <[u8; 32]>::try_from(b).ok())
+        .and_then(|b| VerifyingKey::from_bytes(&b).ok())
+    else {
+        return false;
+    };
+    let Some(signature) = hex::decode(signature)
+        .ok()
+        .and_then(|b| <[u8; 64]>::try_from(b).ok())
+        .map(|b| Signature::from_bytes(&b))
+    else {
+        return false;
+    };
+    keys::address_of(key.as_bytes()) == proposer && key.verify(hash.as_bytes(), &signature).is_ok()
+}
+
+impl BlockHeader {
+    pub fn verify_signature(&self) -> bool {
+        verify_proposer_signature(
+            &self.proposer,
+            &self.proposer_key,
+            &self.signature,
+            &self.hash,
+        )
+    }
+
+    pub fn calculate_hash(&self) -> String {
+        header_hash(
+            self.index,
+            &self.previous_hash,
+            self.timestamp,
+            &self.tx_root,
+            &self.proposer,
+            &self.state_root,
+        )
+    }
 }
 
 impl Block {
@@ -18,8 +104,9 @@ impl Block {
         previous_hash: String,
         transactions: Vec<Transaction>,
         proposer: String,
+        state_root: String,
+        timestamp: u64,
     ) -> Self {
-        let timestamp = chrono::Utc::now().timestamp() as u64;
         let mut block = Block {
             index,
             previous_hash,
@@ -27,18 +114,56 @@ impl Block {
             transactions,
             proposer,
             hash: String::new(),
+            state_root,
+            proposer_key: String::new(),
+            signature: String::new(),
         };
         block.hash = block.calculate_hash();
         block
     }
 
+    /// 以提议者的密钥签署区块哈希，哈希确定之后调用
+    pub fn sign(&mut self, key: &SigningKey) {
+        self.proposer_key = hex::encode(key.verifying_key().as_bytes());
+        self.signature = hex::encode(key.sign(self.hash.as_bytes()).to_bytes());
+    }
+
+    pub fn verify_signature(&self) -> bool {
+        verify_proposer_signature(
+            &self.proposer,
+            &self.proposer_key,
+            &self.signature,
+            &self.hash,
+        )
+    }
+
+    pub fn tx_root(&self) -> String {
+        let leaves: Vec<_> = self.transactions.iter().map(|tx| tx.hash_bytes()).collect();
+        hex::encode(merkle::merkle_root(&leaves))
+    }
+
     pub fn calculate_hash(&self) -> String {
-        let input = format!(
-            "{}{}{}{:?}{}",
-            self.index, self.previous_hash, self.timestamp, self.transactions, self.proposer
-        );
-        let mut hasher = Sha256::new();
-        hasher.update(input.as_bytes());
-        format!("{:x}", hasher.finalize())
+        header_hash(
+            self.index,
+            &self.previous_hash,
+            self.timestamp,
+            &self.tx_root(),
+            &self.proposer,
+            &self.state_root,
+        )
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            previous_hash: self.previous_hash.clone(),
+            timestamp: self.timestamp,
+            tx_root: self.tx_root(),
+            proposer: self.proposer.clone(),
+            hash: self.hash.clone(),
+            state_root: self.state_root.clone(),
+            proposer_key: self.proposer_key.clone(),
+            signature: self.signature.clone(),
+        }
     }
 }
@@ -1,35 +1,184 @@
+use crate::accounts::account::AccountState;
 use crate::block::block;
+use crate::keys;
 use crate::transaction;
-use rand::seq::IteratorRandom;
+use ed25519_dalek::SigningKey;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::{Arc, Mutex};
-use crate::accounts::account::AccountState;
 // 如果 crate::accounts::account::AccountState 无法导入，直接将 AccountState 相关定义复制到本文件顶部，或在 main.rs 添加 mod accounts { pub mod account; }。
 
+pub const BLOCK_REWARD: u64 = 50;
+/// 创世区块使用固定时间戳，保证所有节点的创世哈希一致
+pub const GENESIS_TIMESTAMP: u64 = 0;
+/// 允许区块时间戳领先本地时钟的最大秒数
+pub const MAX_FUTURE_DRIFT_SECS: u64 = 30;
+/// 每出一个区块，提议者增加的权益
+pub const PROPOSER_STAKE_INCREMENT: u64 = 10;
+
+/// 验证者登记的签名公钥（十六进制）与权益
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validator {
+    pub public_key: String,
+    pub stake: u64,
+}
+
+/// 创世时的验证者，以地址为键；之后的权益变化完全由区块头中的提议者决定
+pub fn genesis_validators() -> HashMap<String, Validator> {
+    [("Alice", 100), ("Bob", 50)]
+        .into_iter()
+        .map(|(name, stake)| {
+            let public_key = hex::encode(keys::dev_key(name).verifying_key().as_bytes());
+            (keys::dev_address(name), Validator { public_key, stake })
+        })
+        .collect()
+}
+
+/// 创世时的账户余额，键为开发账户的地址
+pub fn genesis_accounts() -> Vec<(String, u64)> {
+    vec![
+        (keys::dev_address("admin"), 1000000),
+        (keys::dev_address("Alice"), 100),
+        (keys::dev_address("Bob"), 100),
+    ]
+}
+
+/// 按权益加权随机选择 `tip` 之上的提议者。
+/// 随机数以链顶哈希为种子，全节点与轻客户端对同一链状态总是选出同一个提议者
+pub fn select_proposer(validators: &HashMap<String, Validator>, tip: &str) -> String {
+    let mut candidates: Vec<(&String, u64)> =
+        validators.iter().map(|(addr, v)| (addr, v.stake)).collect();
+    candidates.sort();
+    let total: u64 = candidates.iter().map(|(_, stake)| stake).sum();
+    if total == 0 {
+        return "fallback".into();
+    }
+    let seed = u64::from_str_radix(tip.get(..16).unwrap_or(tip), 16).unwrap_or(0);
+    let mut pick = StdRng::seed_from_u64(seed).gen_range(0..total);
+    for (addr, stake) in candidates {
+        if pick < stake {
+            return addr.clone();
+        }
+        pick -= stake;
+    }
+    unreachable!("pick is below the total stake")
+}
+
+/// 分叉选择：高度更高的链优先，高度相同时链顶哈希较小的优先，所有节点据此收敛到同一条链
+pub fn prefer(candidate: (u64, &str), current: (u64, &str)) -> bool {
+    candidate.0 > current.0 || (candidate.0 == current.0 && candidate.1 < current.1)
+}
+
+/// 导入外部区块时的校验错误
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockError {
+    /// 区块高度与本地链的下一个高度不连续
+    UnexpectedIndex {
+        expected: u64,
+        got: u64,
+    },
+    UnknownParent,
+    BadHash,
+    UnknownProposer(String),
+    /// 提议者是验证者，但不是按父区块选出的本高度提议者
+    WrongProposer {
+        expected: String,
+        got: String,
+    },
+    BadTimestamp,
+    InvalidTransaction(String),
+    /// 区块声明的状态根与执行结果不一致
+    BadStateRoot,
+    /// 缺少提议者签名、签名无效或签名公钥不属于提议者
+    BadSignature,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::UnexpectedIndex { expected, got } => {
+                write!(
+                    f,
+                    "unexpected block height: expected {}, got {}",
+                    expected, got
+                )
+            }
+            BlockError::UnknownParent => write!(f, "previous hash does not match local tip"),
+            BlockError::BadHash => write!(f, "block hash does not match its contents"),
+            BlockError::UnknownProposer(p) => write!(f, "unknown proposer {}", p),
+            BlockError::WrongProposer { expected, got } => {
+                write!(f, "proposer {} is not the selected {}", got, expected)
+            }
+            BlockError::BadTimestamp => write!(f, "block timestamp out of range"),
+            BlockError::InvalidTransaction(hash) => write!(f, "invalid transaction {}", hash),
+            BlockError::BadStateRoot => write!(f, "state root does not match execution result"),
+            BlockError::BadSignature => write!(f, "missing or invalid proposer signature"),
+        }
+    }
+}
+
+impl BlockError {
+    /// 区块本身无效；高度不连续或父哈希不匹配只说明双方的链暂时不一致
+    pub fn is_invalid(&self) -> bool {
+        !matches!(
+            self,
+            BlockError::UnexpectedIndex { .. } | BlockError::UnknownParent
+        )
+    }
+}
+
+impl std::error::Error for BlockError {}
+
 #[derive(Clone)]
 pub struct Blockchain {
     pub chain: Vec<block::Block>,
-    pub validators: HashMap<String, u64>,
+    pub validators: HashMap<String, Validator>,
     pub state: AccountState,
+    /// 区块中的交易必须签名绑定到该链
+    pub chain_id: String,
 }
 
 impl Blockchain {
     pub fn new() -> Self {
-        let mut bc = Blockchain {
+        Blockchain {
             chain: vec![],
-            validators: HashMap::new(),
+            validators: genesis_validators(),
             state: AccountState::new(),
-        };
-        bc.validators.insert("Alice".into(), 100);
-        bc.validators.insert("Bob".into(), 50);
-        bc
+            chain_id: crate::network::DEFAULT_CHAIN_ID.to_string(),
+        }
+    }
+
+    /// 只有创世区块与创世账户余额的链
+    pub fn genesis() -> Self {
+        let mut chain = Blockchain::new();
+        chain.create_genesis_block();
+        for (address, balance) in genesis_accounts() {
+            chain.state.credit(&address, balance);
+        }
+        chain
     }
 
     pub fn create_genesis_block(&mut self) {
-        let genesis = block::Block::new(0, "0".into(), vec![], "genesis".into());
+        let genesis = block::Block::new(
+            0,
+            "0".into(),
+            vec![],
+            "genesis".into(),
+            String::new(),
+            GENESIS_TIMESTAMP,
+        );
         self.chain.push(genesis);
     }
 
+    pub fn genesis_hash(&self) -> String {
+        self.chain
+            .first()
+            .map(|b| b.hash.clone())
+            .unwrap_or_else(|| "0".to_string())
+    }
+
     pub fn get_last_hash(&self) -> String {
         self.chain
             .last()
@@ -37,33 +186,179 @@ impl Blockchain {
             .unwrap_or_else(|| "0".to_string())
     }
 
+    pub fn height(&self) -> u64 {
+        self.chain.len().saturating_sub(1) as u64
+    }
+
+    pub fn block_by_hash(&self, hash: &str) -> Option<&block::Block> {
+        self.chain.iter().rev().find(|b| b.hash == hash)
+    }
+
+    /// 在当前链顶之上出块的提议者
     pub fn select_proposer(&self) -> String {
-        let mut rng = rand::thread_rng();
-        self.validators
-            .iter()
-            .flat_map(|(addr, stake)| std::iter::repeat(addr).take(*stake as usize))
-            .choose(&mut rng)
-            .unwrap_or(&"fallback".into())
-            .clone()
+        select_proposer(&self.validators, &self.get_last_hash())
     }
 
-    pub fn add_block(&mut self, txs: Vec<transaction::Transaction>) {
-        let proposer = self.select_proposer();
+    /// `key` 对应的验证者是否为链顶之上的提议者
+    pub fn is_proposer(&self, key: &SigningKey) -> bool {
+        keys::address_of(key.verifying_key().as_bytes()) == self.select_proposer()
+    }
 
-        for tx in &txs {
-            self.state.apply_transaction(&tx.from, &tx.to, tx.amount);
+    /// 以验证者密钥 `key` 在链顶追加区块，时间戳取 `now` 与父区块时间戳中较大的一个；
+    /// 该验证者不是本高度的提议者时不出块并返回 false
+    pub fn add_block(
+        &mut self,
+        key: &SigningKey,
+        txs: Vec<transaction::Transaction>,
+        now: u64,
+    ) -> bool {
+        if !self.is_proposer(key) {
+            return false;
         }
+        let proposer = self.select_proposer();
 
-        let block = block::Block::new(
+        let txs: Vec<_> = txs
+            .into_iter()
+            .filter(|tx| self.state.apply_transaction(tx))
+            .collect();
+        let fees: u64 = txs.iter().map(|tx| tx.fee).sum();
+        self.state.credit(&proposer, BLOCK_REWARD + fees);
+
+        let mut block = block::Block::new(
             self.chain.len() as u64,
             self.get_last_hash(),
             txs,
             proposer.clone(),
+            self.state.state_root(),
+            now.max(self.chain.last().map(|b| b.timestamp).unwrap_or(0)),
         );
+        block.sign(key);
+
+        self.add_stake(&proposer);
+        self.chain.push(block);
+        true
+    }
+
+    /// 以选中提议者的开发密钥出块，供测试构造链
+    #[cfg(test)]
+    pub(crate) fn add_dev_block(&mut self, txs: Vec<transaction::Transaction>, now: u64) {
+        let key =
+            keys::dev_signer(&self.select_proposer()).expect("genesis validators are dev accounts");
+        assert!(self.add_block(&key, txs, now));
+    }
+
+    fn add_stake(&mut self, proposer: &str) {
+        if let Some(validator) = self.validators.get_mut(proposer) {
+            validator.stake += PROPOSER_STAKE_INCREMENT;
+        }
+    }
+
+    /// 校验并追加来自其他节点的区块，任何一笔交易无法执行时整个区块被拒绝；
+    /// `now` 为本地时间，区块时间戳最多领先它 `MAX_FUTURE_DRIFT_SECS`
+    pub fn import_block(&mut self, block: block::Block, now: u64) -> Result<(), BlockError> {
+        let expected = self.chain.len() as u64;
+        if block.index != expected {
+            return Err(BlockError::UnexpectedIndex {
+                expected,
+                got: block.index,
+            });
+        }
+        if block.previous_hash != self.get_last_hash() {
+            return Err(BlockError::UnknownParent);
+        }
+        if block.hash != block.calculate_hash() {
+            return Err(BlockError::BadHash);
+        }
+        let Some(validator) = self.validators.get(&block.proposer) else {
+            return Err(BlockError::UnknownProposer(block.proposer.clone()));
+        };
+        // 区块必须由验证者登记的公钥签名
+        if block.proposer_key != validator.public_key || !block.verify_signature() {
+            return Err(BlockError::BadSignature);
+        }
+        let selected = self.select_proposer();
+        if block.proposer != selected {
+            return Err(BlockError::WrongProposer {
+                expected: selected,
+                got: block.proposer.clone(),
+            });
+        }
+        let parent_time = self.chain.last().map(|b| b.timestamp).unwrap_or(0);
+        if block.timestamp < parent_time || block.timestamp > now + MAX_FUTURE_DRIFT_SECS {
+            return Err(BlockError::BadTimestamp);
+        }
 
-        *self.validators.entry(proposer.clone()).or_insert(0) += 10;
+        let mut state = self.state.clone();
+        for tx in &block.transactions {
+            if tx.chain_id != self.chain_id
+                || !tx.verify_signature()
+                || !state.apply_transaction(tx)
+            {
+                return Err(BlockError::InvalidTransaction(tx.hash()));
+            }
+        }
+        let fees: u64 = block.transactions.iter().map(|tx| tx.fee).sum();
+        state.credit(&block.proposer, BLOCK_REWARD + fees);
+        if block.state_root != state.state_root() {
+            return Err(BlockError::BadStateRoot);
+        }
 
+        self.state = state;
+        self.add_stake(&block.proposer);
         self.chain.push(block);
+        Ok(())
+    }
+
+    /// 执行一个已经校验过的区块，只用于重放本地链上的区块
+    fn replay_block(&mut self, block: &block::Block) {
+        for tx in &block.transactions {
+            self.state.apply_transaction(tx);
+        }
+        let fees: u64 = block.transactions.iter().map(|tx| tx.fee).sum();
+        self.state.credit(&block.proposer, BLOCK_REWARD + fees);
+        self.add_stake(&block.proposer);
+        self.chain.push(block.clone());
+    }
+
+    /// 切换到在 `base` 高度分叉出的另一条链：从创世重放到 `base`，再逐个校验导入 `blocks`。
+    /// 新链不优于当前链时保持不变并返回 Ok(None)，切换成功时返回被替换掉的区块
+    pub fn reorg(
+        &mut self,
+        base: u64,
+        blocks: Vec<block::Block>,
+        now: u64,
+    ) -> Result<Option<Vec<block::Block>>, BlockError> {
+        let mut candidate = Blockchain::genesis();
+        candidate.chain_id = self.chain_id.clone();
+        for block in self.chain.iter().take(base as usize + 1).skip(1) {
+            candidate.replay_block(block);
+        }
+        for block in blocks {
+            candidate.import_block(block, now)?;
+        }
+        if !prefer(
+            (candidate.height(), &candidate.get_last_hash()),
+            (self.height(), &self.get_last_hash()),
+        ) {
+            return Ok(None);
+        }
+        let orphaned = self.chain.split_off(base as usize + 1);
+        *self = candidate;
+        Ok(Some(orphaned))
+    }
+
+    /// 从创世验证者开始，按链上各区块的提议者重新累计权益；从 chain.db 载入区块后调用
+    pub fn replay_validators(&mut self) {
+        self.validators = genesis_validators();
+        let proposers: Vec<String> = self
+            .chain
+            .iter()
+            .skip(1)
+            .map(|b| b.proposer.clone())
+            .collect();
+        for proposer in proposers {
+            self.add_stake(&proposer);
+        }
     }
 
     #[allow(dead_code)]
@@ -81,3 +376,240 @@ impl Blockchain {
         Arc::new(Mutex::new(self))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transaction;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn import_rejects_block_with_forged_transaction() {
+        let mut producer = Blockchain::genesis();
+        let mut forged = Transaction::new(&keys::dev_address("admin"), "b", 10, 0, 0);
+        forged.sign(&keys::dev_key("Alice"));
+        // 出块方不校验签名时，伪造交易会被打包；导入方必须拒绝
+        producer.add_dev_block(vec![forged], NOW);
+        let block = producer.chain.last().unwrap().clone();
+        let mut importer = Blockchain::genesis();
+        assert!(matches!(
+            importer.import_block(block, NOW),
+            Err(BlockError::InvalidTransaction(_))
+        ));
+    }
+
+    #[test]
+    fn import_accepts_block_with_signed_transaction() {
+        let mut producer = Blockchain::genesis();
+        let tx = Transaction::signed(&keys::dev_key("admin"), "b", 10, 1, 0);
+        producer.add_dev_block(vec![tx], NOW);
+        let block = producer.chain.last().unwrap().clone();
+        let mut importer = Blockchain::genesis();
+        importer.import_block(block, NOW).unwrap();
+        assert_eq!(importer.state.balance_of("b"), 10);
+    }
+
+    #[test]
+    fn import_rejects_unsigned_or_resigned_block() {
+        let mut producer = Blockchain::genesis();
+        producer.add_dev_block(vec![], NOW);
+        let signed = producer.chain.last().unwrap().clone();
+        assert!(signed.verify_signature());
+
+        let mut unsigned = signed.clone();
+        unsigned.signature.clear();
+        // 非提议者的密钥签名同样无效
+        let mut resigned = signed.clone();
+        resigned.sign(&keys::dev_key("Charlie"));
+        for block in [unsigned, resigned] {
+            assert_eq!(
+                Blockchain::genesis().import_block(block, NOW),
+                Err(BlockError::BadSignature)
+            );
+        }
+        Blockchain::genesis().import_block(signed, NOW).unwrap();
+    }
+
+    #[test]
+    fn import_rejects_block_from_out_of_turn_validator() {
+        let chain = Blockchain::genesis();
+        let selected = chain.select_proposer();
+        let name = if selected == keys::dev_address("Alice") {
+            "Bob"
+        } else {
+            "Alice"
+        };
+        let other = keys::dev_address(name);
+        // 其他验证者用自己的密钥正确签名、状态根也正确，只是不该在这个高度出块
+        let mut state = chain.state.clone();
+        state.credit(&other, BLOCK_REWARD);
+        let mut block = block::Block::new(
+            1,
+            chain.get_last_hash(),
+            vec![],
+            other.clone(),
+            state.state_root(),
+            NOW,
+        );
+        block.sign(&keys::dev_key(name));
+        assert!(block.verify_signature());
+        let err = Blockchain::genesis().import_block(block, NOW).unwrap_err();
+        assert_eq!(
+            err,
+            BlockError::WrongProposer {
+                expected: selected,
+                got: other
+            }
+        );
+        assert!(err.is_invalid());
+    }
+
+    #[test]
+    fn only_the_selected_validator_produces() {
+        let mut chain = Blockchain::genesis();
+        let selected = chain.select_proposer();
+        let other = if selected == keys::dev_address("Alice") {
+            "Bob"
+        } else {
+            "Alice"
+        };
+        assert!(!chain.add_block(&keys::dev_key(other), vec![], NOW));
+        assert_eq!(chain.height(), 0);
+        assert!(chain.add_block(&keys::dev_signer(&selected).unwrap(), vec![], NOW));
+        assert_eq!(chain.height(), 1);
+    }
+
+    #[test]
+    fn selection_includes_validators_without_dev_keys() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let address = keys::address_of(key.verifying_key().as_bytes());
+        let mut chain = Blockchain::genesis();
+        chain.validators.insert(
+            address.clone(),
+            Validator {
+                public_key: hex::encode(key.verifying_key().as_bytes()),
+                stake: u64::MAX / 2,
+            },
+        );
+        assert_eq!(chain.select_proposer(), address);
+        assert!(chain.add_block(&key, vec![], NOW));
+        let mut importer = Blockchain::genesis();
+        importer.validators = chain.validators.clone();
+        importer.validators.get_mut(&address).unwrap().stake = u64::MAX / 2;
+        importer.import_block(chain.chain[1].clone(), NOW).unwrap();
+    }
+
+    /// 在高度 1 之后分叉的两条链，分叉上的第一个区块包含不同的交易
+    fn forked_pair(left_len: usize, right_len: usize) -> (Blockchain, Blockchain) {
+        let mut left = Blockchain::genesis();
+        left.add_dev_block(vec![], NOW);
+        let mut right = left.clone();
+        for i in 0..left_len {
+            let txs = if i == 0 {
+                vec![Transaction::signed(
+                    &keys::dev_key("admin"),
+                    "left",
+                    10,
+                    1,
+                    0,
+                )]
+            } else {
+                vec![]
+            };
+            left.add_dev_block(txs, NOW);
+        }
+        for i in 0..right_len {
+            let txs = if i == 0 {
+                vec![Transaction::signed(
+                    &keys::dev_key("admin"),
+                    "right",
+                    10,
+                    1,
+                    0,
+                )]
+            } else {
+                vec![]
+            };
+            right.add_dev_block(txs, NOW);
+        }
+        (left, right)
+    }
+
+    #[test]
+    fn reorg_switches_to_longer_fork() {
+        let (mut left, right) = forked_pair(1, 2);
+        let orphaned = left
+            .reorg(1, right.chain[2..].to_vec(), NOW)
+            .unwrap()
+            .unwrap();
+        assert_eq!(orphaned.len(), 1);
+        assert_eq!(left.get_last_hash(), right.get_last_hash());
+        assert_eq!(left.state.balance_of("left"), 0);
+        assert_eq!(left.state.balance_of("right"), 10);
+        assert_eq!(left.validators, right.validators);
+    }
+
+    #[test]
+    fn reorg_ignores_shorter_fork() {
+        let (left, mut right) = forked_pair(1, 2);
+        let tip = right.get_last_hash();
+        assert!(right
+            .reorg(1, left.chain[2..].to_vec(), NOW)
+            .unwrap()
+            .is_none());
+        assert_eq!(right.get_last_hash(), tip);
+        assert_eq!(right.state.balance_of("right"), 10);
+    }
+
+    #[test]
+    fn reorg_breaks_height_ties_by_smaller_hash() {
+        let (left, right) = forked_pair(1, 1);
+        let (mut winner, mut loser) = if left.get_last_hash() < right.get_last_hash() {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        let winner_blocks = winner.chain[2..].to_vec();
+        assert!(winner
+            .reorg(1, loser.chain[2..].to_vec(), NOW)
+            .unwrap()
+            .is_none());
+        assert!(loser.reorg(1, winner_blocks, NOW).unwrap().is_some());
+        assert_eq!(loser.get_last_hash(), winner.get_last_hash());
+    }
+
+    #[test]
+    fn reorg_keeps_the_configured_chain_id() {
+        let mut left = Blockchain::genesis();
+        left.chain_id = "custom-chain".into();
+        left.add_dev_block(vec![], NOW);
+        let mut right = left.clone();
+        left.add_dev_block(vec![], NOW);
+        let mut tx = Transaction::new(&keys::dev_address("admin"), "right", 10, 1, 0)
+            .with_chain_id("custom-chain");
+        tx.sign(&keys::dev_key("admin"));
+        right.add_dev_block(vec![tx], NOW);
+        right.add_dev_block(vec![], NOW);
+        // 分叉中的交易按本节点配置的链 ID 校验，切换后仍沿用该链 ID
+        assert!(left
+            .reorg(1, right.chain[2..].to_vec(), NOW)
+            .unwrap()
+            .is_some());
+        assert_eq!(left.chain_id, "custom-chain");
+        assert_eq!(left.state.balance_of("right"), 10);
+    }
+
+    #[test]
+    fn reorg_rejects_invalid_fork_block() {
+        let (mut left, right) = forked_pair(1, 2);
+        let tip = left.get_last_hash();
+        let mut blocks = right.chain[2..].to_vec();
+        blocks[1].signature.clear();
+        assert!(matches!(
+            left.reorg(1, blocks, NOW),
+            Err(BlockError::BadSignature)
+        ));
+        assert_eq!(left.get_last_hash(), tip);
+    }
+}
@@ -14,10 +14,91 @@ pub enum Command {
         from: String,
         to: String,
         amount: u64,
+        #[arg(long, default_value_t = 0)]
+        fee: u64,
+        #[arg(long)]
+        nonce: Option<u64>,
+        /// 交易绑定的链 ID，需与本地节点一致
+        #[arg(long, default_value = crate::network::DEFAULT_CHAIN_ID)]
+        chain_id: String,
+    },
+    /// 以开发账户的密钥签名一笔转账并打印交易 JSON，可作为 send_transaction 的参数
+    Sign {
+        from: String,
+        to: String,
+        amount: u64,
+        #[arg(long, default_value_t = 0)]
+        fee: u64,
+        #[arg(long)]
+        nonce: u64,
+        /// 签名绑定的链 ID
+        #[arg(long, default_value = crate::network::DEFAULT_CHAIN_ID)]
+        chain_id: String,
+    },
+    /// 在本地链状态上模拟执行转账而不提交
+    DryRun {
+        from: String,
+        to: String,
+        amount: u64,
+        #[arg(long, default_value_t = 0)]
+        fee: u64,
+        #[arg(long)]
+        nonce: Option<u64>,
+        /// 只基于链顶状态，不计入 mempool 中的待打包交易
+        #[arg(long)]
+        latest: bool,
+    },
+    /// 以更高手续费的零金额自转账取消一笔待打包交易
+    Cancel {
+        from: String,
+        nonce: u64,
+        #[arg(long)]
+        fee: u64,
+        /// 交易绑定的链 ID，需与本地节点一致
+        #[arg(long, default_value = crate::network::DEFAULT_CHAIN_ID)]
+        chain_id: String,
     },
     Run {
         #[arg(default_value = "8000", value_parser)]
         port: u16,
+        #[arg(long, default_value = crate::network::DEFAULT_CHAIN_ID)]
+        chain_id: String,
+        /// 握手中公布给其他节点的地址，默认 127.0.0.1:<port>
+        #[arg(long)]
+        advertise: Option<String>,
+        /// 出块使用的验证者私钥（十六进制）或开发账户名，未指定时节点不出块
+        #[arg(long)]
+        validator_key: Option<String>,
+        /// 保存验证者私钥（十六进制）的文件
+        #[arg(long, conflicts_with = "validator_key")]
+        validator_key_file: Option<String>,
+        /// 入站会话数量上限
+        #[arg(long, default_value_t = crate::network::DEFAULT_MAX_INBOUND)]
+        max_inbound: usize,
+        /// 出站会话数量上限
+        #[arg(long, default_value_t = crate::network::DEFAULT_MAX_OUTBOUND)]
+        max_outbound: usize,
+        /// 单个 IP 的入站连接数量上限
+        #[arg(long, default_value_t = crate::network::DEFAULT_MAX_PER_IP)]
+        max_per_ip: usize,
+        /// 启动时加入已知节点的种子节点地址，可重复指定
+        #[arg(long = "seed")]
+        seeds: Vec<String>,
+        /// JSON-RPC 请求体大小上限（字节）
+        #[arg(long, default_value_t = crate::http::DEFAULT_MAX_BODY_BYTES)]
+        rpc_max_body: usize,
+        /// 允许跨域访问 JSON-RPC 的来源，可重复指定，`*` 表示任意来源
+        #[arg(long = "rpc-cors-origin")]
+        rpc_cors_origins: Vec<String>,
+        /// 管理方法的凭据，缺省时读取凭据文件
+        #[arg(long)]
+        admin_token: Option<String>,
+        /// 管理凭据文件，不存在时生成随机凭据写入该文件
+        #[arg(long, default_value = crate::admin::DEFAULT_TOKEN_FILE)]
+        admin_token_file: String,
+        /// 在 127.0.0.1 的该端口单独提供管理方法，主 RPC 端口不再接受管理方法
+        #[arg(long)]
+        admin_port: Option<u16>,
     },
     Query {
         index: u64,
@@ -25,16 +106,93 @@ pub enum Command {
     QueryBalance {
         address: String,
     },
+    /// 打印开发账户的地址，缺省时列出全部开发账户
+    Address {
+        name: Option<String>,
+    },
     AddPeer {
         addr: String,
     },
     QueryPeers,
     JsonRpcServer {
         port: u16,
+        /// JSON-RPC 请求体大小上限（字节）
+        #[arg(long, default_value_t = crate::http::DEFAULT_MAX_BODY_BYTES)]
+        rpc_max_body: usize,
+        /// 允许跨域访问 JSON-RPC 的来源，可重复指定，`*` 表示任意来源
+        #[arg(long = "rpc-cors-origin")]
+        rpc_cors_origins: Vec<String>,
     },
     QueryTx {
         hash: String,
     },
+    /// 输出描述全部 RPC 方法的 OpenRPC 文档
+    #[command(name = "openrpc")]
+    OpenRpc {
+        /// 写入该文件，缺省时打印到标准输出
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// 列出 peers.db 中仍然有效的封禁
+    ListBans,
+    /// 按 node id 或地址封禁节点，运行中的节点在下一次维护时读到
+    Ban {
+        target: String,
+        /// 封禁秒数，缺省为 24 小时
+        #[arg(long, conflicts_with = "permanent")]
+        duration: Option<u64>,
+        /// 永久封禁，直到手动解除
+        #[arg(long)]
+        permanent: bool,
+    },
+    /// 按 node id 或地址解除封禁
+    Unban {
+        target: String,
+    },
+    /// 轻客户端模式：只同步区块头，通过 Merkle 证明验证余额与交易
+    Light {
+        /// 提供数据的全节点地址
+        peer: String,
+        #[arg(long, default_value = crate::network::DEFAULT_CHAIN_ID)]
+        chain_id: String,
+        /// 查询并验证该地址的余额
+        #[arg(long)]
+        balance: Option<String>,
+        /// 查询并验证该交易是否已上链
+        #[arg(long)]
+        tx: Option<String>,
+    },
+    /// 在单个进程内以虚拟时钟模拟多节点网络
+    Simulate {
+        #[arg(long, default_value_t = 4)]
+        nodes: usize,
+        /// 模拟的虚拟时长（秒）
+        #[arg(long, default_value_t = 60)]
+        duration: u64,
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+        #[arg(long, default_value_t = 50)]
+        latency_ms: u64,
+        #[arg(long, default_value_t = 20)]
+        jitter_ms: u64,
+        /// 消息丢失概率，0 到 1 之间
+        #[arg(long, default_value_t = 0.0)]
+        loss: f64,
+        #[arg(long, default_value_t = 3)]
+        block_interval: u64,
+        /// 出块节点的序号，可重复指定；偶数节点持有 Alice、奇数节点持有 Bob 的验证者密钥，默认节点 0 与 1 出块
+        #[arg(long = "producer")]
+        producers: Vec<usize>,
+        /// 在该秒把节点分成两半
+        #[arg(long)]
+        partition_at: Option<u64>,
+        /// 在该秒恢复分区
+        #[arg(long)]
+        heal_at: Option<u64>,
+        /// 开始时提交的转账数量
+        #[arg(long, default_value_t = 0)]
+        txs: u64,
+    },
 }
 
 pub fn parse_cli() -> Cli {
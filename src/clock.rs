@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// 网络层的时间来源：运行中的节点读取系统时间，模拟器使用由自己推进的虚拟时钟
+#[derive(Clone, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    /// 以毫秒计的虚拟时间
+    Virtual(Arc<AtomicU64>),
+}
+
+impl Clock {
+    pub fn new_virtual() -> Self {
+        Clock::Virtual(Arc::new(AtomicU64::new(0)))
+    }
+
+    pub fn now_millis(&self) -> u64 {
+        match self {
+            Clock::System => chrono::Utc::now().timestamp_millis() as u64,
+            Clock::Virtual(ms) => ms.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn now_secs(&self) -> u64 {
+        self.now_millis() / 1000
+    }
+
+    /// 把虚拟时钟推进到 `ms`，系统时钟或更早的时间点被忽略
+    pub fn advance_to(&self, ms: u64) {
+        if let Clock::Virtual(now) = self {
+            now.fetch_max(ms, Ordering::SeqCst);
+        }
+    }
+}
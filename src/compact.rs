@@ -0,0 +1,405 @@
+use crate::block::block::Block;
+use crate::network::{self, NetworkContext};
+use crate::protocol::Message;
+use crate::scoring::{self, Misbehavior};
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// 短 id 取哈希的前 6 字节
+const SHORT_ID_BYTES: usize = 6;
+/// 等待对端补齐交易的最长时间，超时后向其他对端请求完整区块
+const PENDING_TIMEOUT_SECS: u64 = 5;
+const MAX_PENDING: usize = 16;
+
+/// 压缩区块：完整的区块头字段，交易只以短 id 表示，接收方从自己的 mempool 还原
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactBlock {
+    pub index: u64,
+    pub previous_hash: String,
+    pub timestamp: u64,
+    pub proposer: String,
+    pub hash: String,
+    #[serde(default)]
+    pub state_root: String,
+    #[serde(default)]
+    pub proposer_key: String,
+    #[serde(default)]
+    pub signature: String,
+    /// 按区块内顺序排列的交易短 id
+    pub short_ids: Vec<String>,
+}
+
+/// 按区块内位置请求还原时缺少的交易
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockTxsRequest {
+    pub block_hash: String,
+    pub indexes: Vec<usize>,
+}
+
+/// 按请求顺序返回的交易
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockTxs {
+    pub block_hash: String,
+    pub txs: Vec<Transaction>,
+}
+
+/// 交易短 id 混入区块哈希，不同区块中同一交易的短 id 不同，难以构造碰撞
+pub fn short_id(block_hash: &str, tx_hash: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(block_hash.as_bytes());
+    hasher.update(tx_hash.as_bytes());
+    hex::encode(&hasher.finalize()[..SHORT_ID_BYTES])
+}
+
+impl CompactBlock {
+    pub fn from_block(block: &Block) -> Self {
+        CompactBlock {
+            index: block.index,
+            previous_hash: block.previous_hash.clone(),
+            timestamp: block.timestamp,
+            proposer: block.proposer.clone(),
+            hash: block.hash.clone(),
+            state_root: block.state_root.clone(),
+            proposer_key: block.proposer_key.clone(),
+            signature: block.signature.clone(),
+            short_ids: block
+                .transactions
+                .iter()
+                .map(|tx| short_id(&block.hash, &tx.hash()))
+                .collect(),
+        }
+    }
+
+    fn to_block(&self, transactions: Vec<Transaction>) -> Block {
+        Block {
+            index: self.index,
+            previous_hash: self.previous_hash.clone(),
+            timestamp: self.timestamp,
+            transactions,
+            proposer: self.proposer.clone(),
+            hash: self.hash.clone(),
+            state_root: self.state_root.clone(),
+            proposer_key: self.proposer_key.clone(),
+            signature: self.signature.clone(),
+        }
+    }
+}
+
+/// 等待对端补齐交易的压缩区块
+struct PendingBlock {
+    compact: CompactBlock,
+    peer: String,
+    txs: Vec<Option<Transaction>>,
+    /// 已向对端请求的交易位置
+    requested: Vec<usize>,
+    /// 本次请求是否覆盖了全部交易，此时还原失败只能是对端的问题
+    full: bool,
+    received_at: u64,
+}
+
+#[derive(Default)]
+pub struct CompactRelay {
+    pending: HashMap<String, PendingBlock>,
+}
+
+impl CompactRelay {
+    fn insert(&mut self, pending: PendingBlock) {
+        while self.pending.len() >= MAX_PENDING {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, p)| p.received_at)
+                .map(|(hash, _)| hash.clone());
+            match oldest {
+                Some(hash) => self.pending.remove(&hash),
+                None => break,
+            };
+        }
+        self.pending.insert(pending.compact.hash.clone(), pending);
+    }
+
+    /// 取出等待超时的压缩区块
+    fn take_expired(&mut self, now: u64) -> Vec<PendingBlock> {
+        let expired: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now >= p.received_at + PENDING_TIMEOUT_SECS)
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|hash| self.pending.remove(hash))
+            .collect()
+    }
+}
+
+/// 用 mempool 中的交易还原压缩区块，缺少的交易向发送方请求
+/// 区块导入后才记为已见并更新对端链顶，还原失败的区块可以再从其他对端获取
+pub fn on_compact_block(ctx: &NetworkContext, peer_id: &str, compact: CompactBlock) {
+    println!(
+        "📥 接收到压缩区块: {} from {} ({} 笔交易)",
+        compact.index,
+        compact.proposer,
+        compact.short_ids.len()
+    );
+    ctx.gossip
+        .lock()
+        .unwrap()
+        .mark_known(peer_id, &compact.hash);
+    if ctx
+        .chain
+        .lock()
+        .unwrap()
+        .block_by_hash(&compact.hash)
+        .is_some()
+        || ctx
+            .compact
+            .lock()
+            .unwrap()
+            .pending
+            .contains_key(&compact.hash)
+    {
+        return;
+    }
+    let txs: Vec<Option<Transaction>> = {
+        let mempool = ctx.mempool.lock().unwrap();
+        let by_short_id: HashMap<String, &Transaction> = mempool
+            .transactions()
+            .map(|tx| (short_id(&compact.hash, &tx.hash()), tx))
+            .collect();
+        compact
+            .short_ids
+            .iter()
+            .map(|id| by_short_id.get(id).map(|tx| (*tx).clone()))
+            .collect()
+    };
+    let missing: Vec<usize> = (0..txs.len()).filter(|&i| txs[i].is_none()).collect();
+    let pending = PendingBlock {
+        compact,
+        peer: peer_id.to_string(),
+        txs,
+        full: false,
+        requested: Vec::new(),
+        received_at: ctx.clock.now_secs(),
+    };
+    if missing.is_empty() {
+        finish(ctx, pending);
+    } else {
+        println!(
+            "🧩 区块 {} 缺少 {} 笔交易，向对端请求",
+            pending.compact.index,
+            missing.len()
+        );
+        request_missing(ctx, pending, missing);
+    }
+}
+
+fn request_missing(ctx: &NetworkContext, mut pending: PendingBlock, indexes: Vec<usize>) {
+    pending.full = indexes.len() == pending.txs.len();
+    pending.requested = indexes.clone();
+    let request = BlockTxsRequest {
+        block_hash: pending.compact.hash.clone(),
+        indexes,
+    };
+    let peer = pending.peer.clone();
+    ctx.compact.lock().unwrap().insert(pending);
+    ctx.sessions
+        .lock()
+        .unwrap()
+        .send(&peer, Message::GetBlockTxs(request));
+}
+
+/// 交易齐全后校验还原结果；交易根不符时先退回到请求全部交易，仍不符则视为无效区块
+fn finish(ctx: &NetworkContext, pending: PendingBlock) {
+    let txs: Vec<Transaction> = pending.txs.iter().flatten().cloned().collect();
+    let block = pending.compact.to_block(txs);
+    if block.calculate_hash() != block.hash {
+        if pending.full {
+            println!("⚠️ 节点 {} 的区块 {} 无法还原", pending.peer, block.index);
+            scoring::report(ctx, &pending.peer, Misbehavior::InvalidBlock);
+            request_full_block(ctx, &pending);
+        } else {
+            println!("🧩 区块 {} 短 id 冲突，改为请求全部交易", block.index);
+            let all: Vec<usize> = (0..pending.txs.len()).collect();
+            request_missing(ctx, pending, all);
+        }
+        return;
+    }
+    network::import_relayed_block(ctx, &pending.peer, block);
+}
+
+/// 压缩区块超时或无法还原时，向另一个对端请求完整区块，优先选择已知拥有该区块的对端
+fn request_full_block(ctx: &NetworkContext, pending: &PendingBlock) {
+    let hash = &pending.compact.hash;
+    let others: Vec<String> = ctx
+        .sessions
+        .lock()
+        .unwrap()
+        .list()
+        .into_iter()
+        .map(|p| p.node_id)
+        .filter(|id| *id != pending.peer)
+        .collect();
+    let knowing = ctx.gossip.lock().unwrap().peers_knowing(hash);
+    let target = knowing
+        .into_iter()
+        .find(|id| others.contains(id))
+        .or_else(|| others.into_iter().next());
+    let Some(peer) = target else {
+        println!("⚠️ 没有其他对端可以提供区块 {}", pending.compact.index);
+        return;
+    };
+    println!(
+        "🔁 区块 {} 改为向 {} 请求完整区块",
+        pending.compact.index, peer
+    );
+    ctx.sessions
+        .lock()
+        .unwrap()
+        .send(&peer, Message::GetBlock(hash.clone()));
+}
+
+/// 定期检查等待交易超时的压缩区块
+pub fn tick(ctx: &NetworkContext) {
+    let expired = ctx
+        .compact
+        .lock()
+        .unwrap()
+        .take_expired(ctx.clock.now_secs());
+    for pending in expired {
+        println!(
+            "⌛ 节点 {} 未及时补齐区块 {} 的交易",
+            pending.peer, pending.compact.index
+        );
+        request_full_block(ctx, &pending);
+    }
+}
+
+/// 回复本地链上区块中指定位置的交易
+pub fn on_get_block_txs(ctx: &NetworkContext, peer_id: &str, request: BlockTxsRequest) {
+    let txs: Vec<Transaction> = {
+        let chain = ctx.chain.lock().unwrap();
+        let Some(block) = chain.block_by_hash(&request.block_hash) else {
+            return;
+        };
+        request
+            .indexes
+            .iter()
+            .filter_map(|&i| block.transactions.get(i).cloned())
+            .collect()
+    };
+    ctx.sessions.lock().unwrap().send(
+        peer_id,
+        Message::BlockTxs(BlockTxs {
+            block_hash: request.block_hash,
+            txs,
+        }),
+    );
+}
+
+/// 用对端返回的交易补齐等待中的压缩区块
+pub fn on_block_txs(ctx: &NetworkContext, peer_id: &str, response: BlockTxs) {
+    let mut pending = {
+        let mut relay = ctx.compact.lock().unwrap();
+        match relay.pending.get(&response.block_hash) {
+            Some(p) if p.peer == peer_id => relay.pending.remove(&response.block_hash).unwrap(),
+            _ => return,
+        }
+    };
+    if response.txs.len() != pending.requested.len() {
+        println!(
+            "⚠️ 节点 {} 返回的区块 {} 交易数量不符",
+            peer_id, pending.compact.index
+        );
+        scoring::report(ctx, peer_id, Misbehavior::InvalidBlock);
+        request_full_block(ctx, &pending);
+        return;
+    }
+    for (i, tx) in pending.requested.iter().zip(response.txs) {
+        pending.txs[*i] = Some(tx);
+    }
+    finish(ctx, pending);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::keys;
+
+    fn block_with_txs() -> Block {
+        let mut chain = Blockchain::genesis();
+        let admin = keys::dev_key("admin");
+        let txs = (0..3)
+            .map(|nonce| Transaction::signed(&admin, "b", 1, 1, nonce))
+            .collect();
+        chain.add_dev_block(txs, 1);
+        chain.chain.last().unwrap().clone()
+    }
+
+    fn pending(hash: &str, received_at: u64) -> PendingBlock {
+        let mut compact = CompactBlock::from_block(&block_with_txs());
+        compact.hash = hash.to_string();
+        PendingBlock {
+            compact,
+            peer: "peer".to_string(),
+            txs: Vec::new(),
+            requested: Vec::new(),
+            full: false,
+            received_at,
+        }
+    }
+
+    #[test]
+    fn compact_block_reconstructs_with_the_same_transactions() {
+        let block = block_with_txs();
+        let compact = CompactBlock::from_block(&block);
+        assert_eq!(compact.short_ids.len(), 3);
+        let rebuilt = compact.to_block(block.transactions.clone());
+        assert_eq!(rebuilt.calculate_hash(), block.hash);
+        assert!(rebuilt.verify_signature());
+
+        // 顺序或内容不同的交易还原出的区块哈希不符
+        let mut reordered = block.transactions.clone();
+        reordered.swap(0, 1);
+        assert_ne!(compact.to_block(reordered).calculate_hash(), block.hash);
+        assert_ne!(
+            compact
+                .to_block(block.transactions[..2].to_vec())
+                .calculate_hash(),
+            block.hash
+        );
+    }
+
+    #[test]
+    fn short_ids_are_bound_to_the_block() {
+        let tx = Transaction::signed(&keys::dev_key("admin"), "b", 1, 1, 0).hash();
+        let id = short_id("block-a", &tx);
+        assert_eq!(id.len(), SHORT_ID_BYTES * 2);
+        assert_eq!(id, short_id("block-a", &tx));
+        assert_ne!(id, short_id("block-b", &tx));
+    }
+
+    #[test]
+    fn pending_blocks_expire_and_stay_bounded() {
+        let mut relay = CompactRelay::default();
+        relay.insert(pending("stale", 0));
+        relay.insert(pending("fresh", 1));
+        let expired = relay.take_expired(PENDING_TIMEOUT_SECS);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].compact.hash, "stale");
+        assert!(relay.pending.contains_key("fresh"));
+
+        let mut relay = CompactRelay::default();
+        for i in 0..MAX_PENDING {
+            relay.insert(pending(&format!("b{}", i), 100 + i as u64));
+        }
+        assert_eq!(relay.pending.len(), MAX_PENDING);
+        relay.insert(pending("newest", 120));
+        assert_eq!(relay.pending.len(), MAX_PENDING);
+        assert!(!relay.pending.contains_key("b0"));
+        assert!(relay.pending.contains_key("newest"));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::network::{self, NetworkContext};
+use crate::peers::{self, PeerSource};
+use crate::protocol::Message;
+use crate::scoring;
+use rand::seq::SliceRandom;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+/// 每轮向多少个随机会话请求地址
+const PEERS_REQUEST_FANOUT: usize = 3;
+/// 单次 PeersResponse 携带、以及接收处理的地址上限
+pub const MAX_ADDRS_PER_RESPONSE: usize = 32;
+/// 等待探测的地址队列上限
+const MAX_PENDING_PROBES: usize = 256;
+/// 每轮并发探测的地址数量
+const PROBES_PER_ROUND: usize = 8;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// 未在该时间内回复的 PeersRequest 视为失效
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+
+fn now_secs() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+/// 地址交换状态：只接受自己请求过的 PeersResponse，新地址探测成功后才写入 peers.db
+#[derive(Default)]
+pub struct Discovery {
+    /// 已发送 PeersRequest 的会话及发送时间
+    awaiting: HashMap<String, u64>,
+    pending: VecDeque<String>,
+    probing: HashSet<String>,
+}
+
+impl Discovery {
+    fn enqueue(&mut self, addr: String) -> bool {
+        if self.pending.len() >= MAX_PENDING_PROBES
+            || self.probing.contains(&addr)
+            || self.pending.contains(&addr)
+        {
+            return false;
+        }
+        self.pending.push_back(addr);
+        true
+    }
+}
+
+/// 回复随机抽取的、近期成功连通过的地址
+pub fn on_peers_request(ctx: &NetworkContext, peer_id: &str) {
+    let sample = ctx
+        .peers
+        .lock()
+        .unwrap()
+        .sample_reachable(MAX_ADDRS_PER_RESPONSE);
+    ctx.sessions
+        .lock()
+        .unwrap()
+        .send(peer_id, Message::PeersResponse(sample));
+}
+
+/// 把对端回复中未知的有效地址放入探测队列
+pub fn on_peers_response(ctx: &NetworkContext, peer_id: &str, addrs: Vec<String>) {
+    if ctx
+        .discovery
+        .lock()
+        .unwrap()
+        .awaiting
+        .remove(peer_id)
+        .is_none()
+    {
+        return;
+    }
+    let fresh: Vec<String> = {
+        let peers = ctx.peers.lock().unwrap();
+        addrs
+            .into_iter()
+            .take(MAX_ADDRS_PER_RESPONSE)
+            .filter(|addr| peers::is_valid_peer_addr(addr))
+            .filter(|addr| !peers.is_local(addr) && peers.get(addr).is_none())
+            .collect()
+    };
+    if fresh.is_empty() {
+        return;
+    }
+    let bans = scoring::active_bans(ctx);
+    let mut discovery = ctx.discovery.lock().unwrap();
+    let queued = fresh
+        .into_iter()
+        .filter(|addr| !bans.iter().any(|b| b.matches_addr(addr)))
+        .filter(|addr| discovery.enqueue(addr.clone()))
+        .count();
+    if queued > 0 {
+        println!(
+            "[发现节点] 节点 {} 提供了 {} 个新地址，等待探测",
+            peer_id, queued
+        );
+    }
+}
+
+/// 定期向随机会话请求地址，并探测队列中的新地址
+pub fn spawn_discovery(ctx: NetworkContext) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(DISCOVERY_INTERVAL).await;
+            request_addresses(&ctx);
+            let batch: Vec<String> = {
+                let mut discovery = ctx.discovery.lock().unwrap();
+                let n = discovery.pending.len().min(PROBES_PER_ROUND);
+                let batch: Vec<String> = discovery.pending.drain(..n).collect();
+                discovery.probing.extend(batch.iter().cloned());
+                batch
+            };
+            for addr in batch {
+                tokio::spawn(probe(ctx.clone(), addr));
+            }
+        }
+    });
+}
+
+fn request_addresses(ctx: &NetworkContext) {
+    let now = now_secs();
+    let mut targets: Vec<String> = ctx
+        .sessions
+        .lock()
+        .unwrap()
+        .list()
+        .into_iter()
+        .map(|p| p.node_id)
+        .collect();
+    targets.shuffle(&mut rand::thread_rng());
+    targets.truncate(PEERS_REQUEST_FANOUT);
+    {
+        let mut discovery = ctx.discovery.lock().unwrap();
+        discovery
+            .awaiting
+            .retain(|_, sent| now < *sent + REQUEST_TIMEOUT_SECS);
+        for id in &targets {
+            discovery.awaiting.insert(id.clone(), now);
+        }
+    }
+    let sessions = ctx.sessions.lock().unwrap();
+    for id in targets {
+        sessions.send(&id, Message::PeersRequest);
+    }
+}
+
+/// 连接并完成握手即视为可达，随后立即断开；只有可达的地址才会写入 peers.db
+async fn probe(ctx: NetworkContext, addr: String) {
+    let hello = ctx.handshake();
+    let result = tokio::time::timeout(
+        PROBE_TIMEOUT,
+        network::connect(&addr, &ctx.local.key, &hello),
+    )
+    .await;
+    ctx.discovery.lock().unwrap().probing.remove(&addr);
+    let (_, mut writer, remote) = match result {
+        Ok(Ok(connected)) => connected,
+        _ => return,
+    };
+    if scoring::is_banned(&ctx, &remote.node_id, &addr) {
+        return;
+    }
+    let _ = writer
+        .write_message(&Message::Disconnect("probe".to_string()))
+        .await;
+    let (record, total) = {
+        let mut peers = ctx.peers.lock().unwrap();
+        if !peers.add_peer(addr.clone(), PeerSource::Exchange) {
+            return;
+        }
+        peers.record_success(&addr, &remote.node_id, now_secs());
+        (peers.get(&addr).cloned(), peers.list().len())
+    };
+    // 释放 peers 锁后再写库
+    if let Some(record) = record {
+        let _ = peers::save_records(&ctx.peer_db.lock().unwrap(), &[record]);
+    }
+    println!(
+        "[发现节点] 新增可达节点 {} ({})，当前已知节点总数: {}",
+        addr, remote.node_id, total
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_queue_skips_duplicates_and_stays_bounded() {
+        let mut discovery = Discovery::default();
+        assert!(discovery.enqueue("10.0.0.1:8000".to_string()));
+        assert!(!discovery.enqueue("10.0.0.1:8000".to_string()));
+        discovery.probing.insert("10.0.0.2:8000".to_string());
+        assert!(!discovery.enqueue("10.0.0.2:8000".to_string()));
+        for i in 1..MAX_PENDING_PROBES {
+            assert!(discovery.enqueue(format!("10.1.{}.{}:8000", i / 256, i % 256)));
+        }
+        assert!(!discovery.enqueue("10.0.0.3:8000".to_string()));
+        assert_eq!(discovery.pending.len(), MAX_PENDING_PROBES);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::accounts::account::{AccountState, ExecError};
+use crate::blockchain::Blockchain;
+use crate::mempool::Mempool;
+use crate::transaction::Transaction;
+use serde::Serialize;
+
+pub const STATE_VIEWS: &[&str] = &["latest", "pending"];
+pub const SIGNATURE_CHECKS: &[&str] = &["valid", "invalid", "not_checked"];
+/// 回执中可能出现的错误码：签名错误加上执行错误
+pub const ERROR_CODES: &[&str] = &[
+    "invalid_signature",
+    "unknown_sender",
+    "bad_nonce",
+    "amount_overflow",
+    "insufficient_balance",
+];
+
+/// 模拟执行所基于的状态：链顶状态，或在其上依次执行 mempool 中下一个区块会打包的交易
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateView {
+    Latest,
+    Pending,
+}
+
+impl StateView {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "latest" => Some(StateView::Latest),
+            "pending" => Some(StateView::Pending),
+            _ => None,
+        }
+    }
+
+    /// 未指定 nonce 时在该状态下应使用的 nonce
+    pub fn next_nonce(self, chain: &Blockchain, mempool: &Mempool, sender: &str) -> u64 {
+        match self {
+            StateView::Latest => chain.state.nonce_of(sender),
+            StateView::Pending => mempool.next_nonce(&chain.state, sender),
+        }
+    }
+}
+
+/// 待模拟的转账；未指定 nonce 时按所选状态补齐，未指定链 ID 时使用节点的链 ID
+#[derive(Debug, Clone, Default)]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+    pub fee: u64,
+    pub nonce: Option<u64>,
+    pub chain_id: Option<String>,
+    pub public_key: Option<String>,
+    pub signature: Option<String>,
+}
+
+impl Transfer {
+    pub fn into_transaction(
+        self,
+        chain: &Blockchain,
+        mempool: &Mempool,
+        view: StateView,
+    ) -> Transaction {
+        let nonce = self
+            .nonce
+            .unwrap_or_else(|| view.next_nonce(chain, mempool, &self.from));
+        let chain_id = self.chain_id.unwrap_or_else(|| chain.chain_id.clone());
+        let mut tx = Transaction::new(&self.from, &self.to, self.amount, self.fee, nonce)
+            .with_chain_id(&chain_id);
+        tx.public_key = self.public_key;
+        tx.signature = self.signature;
+        tx
+    }
+}
+
+/// 签名检查结果，不带签名的交易不检查
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureCheck {
+    Valid,
+    Invalid,
+    NotChecked,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceChange {
+    pub address: String,
+    pub before: u64,
+    pub after: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecFailure {
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// 模拟执行的结果；手续费归出块的提议者，提议者未知，不计入余额变化
+#[derive(Debug, Clone, Serialize)]
+pub struct Receipt {
+    pub tx_hash: String,
+    pub transaction: Transaction,
+    pub state: StateView,
+    /// 执行所基于的链顶高度，交易最早在下一个高度上链
+    pub tip_height: u64,
+    pub signature: SignatureCheck,
+    pub success: bool,
+    pub error: Option<ExecFailure>,
+    /// 交易执行成功时支付的手续费
+    pub fee: u64,
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+/// 在状态副本上按出块的执行路径运行交易，不修改链状态与 mempool；
+/// 带签名的交易先检查签名，签名无效时不执行
+pub fn simulate(
+    chain: &Blockchain,
+    mempool: &Mempool,
+    view: StateView,
+    tx: Transaction,
+) -> Receipt {
+    let signature = match (&tx.public_key, &tx.signature) {
+        (None, None) => SignatureCheck::NotChecked,
+        _ if tx.verify_signature() => SignatureCheck::Valid,
+        _ => SignatureCheck::Invalid,
+    };
+    let base = match view {
+        StateView::Latest => chain.state.clone(),
+        StateView::Pending => pending_state(&chain.state, mempool),
+    };
+    let mut state = base.clone();
+    let error = if signature == SignatureCheck::Invalid {
+        Some(ExecFailure {
+            code: "invalid_signature",
+            message: "signature does not match the sender and transaction".to_string(),
+        })
+    } else {
+        state.execute(&tx).err().map(|e: ExecError| ExecFailure {
+            code: e.code(),
+            message: e.to_string(),
+        })
+    };
+    let success = error.is_none();
+    Receipt {
+        tx_hash: tx.hash(),
+        state: view,
+        tip_height: chain.height(),
+        signature,
+        success,
+        error,
+        fee: if success { tx.fee } else { 0 },
+        balance_changes: if success {
+            balance_changes(&base, &state, &tx)
+        } else {
+            Vec::new()
+        },
+        transaction: tx,
+    }
+}
+
+/// 与出块时相同，依次执行 mempool 选出的交易并跳过无法执行的交易
+fn pending_state(state: &AccountState, mempool: &Mempool) -> AccountState {
+    let mut pending = state.clone();
+    for tx in mempool.pending_transactions(state) {
+        pending.apply_transaction(&tx);
+    }
+    pending
+}
+
+fn balance_changes(
+    before: &AccountState,
+    after: &AccountState,
+    tx: &Transaction,
+) -> Vec<BalanceChange> {
+    let mut addresses = vec![tx.from.as_str()];
+    if tx.to != tx.from {
+        addresses.push(tx.to.as_str());
+    }
+    addresses
+        .into_iter()
+        .map(|address| BalanceChange {
+            address: address.to_string(),
+            before: before.balance_of(address),
+            after: after.balance_of(address),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+
+    fn admin() -> String {
+        keys::dev_address("admin")
+    }
+
+    fn pool_with_pending_spend(chain: &Blockchain) -> Mempool {
+        let mut mempool = Mempool::default();
+        let tx = Transaction::signed(&keys::dev_key("admin"), "b", 999_000, 500, 0);
+        mempool.add(tx, &chain.state, None).unwrap();
+        mempool
+    }
+
+    #[test]
+    fn pending_view_runs_after_the_mempool() {
+        let chain = Blockchain::genesis();
+        let mempool = pool_with_pending_spend(&chain);
+        let tx = |nonce| Transaction::new(&admin(), "c", 1000, 1, nonce);
+
+        let latest = simulate(&chain, &mempool, StateView::Latest, tx(0));
+        assert!(latest.success);
+        assert_eq!(latest.fee, 1);
+        assert_eq!(latest.balance_changes[0].before, 1_000_000);
+        assert_eq!(latest.balance_changes[0].after, 1_000_000 - 1001);
+        assert_eq!(latest.balance_changes[1].after, 1000);
+
+        let pending = simulate(&chain, &mempool, StateView::Pending, tx(1));
+        assert!(!pending.success);
+        assert_eq!(pending.error.unwrap().code, "insufficient_balance");
+        assert_eq!(pending.fee, 0);
+        assert!(pending.balance_changes.is_empty());
+        assert_eq!(StateView::Pending.next_nonce(&chain, &mempool, &admin()), 1);
+    }
+
+    #[test]
+    fn simulation_leaves_chain_and_mempool_untouched() {
+        let chain = Blockchain::genesis();
+        let mempool = pool_with_pending_spend(&chain);
+        let root = chain.state.state_root();
+        let receipt = simulate(
+            &chain,
+            &mempool,
+            StateView::Latest,
+            Transaction::new(&admin(), "c", 10, 0, 0),
+        );
+        assert!(receipt.success);
+        assert_eq!(chain.state.state_root(), root);
+        assert_eq!(mempool.transactions().count(), 1);
+
+        let bad_nonce = simulate(
+            &chain,
+            &mempool,
+            StateView::Latest,
+            Transaction::new(&admin(), "c", 10, 0, 5),
+        );
+        assert_eq!(bad_nonce.error.unwrap().code, "bad_nonce");
+    }
+
+    #[test]
+    fn signatures_are_checked_only_when_present() {
+        let chain = Blockchain::genesis();
+        let mempool = Mempool::default();
+        let run = |tx| simulate(&chain, &mempool, StateView::Latest, tx);
+        let unsigned = run(Transaction::new(&admin(), "c", 10, 0, 0));
+        assert!(unsigned.success);
+        assert_eq!(unsigned.signature, SignatureCheck::NotChecked);
+
+        let signed = Transaction::signed(&keys::dev_key("admin"), "c", 10, 0, 0);
+        let valid = run(signed.clone());
+        assert!(valid.success);
+        assert_eq!(valid.signature, SignatureCheck::Valid);
+
+        // 改动金额或链 ID 后签名不再匹配，交易不执行
+        let mut tampered = signed.clone();
+        tampered.amount = 11;
+        for tx in [tampered, signed.with_chain_id("other-chain")] {
+            let receipt = run(tx);
+            assert!(!receipt.success);
+            assert_eq!(receipt.signature, SignatureCheck::Invalid);
+            assert_eq!(receipt.error.unwrap().code, "invalid_signature");
+            assert!(receipt.balance_changes.is_empty());
+        }
+    }
+
+    #[test]
+    fn error_codes_cover_every_execution_error() {
+        let errors = [
+            ExecError::UnknownSender,
+            ExecError::BadNonce {
+                expected: 0,
+                got: 1,
+            },
+            ExecError::AmountOverflow,
+            ExecError::InsufficientBalance {
+                balance: 0,
+                required: 1,
+            },
+        ];
+        let codes: Vec<&str> = errors.iter().map(ExecError::code).collect();
+        assert_eq!(&ERROR_CODES[1..], codes);
+    }
+
+    #[test]
+    fn self_transfer_reports_one_balance_change() {
+        let chain = Blockchain::genesis();
+        let receipt = simulate(
+            &chain,
+            &Mempool::default(),
+            StateView::Latest,
+            Transaction::new(&admin(), &admin(), 0, 3, 0),
+        );
+        assert!(receipt.success);
+        assert_eq!(receipt.balance_changes.len(), 1);
+        assert_eq!(receipt.balance_changes[0].after, 1_000_000 - 3);
+    }
+}
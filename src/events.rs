@@ -0,0 +1,146 @@
+use crate::block::block::Block;
+use crate::blockchain::Blockchain;
+use crate::transaction::Transaction;
+use tokio::sync::broadcast;
+
+/// 事件通道容量，订阅方落后超过该数量时丢失最早的事件
+const EVENT_CAPACITY: usize = 1024;
+/// 区块之上累积该数量的确认后发布最终确定事件。分叉选择不限制重组深度，
+/// 更长的分叉仍可能替换已发布为最终确定的区块，这只是给应用的安全余量，不是共识层面的最终性
+pub const FINALITY_DEPTH: u64 = 6;
+
+/// 节点内部发生的链事件，供 WebSocket 订阅等消费方使用
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// 新区块追加到链上，包括本地出块与导入的外部区块
+    NewBlock(Block),
+    /// 区块因链重组被移出主链，按从链顶向下的顺序发布
+    Removed(Block),
+    /// 区块达到最终确认深度
+    Finalized(Block),
+    /// 交易进入 mempool
+    PendingTransaction(Transaction),
+}
+
+/// 基于 broadcast 通道的事件总线，没有订阅者时发布的事件直接丢弃
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChainEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus {
+            sender: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: ChainEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// 链重组后先发布被移出的旧链区块，再发布新链上 `base` 以上的区块，
+    /// 以及新链上达到确认深度、此前未按新链发布过的区块
+    pub fn reorganized(&self, chain: &Blockchain, orphaned: &[Block], base: u64) {
+        let old_height = base + orphaned.len() as u64;
+        for block in orphaned.iter().rev() {
+            self.publish(ChainEvent::Removed(block.clone()));
+        }
+        for block in chain.chain.iter().skip(base as usize + 1) {
+            self.publish(ChainEvent::NewBlock(block.clone()));
+        }
+        let first = base.min(old_height.saturating_sub(FINALITY_DEPTH)) + 1;
+        let last = chain.height().saturating_sub(FINALITY_DEPTH);
+        for height in first..=last {
+            if let Some(block) = chain.chain.get(height as usize) {
+                self.publish(ChainEvent::Finalized(block.clone()));
+            }
+        }
+    }
+
+    /// 链顶刚追加了一个区块，发布新区块事件以及因此达到确认深度的区块
+    pub fn block_added(&self, chain: &Blockchain) {
+        let Some(tip) = chain.chain.last() else {
+            return;
+        };
+        self.publish(ChainEvent::NewBlock(tip.clone()));
+        if let Some(height) = tip.index.checked_sub(FINALITY_DEPTH) {
+            if let Some(block) = chain.chain.get(height as usize) {
+                self.publish(ChainEvent::Finalized(block.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(rx: &mut broadcast::Receiver<ChainEvent>) -> Vec<(&'static str, u64)> {
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(match event {
+                ChainEvent::NewBlock(b) => ("new", b.index),
+                ChainEvent::Removed(b) => ("removed", b.index),
+                ChainEvent::Finalized(b) => ("final", b.index),
+                ChainEvent::PendingTransaction(_) => ("pending", 0),
+            });
+        }
+        events
+    }
+
+    #[test]
+    fn blocks_finalize_once_buried_deep_enough() {
+        let bus = EventBus::default();
+        let mut rx = bus.subscribe();
+        let mut chain = Blockchain::genesis();
+        let mut finalized = Vec::new();
+        for now in 1..=FINALITY_DEPTH + 2 {
+            chain.add_dev_block(vec![], now);
+            bus.block_added(&chain);
+            let events = drain(&mut rx);
+            assert_eq!(events[0], ("new", now));
+            finalized.extend(events[1..].iter().map(|&(kind, h)| {
+                assert_eq!(kind, "final");
+                h
+            }));
+        }
+        assert_eq!(finalized, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn reorg_republishes_the_new_branch_and_its_finalized_blocks() {
+        let bus = EventBus::default();
+        let mut chain = Blockchain::genesis();
+        for now in 1..=FINALITY_DEPTH + 4 {
+            chain.add_dev_block(vec![], now);
+        }
+        let orphaned: Vec<Block> = chain.chain[6..9].to_vec();
+        let mut rx = bus.subscribe();
+        // 旧链高度为 8，新链在高度 5 分叉后长到 10：只有高度 2 及以下的区块已按旧链发布为最终确定
+        bus.reorganized(&chain, &orphaned, 5);
+        let events = drain(&mut rx);
+        assert_eq!(
+            &events[..3],
+            &[("removed", 8), ("removed", 7), ("removed", 6)]
+        );
+        let new: Vec<u64> = events
+            .iter()
+            .filter(|e| e.0 == "new")
+            .map(|e| e.1)
+            .collect();
+        let finalized: Vec<u64> = events
+            .iter()
+            .filter(|e| e.0 == "final")
+            .map(|e| e.1)
+            .collect();
+        assert_eq!(new, (6..=10).collect::<Vec<_>>());
+        assert_eq!(finalized, vec![3, 4]);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::network::NetworkContext;
+use crate::protocol::{InvItem, InvKind, Message};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::{BTreeMap, HashMap};
+
+/// 本节点记住的已见交易与区块哈希数量
+pub const SEEN_CACHE_SIZE: usize = 8192;
+/// 每个对端记住的已知哈希数量，用于避免重复公告
+const PEER_KNOWN_SIZE: usize = 2048;
+/// 每笔新交易最多公告给的对端数
+pub const TX_FANOUT: usize = 8;
+/// 每个新区块最多公告给的对端数
+pub const BLOCK_FANOUT: usize = 16;
+/// 单条 Inventory 或 GetData 消息处理的条目上限
+pub const MAX_INV_ITEMS: usize = 1000;
+/// 向某个对端请求后等待的时间，超时后可以向其他公告者重新请求
+const REQUEST_TIMEOUT_SECS: u64 = 5;
+
+/// 固定容量的 LRU 集合，超出容量时淘汰最久未访问的条目
+pub struct LruSet {
+    capacity: usize,
+    counter: u64,
+    entries: HashMap<String, u64>,
+    order: BTreeMap<u64, String>,
+}
+
+impl LruSet {
+    pub fn new(capacity: usize) -> Self {
+        LruSet {
+            capacity,
+            counter: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// 插入或刷新条目，返回插入前是否不存在
+    pub fn insert(&mut self, key: &str) -> bool {
+        self.counter += 1;
+        let fresh = match self.entries.insert(key.to_string(), self.counter) {
+            Some(old) => {
+                self.order.remove(&old);
+                false
+            }
+            None => true,
+        };
+        self.order.insert(self.counter, key.to_string());
+        while self.entries.len() > self.capacity {
+            match self.order.pop_first() {
+                Some((_, oldest)) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+        fresh
+    }
+}
+
+/// 按哈希公告、按哈希拉取的 gossip 状态
+pub struct Gossip {
+    seen: LruSet,
+    /// 每个对端已经拥有或已被告知的哈希
+    known: HashMap<String, LruSet>,
+    /// 已发出 GetData 但尚未收到的哈希，记录请求对象与时间
+    requested: HashMap<String, (String, u64)>,
+    /// 选择公告目标用的随机数，模拟器中使用固定种子
+    rng: StdRng,
+}
+
+impl Gossip {
+    pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: StdRng) -> Self {
+        Gossip {
+            seen: LruSet::new(SEEN_CACHE_SIZE),
+            known: HashMap::new(),
+            requested: HashMap::new(),
+            rng,
+        }
+    }
+
+    /// 记录对端拥有该哈希，不影响本节点的已见集合
+    pub fn mark_known(&mut self, peer: &str, hash: &str) {
+        self.known
+            .entry(peer.to_string())
+            .or_insert_with(|| LruSet::new(PEER_KNOWN_SIZE))
+            .insert(hash);
+    }
+
+    /// 记录收到或本地产生的交易、区块，返回是否第一次见到
+    pub fn mark_seen(&mut self, peer: Option<&str>, hash: &str) -> bool {
+        self.requested.remove(hash);
+        if let Some(peer) = peer {
+            self.mark_known(peer, hash);
+        }
+        self.seen.insert(hash)
+    }
+
+    /// 已知拥有该哈希的对端，按 id 排序
+    pub fn peers_knowing(&self, hash: &str) -> Vec<String> {
+        let mut peers: Vec<String> = self
+            .known
+            .iter()
+            .filter(|(_, known)| known.contains(hash))
+            .map(|(id, _)| id.clone())
+            .collect();
+        peers.sort();
+        peers
+    }
+
+    /// 处理对端公告，返回尚未见过且没有在途请求的条目
+    pub fn on_inventory(&mut self, peer: &str, items: Vec<InvItem>, now: u64) -> Vec<InvItem> {
+        self.requested
+            .retain(|_, (_, at)| now < *at + REQUEST_TIMEOUT_SECS);
+        let mut wanted = Vec::new();
+        for item in items.into_iter().take(MAX_INV_ITEMS) {
+            self.mark_known(peer, &item.hash);
+            if self.seen.contains(&item.hash) || self.requested.contains_key(&item.hash) {
+                continue;
+            }
+            self.requested
+                .insert(item.hash.clone(), (peer.to_string(), now));
+            wanted.push(item);
+        }
+        wanted
+    }
+
+    /// 从当前对端中随机选择不超过扇出上限的公告目标，跳过已知该哈希的对端
+    pub fn select_targets(
+        &mut self,
+        item: &InvItem,
+        peers: &[String],
+        except: Option<&str>,
+        fanout: usize,
+    ) -> Vec<String> {
+        self.known.retain(|id, _| peers.contains(id));
+        let mut candidates: Vec<&String> = peers
+            .iter()
+            .filter(|id| Some(id.as_str()) != except)
+            .filter(|id| {
+                self.known
+                    .get(*id)
+                    .map(|k| !k.contains(&item.hash))
+                    .unwrap_or(true)
+            })
+            .collect();
+        // 先排序再打乱，结果只取决于随机数种子
+        candidates.sort();
+        candidates.shuffle(&mut self.rng);
+        let targets: Vec<String> = candidates.into_iter().take(fanout).cloned().collect();
+        for id in &targets {
+            self.mark_known(id, &item.hash);
+        }
+        targets
+    }
+}
+
+/// 向部分对端公告新验证的交易或区块，`except` 为内容的来源节点
+pub fn announce(ctx: &NetworkContext, item: InvItem, except: Option<&str>) -> usize {
+    let fanout = match item.kind {
+        InvKind::Tx => TX_FANOUT,
+        InvKind::Block => BLOCK_FANOUT,
+    };
+    let peers: Vec<String> = ctx
+        .sessions
+        .lock()
+        .unwrap()
+        .list()
+        .into_iter()
+        .map(|p| p.node_id)
+        .collect();
+    let targets = {
+        let mut gossip = ctx.gossip.lock().unwrap();
+        gossip.mark_seen(None, &item.hash);
+        gossip.select_targets(&item, &peers, except, fanout)
+    };
+    let sessions = ctx.sessions.lock().unwrap();
+    targets
+        .iter()
+        .filter(|id| sessions.send(id, Message::Inventory(vec![item.clone()])))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(hash: &str) -> InvItem {
+        InvItem {
+            kind: InvKind::Tx,
+            hash: hash.to_string(),
+        }
+    }
+
+    fn peers(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("peer-{}", i)).collect()
+    }
+
+    #[test]
+    fn lru_set_evicts_least_recently_used() {
+        let mut set = LruSet::new(2);
+        assert!(set.insert("a"));
+        assert!(set.insert("b"));
+        assert!(!set.insert("a"));
+        set.insert("c");
+        assert!(set.contains("a") && set.contains("c"));
+        assert!(!set.contains("b"));
+    }
+
+    #[test]
+    fn inventory_requests_each_unseen_hash_once() {
+        let mut gossip = Gossip::with_seed(1);
+        gossip.mark_seen(None, "old");
+        let wanted = gossip.on_inventory("p1", vec![item("old"), item("new")], 0);
+        assert_eq!(wanted.len(), 1);
+        assert_eq!(wanted[0].hash, "new");
+        assert!(gossip.on_inventory("p2", vec![item("new")], 1).is_empty());
+        // 请求超时后可以向另一个公告者重新请求
+        let retry = gossip.on_inventory("p2", vec![item("new")], REQUEST_TIMEOUT_SECS);
+        assert_eq!(retry.len(), 1);
+    }
+
+    #[test]
+    fn targets_skip_source_and_peers_that_know_the_hash() {
+        let mut gossip = Gossip::with_seed(1);
+        let peers = peers(4);
+        gossip.on_inventory("peer-1", vec![item("h")], 0);
+        let targets = gossip.select_targets(&item("h"), &peers, Some("peer-0"), 8);
+        let mut sorted = targets.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec!["peer-2".to_string(), "peer-3".to_string()]);
+        // 已公告过的对端不会再次收到同一哈希
+        assert_eq!(
+            gossip.select_targets(&item("h"), &peers, None, 8),
+            vec!["peer-0".to_string()]
+        );
+    }
+
+    #[test]
+    fn fanout_limits_targets_and_seed_fixes_the_choice() {
+        let peers = peers(20);
+        let pick =
+            |seed| Gossip::with_seed(seed).select_targets(&item("h"), &peers, None, TX_FANOUT);
+        let targets = pick(3);
+        assert_eq!(targets.len(), TX_FANOUT);
+        assert_eq!(targets, pick(3));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::admin::AdminAuth;
+use crate::network::NetworkContext;
+use crate::ratelimit::ConnectionPermit;
+use crate::rest;
+use crate::rpc::{self, Access};
+use crate::ws;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use serde_json::json;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+/// 读取请求头的超时，也是 keep-alive 连接的空闲超时
+const HEADER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// 读取请求体的超时
+const BODY_READ_TIMEOUT: Duration = Duration::from_secs(10);
+const CORS_MAX_AGE_SECS: u32 = 600;
+
+/// RPC 服务的 HTTP 层配置
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// 请求体大小上限，超过时回复 413
+    pub max_body_bytes: usize,
+    /// 允许跨域访问的来源，`*` 表示任意来源；为空时不返回 CORS 头
+    pub cors_origins: Vec<String>,
+}
+
+impl HttpConfig {
+    /// 请求来源被允许时返回应写入 `Access-Control-Allow-Origin` 的值
+    fn allowed_origin(&self, origin: &str) -> Option<HeaderValue> {
+        if self.cors_origins.iter().any(|o| o == "*") {
+            return Some(HeaderValue::from_static("*"));
+        }
+        if self.cors_origins.iter().any(|o| o == origin) {
+            return HeaderValue::from_str(origin).ok();
+        }
+        None
+    }
+}
+
+/// 每个连接共享的处理状态
+pub struct HttpState {
+    pub node: Option<NetworkContext>,
+    pub config: HttpConfig,
+    /// 为 None 时该端口不接受管理方法
+    pub admin: Option<Arc<AdminAuth>>,
+}
+
+impl HttpState {
+    /// 携带正确的 `Authorization: Bearer <token>` 时获得管理权限；
+    /// 端口接受管理方法而 Bearer 凭据错误时返回 None。其他认证方式（例如反向代理附加的
+    /// Basic 认证）与管理凭据无关，按匿名请求处理，调用管理方法时由 `rpc::call` 拒绝
+    fn access(&self, headers: &HeaderMap) -> Option<Access> {
+        let Some(auth) = &self.admin else {
+            return Some(Access::Public);
+        };
+        let Some(token) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        else {
+            return Some(Access::Public);
+        };
+        auth.verify(token.trim()).then_some(Access::Admin)
+    }
+}
+
+pub type HttpResponse = Response<Full<Bytes>>;
+
+/// 在一个连接上按 HTTP/1.1 处理请求，支持 keep-alive 与分块传输编码；
+/// 连接名额在升级出的 WebSocket 连接结束后才释放
+pub async fn serve_connection(socket: TcpStream, state: Arc<HttpState>, permit: ConnectionPermit) {
+    let permit = Arc::new(permit);
+    let service = service_fn(move |req| {
+        let state = Arc::clone(&state);
+        let permit = Arc::clone(&permit);
+        async move { Ok::<_, Infallible>(handle(req, &state, permit).await) }
+    });
+    let _ = http1::Builder::new()
+        .timer(TokioTimer::new())
+        .header_read_timeout(HEADER_READ_TIMEOUT)
+        .keep_alive(true)
+        .serve_connection(TokioIo::new(socket), service)
+        .with_upgrades()
+        .await;
+}
+
+async fn handle(
+    req: Request<Incoming>,
+    state: &HttpState,
+    permit: Arc<ConnectionPermit>,
+) -> HttpResponse {
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|o| o.to_str().ok())
+        .and_then(|o| state.config.allowed_origin(o));
+    let access = state.access(req.headers());
+    let mut resp = match access {
+        _ if req.method() == Method::OPTIONS => preflight(origin.is_some()),
+        None => unauthorized(),
+        Some(access) if req.uri().path() == "/ws" => {
+            upgrade_websocket(req, state, origin.is_some(), permit, access)
+        }
+        Some(access) => route(req, state, access).await,
+    };
+    if let Some(origin) = origin {
+        let headers = resp.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    }
+    resp
+}
+
+async fn route(req: Request<Incoming>, state: &HttpState, access: Access) -> HttpResponse {
+    match req.uri().path() {
+        "/" => {
+            if req.method() != Method::POST {
+                return method_not_allowed("POST, OPTIONS");
+            }
+            let body = match read_body(req, state.config.max_body_bytes).await {
+                Ok(body) => body,
+                Err(resp) => return resp,
+            };
+            let Ok(body) = std::str::from_utf8(&body) else {
+                return error(StatusCode::BAD_REQUEST, "request body is not valid UTF-8");
+            };
+            match rpc::handle_jsonrpc_body(body, state.node.as_ref(), access) {
+                Some(resp) => json_response(StatusCode::OK, &resp),
+                None => empty(StatusCode::NO_CONTENT),
+            }
+        }
+        _ => rest::route(req, state).await,
+    }
+}
+
+/// 完成 WebSocket 握手并在后台处理升级后的连接；浏览器发起的连接必须来自允许的来源
+fn upgrade_websocket(
+    mut req: Request<Incoming>,
+    state: &HttpState,
+    origin_allowed: bool,
+    permit: Arc<ConnectionPermit>,
+    access: Access,
+) -> HttpResponse {
+    if req.method() != Method::GET {
+        return method_not_allowed("GET");
+    }
+    if req.headers().contains_key(header::ORIGIN) && !origin_allowed {
+        return error(StatusCode::FORBIDDEN, "origin not allowed");
+    }
+    let headers = req.headers();
+    let is_upgrade = headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    let version_ok = headers
+        .get(header::SEC_WEBSOCKET_VERSION)
+        .is_some_and(|v| v == "13");
+    let Some(key) = headers
+        .get(header::SEC_WEBSOCKET_KEY)
+        .filter(|_| is_upgrade && version_ok)
+    else {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "expected a WebSocket upgrade request",
+        );
+    };
+    let accept = derive_accept_key(key.as_bytes());
+    let node = state.node.clone();
+    let max_message_bytes = state.config.max_body_bytes;
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => ws::serve(upgraded, node, access, max_message_bytes, permit).await,
+            Err(e) => println!("⚠️ [WebSocket] 连接升级失败: {}", e),
+        }
+    });
+    let mut resp = empty(StatusCode::SWITCHING_PROTOCOLS);
+    let headers = resp.headers_mut();
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+    headers.insert(
+        header::SEC_WEBSOCKET_ACCEPT,
+        HeaderValue::from_str(&accept).unwrap(),
+    );
+    resp
+}
+
+/// 按上限读取完整请求体，`Content-Length` 超限时不读取直接拒绝
+pub async fn read_body(req: Request<Incoming>, limit: usize) -> Result<Bytes, HttpResponse> {
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|len| len > limit as u64) {
+        return Err(too_large(limit));
+    }
+    let collect = Limited::new(req.into_body(), limit).collect();
+    match tokio::time::timeout(BODY_READ_TIMEOUT, collect).await {
+        Ok(Ok(body)) => Ok(body.to_bytes()),
+        Ok(Err(e)) if e.is::<http_body_util::LengthLimitError>() => Err(too_large(limit)),
+        Ok(Err(_)) => Err(error(
+            StatusCode::BAD_REQUEST,
+            "failed to read request body",
+        )),
+        Err(_) => Err(error(StatusCode::REQUEST_TIMEOUT, "request body timed out")),
+    }
+}
+
+fn preflight(allowed: bool) -> HttpResponse {
+    let mut resp = empty(StatusCode::NO_CONTENT);
+    if allowed {
+        let headers = resp.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, OPTIONS"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("Content-Type, Authorization"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from(CORS_MAX_AGE_SECS),
+        );
+    }
+    resp
+}
+
+pub fn json_response(status: StatusCode, body: &serde_json::Value) -> HttpResponse {
+    let mut resp = Response::new(Full::new(Bytes::from(body.to_string())));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+fn empty(status: StatusCode) -> HttpResponse {
+    let mut resp = Response::new(Full::new(Bytes::new()));
+    *resp.status_mut() = status;
+    resp
+}
+
+pub fn error(status: StatusCode, message: &str) -> HttpResponse {
+    json_response(status, &json!({ "error": message }))
+}
+
+pub fn method_not_allowed(allow: &'static str) -> HttpResponse {
+    let mut resp = error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed");
+    resp.headers_mut()
+        .insert(header::ALLOW, HeaderValue::from_static(allow));
+    resp
+}
+
+fn unauthorized() -> HttpResponse {
+    let mut resp = error(StatusCode::UNAUTHORIZED, "invalid admin token");
+    resp.headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    resp
+}
+
+fn too_large(limit: usize) -> HttpResponse {
+    error(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        &format!("request body exceeds {} bytes", limit),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::AdminConfig;
+
+    fn state() -> HttpState {
+        let config = AdminConfig {
+            token: Some("secret".to_string()),
+            token_file: String::new(),
+            port: None,
+        };
+        HttpState {
+            node: None,
+            config: HttpConfig {
+                max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+                cors_origins: Vec::new(),
+            },
+            admin: Some(Arc::new(AdminAuth::load_or_create(&config).unwrap())),
+        }
+    }
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(authorization).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn bearer_token_grants_admin_access() {
+        let state = state();
+        assert_eq!(
+            state.access(&headers("Bearer secret")),
+            Some(Access::Admin)
+        );
+        assert_eq!(state.access(&headers("Bearer wrong")), None);
+    }
+
+    #[test]
+    fn other_schemes_are_treated_as_anonymous() {
+        let state = state();
+        assert_eq!(
+            state.access(&headers("Basic dXNlcjpwYXNz")),
+            Some(Access::Public)
+        );
+        assert_eq!(state.access(&HeaderMap::new()), Some(Access::Public));
+    }
+}
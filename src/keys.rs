@@ -0,0 +1,100 @@
+use ed25519_dalek::SigningKey;
+use sha2::{Digest, Sha256};
+
+/// 开发链预置账户的名称；它们的密钥由名称确定性派生，任何人都能算出，只适用于本地开发
+pub const DEV_ACCOUNTS: &[&str] = &["admin", "Alice", "Bob", "Charlie"];
+
+/// 账户地址为 ed25519 公钥 SHA-256 的前 20 字节，交易的 `from` 必须与签名公钥的地址一致
+pub fn address_of(public_key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(public_key)[..20])
+}
+
+pub fn dev_key(name: &str) -> SigningKey {
+    let seed: [u8; 32] = Sha256::digest(format!("async-pos-chain dev key:{}", name)).into();
+    SigningKey::from_bytes(&seed)
+}
+
+pub fn dev_address(name: &str) -> String {
+    address_of(dev_key(name).verifying_key().as_bytes())
+}
+
+/// 把开发账户名解析为地址，其他输入视为地址原样返回
+pub fn resolve(name_or_address: &str) -> String {
+    if DEV_ACCOUNTS.contains(&name_or_address) {
+        dev_address(name_or_address)
+    } else {
+        name_or_address.to_string()
+    }
+}
+
+/// 解析验证者私钥：十六进制编码的 32 字节 ed25519 私钥，或开发账户名
+pub fn parse_signing_key(input: &str) -> Option<SigningKey> {
+    let input = input.trim();
+    if DEV_ACCOUNTS.contains(&input) {
+        return Some(dev_key(input));
+    }
+    let bytes: [u8; 32] = hex::decode(input).ok()?.try_into().ok()?;
+    Some(SigningKey::from_bytes(&bytes))
+}
+
+/// 从命令行参数或密钥文件载入验证者私钥，两者都未指定时返回 None
+pub fn load_validator_key(
+    key: Option<&str>,
+    key_file: Option<&str>,
+) -> Result<Option<SigningKey>, String> {
+    let input = match (key, key_file) {
+        (Some(key), _) => key.to_string(),
+        (None, Some(path)) => {
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?
+        }
+        (None, None) => return Ok(None),
+    };
+    parse_signing_key(&input).map(Some).ok_or_else(|| {
+        "validator key must be 32 hex-encoded bytes or a dev account name".to_string()
+    })
+}
+
+/// 按账户名或地址查找开发账户的签名密钥
+pub fn dev_signer(name_or_address: &str) -> Option<SigningKey> {
+    DEV_ACCOUNTS
+        .iter()
+        .find(|name| **name == name_or_address || dev_address(name) == name_or_address)
+        .map(|name| dev_key(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dev_accounts_resolve_to_stable_distinct_addresses() {
+        let alice = dev_address("Alice");
+        assert_eq!(alice.len(), 40);
+        assert_eq!(resolve("Alice"), alice);
+        assert_ne!(alice, dev_address("Bob"));
+        assert_eq!(resolve("ab12"), "ab12");
+    }
+
+    #[test]
+    fn signer_lookup_accepts_name_or_address() {
+        let by_name = dev_signer("Bob").unwrap();
+        let by_address = dev_signer(&dev_address("Bob")).unwrap();
+        assert_eq!(by_name.to_bytes(), by_address.to_bytes());
+        assert!(dev_signer("Mallory").is_none());
+    }
+
+    #[test]
+    fn validator_key_parses_from_hex_or_dev_name() {
+        let alice = dev_key("Alice");
+        let hex_key = hex::encode(alice.to_bytes());
+        let parsed = parse_signing_key(&format!("{}\n", hex_key)).unwrap();
+        assert_eq!(parsed.to_bytes(), alice.to_bytes());
+        assert_eq!(
+            parse_signing_key("Alice").unwrap().to_bytes(),
+            alice.to_bytes()
+        );
+        assert!(parse_signing_key("not-a-key").is_none());
+        assert!(load_validator_key(None, None).unwrap().is_none());
+        assert!(load_validator_key(None, Some("/nonexistent/validator.key")).is_err());
+    }
+}
@@ -0,0 +1,573 @@
+use crate::accounts::account;
+use crate::block::block::BlockHeader;
+use crate::blockchain::{self, Blockchain, Validator};
+use crate::clock::Clock;
+use crate::keys;
+use crate::merkle::{self, Hash};
+use crate::network::{self, TcpReader, TcpWriter};
+use crate::noise::NodeKey;
+use crate::protocol::{self, BlockRange, Handshake, Message, ProtocolError};
+use crate::sync::MAX_HEADERS_PER_REQUEST;
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 交易包含证明：交易在区块交易列表中的位置，以及到区块头 `tx_root` 的 Merkle 路径
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxProof {
+    pub header: BlockHeader,
+    pub tx: Transaction,
+    pub index: usize,
+    pub branch: Vec<String>,
+}
+
+/// 账户证明：`height` 处区块头的状态根下，账户的余额与 nonce
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountProof {
+    pub height: u64,
+    pub block_hash: String,
+    pub address: String,
+    pub balance: u64,
+    pub nonce: u64,
+    pub index: usize,
+    pub branch: Vec<String>,
+}
+
+/// 为链上的交易生成包含证明
+pub fn tx_proof(chain: &Blockchain, tx_hash: &str) -> Option<TxProof> {
+    chain.chain.iter().rev().find_map(|block| {
+        let index = block
+            .transactions
+            .iter()
+            .position(|tx| tx.hash() == tx_hash)?;
+        let leaves: Vec<Hash> = block
+            .transactions
+            .iter()
+            .map(|tx| tx.hash_bytes())
+            .collect();
+        let branch = merkle::merkle_proof(&leaves, index)?;
+        Some(TxProof {
+            header: block.header(),
+            tx: block.transactions[index].clone(),
+            index,
+            branch: branch.iter().map(hex::encode).collect(),
+        })
+    })
+}
+
+/// 针对链顶状态根生成账户证明；链顶区块没有状态根或账户不存在时返回 None
+pub fn account_proof(chain: &Blockchain, address: &str) -> Option<AccountProof> {
+    let tip = chain.chain.last()?;
+    if tip.state_root.is_empty() {
+        return None;
+    }
+    let (index, branch) = chain.state.proof(address)?;
+    Some(AccountProof {
+        height: tip.index,
+        block_hash: tip.hash.clone(),
+        address: address.to_string(),
+        balance: chain.state.balance_of(address),
+        nonce: chain.state.nonce_of(address),
+        index,
+        branch: branch.iter().map(hex::encode).collect(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LightError {
+    /// 区块头与已验证的链不连续或内容无效
+    BadHeader {
+        height: u64,
+        reason: String,
+    },
+    /// 证明引用的区块头尚未同步或与本地不一致
+    UnknownHeader(u64),
+    MissingStateRoot(u64),
+    BadProof,
+    /// 证明有效，但针对的不是请求的交易或账户
+    WrongSubject(String),
+}
+
+impl fmt::Display for LightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightError::BadHeader { height, reason } => {
+                write!(f, "invalid header {}: {}", height, reason)
+            }
+            LightError::UnknownHeader(h) => write!(f, "header {} is not verified locally", h),
+            LightError::MissingStateRoot(h) => write!(f, "header {} has no state root", h),
+            LightError::BadProof => write!(f, "merkle proof does not match header"),
+            LightError::WrongSubject(got) => write!(f, "proof is for {} instead", got),
+        }
+    }
+}
+
+fn decode_branch(branch: &[String]) -> Option<Vec<Hash>> {
+    branch
+        .iter()
+        .map(|h| hex::decode(h).ok()?.try_into().ok())
+        .collect()
+}
+
+fn decode_root(root: &str) -> Option<Hash> {
+    hex::decode(root).ok()?.try_into().ok()
+}
+
+/// 只保存区块头的轻客户端状态，验证规则与全节点导入区块时对区块头的检查一致：
+/// 哈希连续、提议者属于验证者集合且区块头带有提议者的有效签名
+pub struct LightClient {
+    headers: Vec<BlockHeader>,
+    /// 由区块头的提议者推导出的验证者集合
+    validators: HashMap<String, Validator>,
+    /// 检查区块头时间戳是否超前使用的时间来源
+    clock: Clock,
+}
+
+impl LightClient {
+    pub fn new() -> Self {
+        Self::with_clock(Clock::System)
+    }
+
+    pub fn with_clock(clock: Clock) -> Self {
+        let mut chain = Blockchain::new();
+        chain.create_genesis_block();
+        LightClient {
+            headers: vec![chain.chain[0].header()],
+            validators: blockchain::genesis_validators(),
+            clock,
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.headers.len() as u64 - 1
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        self.headers.last().unwrap()
+    }
+
+    pub fn genesis_hash(&self) -> String {
+        self.headers[0].hash.clone()
+    }
+
+    pub fn validators(&self) -> &HashMap<String, Validator> {
+        &self.validators
+    }
+
+    /// 依次验证并追加区块头，遇到无效区块头时停止并返回错误，之前的区块头保留
+    pub fn apply_headers(&mut self, headers: Vec<BlockHeader>) -> Result<usize, LightError> {
+        let mut applied = 0;
+        for header in headers {
+            self.verify_next(&header)?;
+            if let Some(validator) = self.validators.get_mut(&header.proposer) {
+                validator.stake += blockchain::PROPOSER_STAKE_INCREMENT;
+            }
+            self.headers.push(header);
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    fn verify_next(&self, header: &BlockHeader) -> Result<(), LightError> {
+        let bad = |reason: &str| LightError::BadHeader {
+            height: header.index,
+            reason: reason.to_string(),
+        };
+        let tip = self.tip();
+        if header.index != tip.index + 1 {
+            return Err(bad("unexpected height"));
+        }
+        if header.previous_hash != tip.hash {
+            return Err(bad("previous hash does not match"));
+        }
+        if header.hash != header.calculate_hash() {
+            return Err(bad("hash does not match contents"));
+        }
+        let Some(validator) = self.validators.get(&header.proposer) else {
+            return Err(bad("unknown proposer"));
+        };
+        if header.proposer_key != validator.public_key || !header.verify_signature() {
+            return Err(bad("missing or invalid proposer signature"));
+        }
+        if header.proposer != blockchain::select_proposer(&self.validators, &tip.hash) {
+            return Err(bad("proposer is not the selected one for this height"));
+        }
+        let now = self.clock.now_secs();
+        if header.timestamp < tip.timestamp
+            || header.timestamp > now + blockchain::MAX_FUTURE_DRIFT_SECS
+        {
+            return Err(bad("timestamp out of range"));
+        }
+        Ok(())
+    }
+
+    fn verified_header(&self, height: u64, hash: &str) -> Result<&BlockHeader, LightError> {
+        self.headers
+            .get(height as usize)
+            .filter(|h| h.hash == hash)
+            .ok_or(LightError::UnknownHeader(height))
+    }
+
+    /// `tx_hash` 为请求的交易哈希，证明中的交易必须正是它
+    pub fn verify_tx_proof(&self, proof: &TxProof, tx_hash: &str) -> Result<(), LightError> {
+        let got = proof.tx.hash();
+        if got != tx_hash {
+            return Err(LightError::WrongSubject(got));
+        }
+        let header = self.verified_header(proof.header.index, &proof.header.hash)?;
+        let branch = decode_branch(&proof.branch).ok_or(LightError::BadProof)?;
+        let root = decode_root(&header.tx_root).ok_or(LightError::BadProof)?;
+        if !proof.tx.verify_signature()
+            || !merkle::verify_proof(&proof.tx.hash_bytes(), proof.index, &branch, &root)
+        {
+            return Err(LightError::BadProof);
+        }
+        Ok(())
+    }
+
+    /// `address` 为请求的账户，证明中的地址必须正是它
+    pub fn verify_account_proof(
+        &self,
+        proof: &AccountProof,
+        address: &str,
+    ) -> Result<(), LightError> {
+        if proof.address != address {
+            return Err(LightError::WrongSubject(proof.address.clone()));
+        }
+        let header = self.verified_header(proof.height, &proof.block_hash)?;
+        if header.state_root.is_empty() {
+            return Err(LightError::MissingStateRoot(proof.height));
+        }
+        let branch = decode_branch(&proof.branch).ok_or(LightError::BadProof)?;
+        let root = decode_root(&header.state_root).ok_or(LightError::BadProof)?;
+        let leaf = account::account_leaf(&proof.address, proof.balance, proof.nonce);
+        if !merkle::verify_proof(&leaf, proof.index, &branch, &root) {
+            return Err(LightError::BadProof);
+        }
+        Ok(())
+    }
+}
+
+/// 等待对端的下一条业务消息，期间回复 Ping 并忽略公告
+async fn next_reply(
+    reader: &mut TcpReader,
+    writer: &mut TcpWriter,
+) -> Result<Message, ProtocolError> {
+    loop {
+        let msg = tokio::time::timeout(RESPONSE_TIMEOUT, reader.read_message())
+            .await
+            .map_err(|_| ProtocolError::Handshake("response timed out".to_string()))??;
+        match msg {
+            Some(Message::Ping(nonce)) => writer.write_message(&Message::Pong(nonce)).await?,
+            Some(Message::Inventory(_)) | Some(Message::Pong(_)) => {}
+            Some(Message::Disconnect(reason)) => return Err(ProtocolError::Handshake(reason)),
+            Some(msg) => return Ok(msg),
+            None => return Err(ProtocolError::Handshake("connection closed".to_string())),
+        }
+    }
+}
+
+/// 从对端下载区块头直到 `target` 或对端没有更多区块头
+async fn sync_headers(
+    client: &mut LightClient,
+    reader: &mut TcpReader,
+    writer: &mut TcpWriter,
+    target: u64,
+) -> Result<(), String> {
+    while client.height() < target {
+        let range = BlockRange {
+            start: client.height() + 1,
+            count: MAX_HEADERS_PER_REQUEST,
+        };
+        writer
+            .write_message(&Message::GetHeaders(range))
+            .await
+            .map_err(|e| e.to_string())?;
+        let headers = match next_reply(reader, writer)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            Message::Headers(headers) => headers,
+            other => return Err(format!("unexpected reply {:?}", other.kind())),
+        };
+        if headers.is_empty() {
+            break;
+        }
+        client.apply_headers(headers).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// 轻客户端模式：连接一个全节点，只同步区块头，按需通过 Merkle 证明查询余额与交易
+pub async fn run_light_client(
+    peer: String,
+    chain_id: String,
+    balance: Option<String>,
+    tx: Option<String>,
+) {
+    let mut client = LightClient::new();
+    let key = NodeKey::generate();
+    let hello = Handshake {
+        protocol_version: protocol::PROTOCOL_VERSION,
+        chain_id,
+        genesis_hash: client.genesis_hash(),
+        node_id: key.node_id(),
+        listen_addr: String::new(),
+        best_height: 0,
+        best_hash: client.genesis_hash(),
+    };
+    let (mut reader, mut writer, remote) = match network::connect(&peer, &key, &hello).await {
+        Ok(connected) => connected,
+        Err(e) => {
+            println!("❌ 无法连接节点 {}: {}", peer, e);
+            return;
+        }
+    };
+    println!("🪶 轻客户端已连接 {} ({})", remote.node_id, peer);
+
+    if let Err(e) = sync_headers(&mut client, &mut reader, &mut writer, remote.best_height).await {
+        println!("⚠️ 区块头同步中断: {}", e);
+    }
+    println!(
+        "✅ 已验证区块头至高度 {} | 链顶: {}",
+        client.height(),
+        client.tip().hash
+    );
+    let stakes: HashMap<&String, u64> = client
+        .validators()
+        .iter()
+        .map(|(address, v)| (address, v.stake))
+        .collect();
+    println!("👥 验证者: {:?}", stakes);
+
+    if let Some(address) = balance {
+        let address = keys::resolve(&address);
+        query_balance(&mut client, &mut reader, &mut writer, &address).await;
+    }
+    if let Some(hash) = tx {
+        query_tx(&mut client, &mut reader, &mut writer, &hash).await;
+    }
+    let _ = writer
+        .write_message(&Message::Disconnect("light client done".to_string()))
+        .await;
+}
+
+async fn query_balance(
+    client: &mut LightClient,
+    reader: &mut TcpReader,
+    writer: &mut TcpWriter,
+    address: &str,
+) {
+    let request = Message::GetAccountProof(address.to_string());
+    let reply = match writer.write_message(&request).await {
+        Ok(()) => next_reply(reader, writer).await,
+        Err(e) => Err(e),
+    };
+    let proof = match reply {
+        Ok(Message::AccountProof(Some(proof))) => proof,
+        Ok(Message::AccountProof(None)) => {
+            println!("未找到账户 {} 的证明", address);
+            return;
+        }
+        Ok(other) => {
+            println!("⚠️ 意外的回复: {:?}", other.kind());
+            return;
+        }
+        Err(e) => {
+            println!("⚠️ 查询余额失败: {}", e);
+            return;
+        }
+    };
+    // 证明可能基于刚产生的区块，先补齐区块头
+    if proof.height > client.height() {
+        if let Err(e) = sync_headers(client, reader, writer, proof.height).await {
+            println!("⚠️ 区块头同步中断: {}", e);
+        }
+    }
+    match client.verify_account_proof(&proof, address) {
+        Ok(()) => println!(
+            "💰 {} 余额: {} nonce: {} (已验证，高度 {})",
+            proof.address, proof.balance, proof.nonce, proof.height
+        ),
+        Err(e) => println!("❌ 余额证明无效: {}", e),
+    }
+}
+
+async fn query_tx(
+    client: &mut LightClient,
+    reader: &mut TcpReader,
+    writer: &mut TcpWriter,
+    hash: &str,
+) {
+    let request = Message::GetTxProof(hash.to_string());
+    let reply = match writer.write_message(&request).await {
+        Ok(()) => next_reply(reader, writer).await,
+        Err(e) => Err(e),
+    };
+    let proof = match reply {
+        Ok(Message::TxProof(Some(proof))) => proof,
+        Ok(Message::TxProof(None)) => {
+            println!("未找到交易 {}", hash);
+            return;
+        }
+        Ok(other) => {
+            println!("⚠️ 意外的回复: {:?}", other.kind());
+            return;
+        }
+        Err(e) => {
+            println!("⚠️ 查询交易失败: {}", e);
+            return;
+        }
+    };
+    if proof.header.index > client.height() {
+        if let Err(e) = sync_headers(client, reader, writer, proof.header.index).await {
+            println!("⚠️ 区块头同步中断: {}", e);
+        }
+    }
+    match client.verify_tx_proof(&proof, hash) {
+        Ok(()) => println!(
+            "🧾 交易 {} 已包含在区块 {} 中 (已验证): {} -> {} [{}]",
+            hash, proof.header.index, proof.tx.from, proof.tx.to, proof.tx.amount
+        ),
+        Err(e) => println!("❌ 交易证明无效: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transaction;
+
+    fn chain_with_tx() -> (Blockchain, Transaction) {
+        let mut chain = Blockchain::genesis();
+        let tx = Transaction::signed(&keys::dev_key("admin"), &keys::dev_address("Bob"), 5, 1, 0);
+        chain.add_dev_block(vec![tx.clone()], 1);
+        (chain, tx)
+    }
+
+    fn synced_client(chain: &Blockchain) -> LightClient {
+        let mut client = LightClient::new();
+        let headers = chain.chain[1..].iter().map(|b| b.header()).collect();
+        client.apply_headers(headers).unwrap();
+        client
+    }
+
+    #[test]
+    fn proofs_verify_only_for_the_requested_subject() {
+        let (chain, tx) = chain_with_tx();
+        let client = synced_client(&chain);
+
+        let proof = tx_proof(&chain, &tx.hash()).unwrap();
+        client.verify_tx_proof(&proof, &tx.hash()).unwrap();
+        assert!(matches!(
+            client.verify_tx_proof(&proof, "other"),
+            Err(LightError::WrongSubject(_))
+        ));
+
+        let bob = keys::dev_address("Bob");
+        let proof = account_proof(&chain, &bob).unwrap();
+        client.verify_account_proof(&proof, &bob).unwrap();
+        assert!(matches!(
+            client.verify_account_proof(&proof, &keys::dev_address("Alice")),
+            Err(LightError::WrongSubject(_))
+        ));
+
+        let mut forged = proof.clone();
+        forged.balance += 1;
+        assert_eq!(
+            client.verify_account_proof(&forged, &bob),
+            Err(LightError::BadProof)
+        );
+    }
+
+    #[test]
+    fn headers_without_a_valid_proposer_signature_are_rejected() {
+        let (chain, _) = chain_with_tx();
+        let mut header = chain.chain[1].header();
+        header.signature.clear();
+        let mut client = LightClient::new();
+        assert!(matches!(
+            client.apply_headers(vec![header]),
+            Err(LightError::BadHeader { height: 1, .. })
+        ));
+        assert_eq!(client.height(), 0);
+    }
+
+    #[test]
+    fn headers_from_out_of_turn_validators_are_rejected() {
+        let (chain, _) = chain_with_tx();
+        let mut block = chain.chain[1].clone();
+        let name = if block.proposer == keys::dev_address("Alice") {
+            "Bob"
+        } else {
+            "Alice"
+        };
+        // 区块头由另一个验证者正确签名，但这个高度不该由它出块
+        block.proposer = keys::dev_address(name);
+        block.hash = block.calculate_hash();
+        block.sign(&keys::dev_key(name));
+        assert!(block.verify_signature());
+        let mut client = LightClient::new();
+        assert!(matches!(
+            client.apply_headers(vec![block.header()]),
+            Err(LightError::BadHeader { height: 1, .. })
+        ));
+        assert_eq!(client.height(), 0);
+    }
+
+    #[test]
+    fn header_timestamps_are_checked_against_the_client_clock() {
+        let mut chain = Blockchain::genesis();
+        chain.add_dev_block(vec![], 1_000);
+        let header = chain.chain[1].header();
+        let clock = Clock::new_virtual();
+        let mut client = LightClient::with_clock(clock.clone());
+        assert!(matches!(
+            client.apply_headers(vec![header.clone()]),
+            Err(LightError::BadHeader { height: 1, .. })
+        ));
+        clock.advance_to(1_000 * 1000);
+        assert_eq!(client.apply_headers(vec![header]), Ok(1));
+    }
+
+    #[test]
+    fn proofs_need_a_locally_verified_header() {
+        let (chain, tx) = chain_with_tx();
+        let client = LightClient::new();
+        let proof = tx_proof(&chain, &tx.hash()).unwrap();
+        assert_eq!(
+            client.verify_tx_proof(&proof, &tx.hash()),
+            Err(LightError::UnknownHeader(1))
+        );
+        let bob = keys::dev_address("Bob");
+        let proof = account_proof(&chain, &bob).unwrap();
+        assert_eq!(
+            client.verify_account_proof(&proof, &bob),
+            Err(LightError::UnknownHeader(1))
+        );
+    }
+
+    #[test]
+    fn header_sync_stops_at_the_first_gap() {
+        let (mut chain, _) = chain_with_tx();
+        chain.add_dev_block(vec![], 2);
+        chain.add_dev_block(vec![], 3);
+        let mut client = LightClient::new();
+        let headers = vec![chain.chain[1].header(), chain.chain[3].header()];
+        assert!(matches!(
+            client.apply_headers(headers),
+            Err(LightError::BadHeader { height: 3, .. })
+        ));
+        assert_eq!(client.height(), 1);
+        assert_eq!(
+            client.apply_headers(vec![chain.chain[2].header(), chain.chain[3].header()]),
+            Ok(2)
+        );
+        assert_eq!(client.tip().hash, chain.get_last_hash());
+        assert_eq!(client.validators(), &chain.validators);
+    }
+}
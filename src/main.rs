@@ -1,18 +1,40 @@
+mod admin;
 mod blockchain;
 mod cli;
+mod clock;
+mod compact;
+mod discovery;
+mod dry_run;
+mod events;
+mod gossip;
+mod http;
+mod keys;
+mod light;
 mod mempool;
+mod merkle;
 mod network;
 mod node;
+mod noise;
+mod openrpc;
 mod peers;
+mod protocol;
+mod ratelimit;
+mod rest;
 mod rpc;
+mod scoring;
+mod session;
+mod simulator;
 mod storage;
+mod sync;
 mod transaction;
+mod ws;
 
 mod accounts {
     pub mod account;
 }
 
 mod block {
+    #[allow(clippy::module_inception)]
     pub mod block;
 }
 
@@ -20,13 +42,158 @@ mod block {
 async fn main() {
     let cli = cli::parse_cli();
     match cli.command {
-        cli::Command::Run { port } => node::run_node(port).await,
-        cli::Command::Submit { from, to, amount } => node::submit_tx(from, to, amount).await,
+        cli::Command::Run {
+            port,
+            chain_id,
+            advertise,
+            validator_key,
+            validator_key_file,
+            max_inbound,
+            max_outbound,
+            max_per_ip,
+            seeds,
+            rpc_max_body,
+            rpc_cors_origins,
+            admin_token,
+            admin_token_file,
+            admin_port,
+        } => {
+            let validator = match keys::load_validator_key(
+                validator_key.as_deref(),
+                validator_key_file.as_deref(),
+            ) {
+                Ok(key) => key,
+                Err(e) => {
+                    println!("❌ 无法载入验证者密钥: {}", e);
+                    return;
+                }
+            };
+            let identity = node::NodeIdentity {
+                chain_id,
+                advertise,
+                validator,
+            };
+            let limits = network::ConnectionLimits {
+                max_inbound,
+                max_outbound,
+                max_per_ip,
+            };
+            let rpc = http::HttpConfig {
+                max_body_bytes: rpc_max_body,
+                cors_origins: rpc_cors_origins,
+            };
+            let admin = admin::AdminConfig {
+                token: admin_token,
+                token_file: admin_token_file,
+                port: admin_port,
+            };
+            node::run_node(port, identity, limits, seeds, rpc, admin).await
+        }
+        cli::Command::Submit {
+            from,
+            to,
+            amount,
+            fee,
+            nonce,
+            chain_id,
+        } => node::submit_tx(from, to, amount, fee, nonce, chain_id).await,
+        cli::Command::Sign {
+            from,
+            to,
+            amount,
+            fee,
+            nonce,
+            chain_id,
+        } => node::sign_tx(from, to, amount, fee, nonce, chain_id),
+        cli::Command::DryRun {
+            from,
+            to,
+            amount,
+            fee,
+            nonce,
+            latest,
+        } => {
+            let view = if latest {
+                dry_run::StateView::Latest
+            } else {
+                dry_run::StateView::Pending
+            };
+            node::dry_run(view, from, to, amount, fee, nonce)
+        }
+        cli::Command::Cancel {
+            from,
+            nonce,
+            fee,
+            chain_id,
+        } => node::submit_tx(from.clone(), from, 0, fee, Some(nonce), chain_id).await,
         cli::Command::Query { index } => node::query_block(index),
         cli::Command::QueryBalance { address } => node::query_balance(address),
+        cli::Command::Address { name } => node::print_addresses(name),
         cli::Command::AddPeer { addr } => node::add_peer(addr),
         cli::Command::QueryPeers => node::query_peers(),
-        cli::Command::JsonRpcServer { port } => rpc::start_jsonrpc_server(port).await,
+        cli::Command::JsonRpcServer {
+            port,
+            rpc_max_body,
+            rpc_cors_origins,
+        } => {
+            let rpc = http::HttpConfig {
+                max_body_bytes: rpc_max_body,
+                cors_origins: rpc_cors_origins,
+            };
+            rpc::start_jsonrpc_server(port, None, rpc, None).await
+        }
         cli::Command::QueryTx { hash } => node::query_tx(hash),
+        cli::Command::OpenRpc { output } => openrpc::dump(output),
+        cli::Command::ListBans => node::list_bans(),
+        cli::Command::Ban {
+            target,
+            duration,
+            permanent,
+        } => node::ban(target, duration, permanent),
+        cli::Command::Unban { target } => node::unban(target),
+        cli::Command::Light {
+            peer,
+            chain_id,
+            balance,
+            tx,
+        } => light::run_light_client(peer, chain_id, balance, tx).await,
+        cli::Command::Simulate {
+            nodes,
+            duration,
+            seed,
+            latency_ms,
+            jitter_ms,
+            loss,
+            block_interval,
+            producers,
+            partition_at,
+            heal_at,
+            txs,
+        } => {
+            if nodes == 0 || producers.iter().any(|&p| p >= nodes) || !(0.0..=1.0).contains(&loss) {
+                println!("❌ 无效的模拟参数");
+                return;
+            }
+            let config = simulator::SimConfig {
+                nodes,
+                seed,
+                latency_ms,
+                jitter_ms,
+                loss,
+                block_interval_secs: block_interval,
+                producers: if producers.is_empty() {
+                    (0..nodes.min(2)).collect()
+                } else {
+                    producers
+                },
+            };
+            let scenario = simulator::Scenario {
+                duration_secs: duration,
+                partition_at,
+                heal_at,
+                txs,
+            };
+            simulator::run_scenario(config, scenario)
+        }
     }
 }
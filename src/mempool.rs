@@ -1,32 +1,959 @@
+use crate::accounts::account::AccountState;
+use crate::clock::Clock;
 use crate::transaction::Transaction;
 use rusqlite::Connection;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
-#[derive(Default)]
+#[derive(Clone)]
+pub struct MempoolConfig {
+    pub max_txs: usize,
+    pub max_per_sender: usize,
+    pub max_tx_size: usize,
+    pub ttl_secs: u64,
+    /// 替换同 nonce 交易时手续费至少提高的百分比
+    pub replace_fee_bump_percent: u64,
+    /// 允许排队等待的最大 nonce 超前量
+    pub max_nonce_gap: u64,
+    /// 排队交易等待前序交易的最长时间
+    pub queued_ttl_secs: u64,
+    /// 只接受签名绑定到该链的交易
+    pub chain_id: String,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            max_txs: 1024,
+            max_per_sender: 16,
+            max_tx_size: 2048,
+            ttl_secs: 600,
+            replace_fee_bump_percent: 10,
+            max_nonce_gap: 64,
+            queued_ttl_secs: 180,
+            chain_id: crate::network::DEFAULT_CHAIN_ID.to_string(),
+        }
+    }
+}
+
+/// 交易被拒绝进入 mempool 的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    TooLarge {
+        size: usize,
+        max: usize,
+    },
+    ZeroAmount,
+    /// 取消交易只能替换池中同 nonce 的待打包交易
+    NothingToCancel {
+        nonce: u64,
+    },
+    WrongChain {
+        expected: String,
+        got: String,
+    },
+    InvalidSignature,
+    Duplicate,
+    UnknownSender,
+    NonceTooLow {
+        expected: u64,
+        got: u64,
+    },
+    NonceGap {
+        expected: u64,
+        got: u64,
+    },
+    ReplacementUnderpriced {
+        min_fee: u64,
+    },
+    InsufficientBalance {
+        balance: u64,
+        required: u64,
+    },
+    SenderLimit {
+        max: usize,
+    },
+    PoolFull,
+}
+
+impl RejectReason {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectReason::TooLarge { .. } => "too_large",
+            RejectReason::ZeroAmount => "zero_amount",
+            RejectReason::NothingToCancel { .. } => "nothing_to_cancel",
+            RejectReason::WrongChain { .. } => "wrong_chain",
+            RejectReason::InvalidSignature => "invalid_signature",
+            RejectReason::Duplicate => "duplicate",
+            RejectReason::UnknownSender => "unknown_sender",
+            RejectReason::NonceTooLow { .. } => "nonce_too_low",
+            RejectReason::NonceGap { .. } => "nonce_gap",
+            RejectReason::ReplacementUnderpriced { .. } => "replacement_underpriced",
+            RejectReason::InsufficientBalance { .. } => "insufficient_balance",
+            RejectReason::SenderLimit { .. } => "sender_limit",
+            RejectReason::PoolFull => "pool_full",
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::TooLarge { size, max } => {
+                write!(f, "transaction size {} exceeds limit {}", size, max)
+            }
+            RejectReason::ZeroAmount => write!(f, "amount must be greater than zero"),
+            RejectReason::NothingToCancel { nonce } => {
+                write!(f, "no pending transaction with nonce {} to cancel", nonce)
+            }
+            RejectReason::WrongChain { expected, got } => {
+                write!(f, "transaction is for chain {}, expected {}", got, expected)
+            }
+            RejectReason::InvalidSignature => {
+                write!(
+                    f,
+                    "missing or invalid signature, or key does not match sender"
+                )
+            }
+            RejectReason::Duplicate => write!(f, "transaction already in mempool"),
+            RejectReason::UnknownSender => write!(f, "unknown sender account"),
+            RejectReason::NonceTooLow { expected, got } => {
+                write!(f, "nonce too low: expected {}, got {}", expected, got)
+            }
+            RejectReason::NonceGap { expected, got } => {
+                write!(f, "nonce gap: expected {}, got {}", expected, got)
+            }
+            RejectReason::ReplacementUnderpriced { min_fee } => {
+                write!(f, "replacement fee too low: need at least {}", min_fee)
+            }
+            RejectReason::InsufficientBalance { balance, required } => {
+                write!(
+                    f,
+                    "insufficient balance: have {}, need {}",
+                    balance, required
+                )
+            }
+            RejectReason::SenderLimit { max } => {
+                write!(f, "sender already has {} pending transactions", max)
+            }
+            RejectReason::PoolFull => write!(f, "mempool is full and fee is too low to evict"),
+        }
+    }
+}
+
+/// 交易成功进入 mempool 的结果，`replaced` 为被替换掉的旧交易哈希，
+/// `queued` 表示 nonce 存在空洞、需等待前序交易到达后才能打包
+pub struct Admission {
+    pub hash: String,
+    pub replaced: Option<String>,
+    pub queued: bool,
+}
+
+#[derive(Clone)]
+struct PoolEntry {
+    tx: Transaction,
+    received_at: u64,
+}
+
+/// 单个发送方的待打包交易，`ready` 从链上 nonce 起连续可执行，`queued` 等待补齐空洞
+#[derive(Clone, Default)]
+struct SenderTxs {
+    ready: BTreeMap<u64, String>,
+    queued: BTreeMap<u64, String>,
+}
+
+impl SenderTxs {
+    fn len(&self) -> usize {
+        self.ready.len() + self.queued.len()
+    }
+
+    fn get(&self, nonce: u64) -> Option<&String> {
+        self.ready.get(&nonce).or_else(|| self.queued.get(&nonce))
+    }
+
+    fn hashes(&self) -> impl Iterator<Item = &String> {
+        self.ready.values().chain(self.queued.values())
+    }
+
+    /// 淘汰时优先选择的交易：有排队交易时取 nonce 最大的排队交易，否则取 ready 的末尾
+    fn tail(&self) -> Option<&String> {
+        self.queued
+            .values()
+            .next_back()
+            .or_else(|| self.ready.values().next_back())
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct Mempool {
-    pub pool: Vec<Transaction>,
+    pub config: MempoolConfig,
+    entries: HashMap<String, PoolEntry>,
+    senders: HashMap<String, SenderTxs>,
+    /// 接收时间与 TTL 过期的时间来源，模拟器中为虚拟时钟
+    clock: Clock,
 }
 
 impl Mempool {
-    pub fn add(&mut self, tx: Transaction, conn: Option<&Connection>) {
-        self.pool.push(tx.clone());
+    pub fn with_clock(clock: Clock) -> Self {
+        Mempool {
+            clock,
+            ..Mempool::default()
+        }
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Transaction> {
+        self.entries.get(hash).map(|e| &e.tx)
+    }
+
+    /// 池中全部交易，顺序不定
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.entries.values().map(|e| &e.tx)
+    }
+
+    /// 发送方下一笔可直接执行的交易应使用的 nonce
+    pub fn next_nonce(&self, state: &AccountState, sender: &str) -> u64 {
+        let mut next = state.nonce_of(sender);
+        // 从链上 nonce 起沿连续的 ready 交易前进，重组后链上 nonce 回退时不会越过空洞
+        if let Some(txs) = self.senders.get(sender) {
+            while txs.ready.contains_key(&next) {
+                next += 1;
+            }
+        }
+        next
+    }
+
+    pub fn add(
+        &mut self,
+        tx: Transaction,
+        state: &AccountState,
+        conn: Option<&Connection>,
+    ) -> Result<Admission, RejectReason> {
+        self.admit(tx, state, conn, false)
+    }
+
+    /// 接收对端转发或重组放回的交易。原交易可能从未到达本节点，
+    /// 此时取消交易与普通交易一样占用该 nonce，与发出它的节点保持一致
+    pub fn add_relayed(
+        &mut self,
+        tx: Transaction,
+        state: &AccountState,
+        conn: Option<&Connection>,
+    ) -> Result<Admission, RejectReason> {
+        self.admit(tx, state, conn, true)
+    }
+
+    fn admit(
+        &mut self,
+        tx: Transaction,
+        state: &AccountState,
+        conn: Option<&Connection>,
+        lone_cancellation: bool,
+    ) -> Result<Admission, RejectReason> {
+        let received_at = self.clock.now_secs();
+        let admission = self.insert(tx.clone(), received_at, state, conn, lone_cancellation)?;
         if let Some(conn) = conn {
-            let _ = crate::storage::insert_mempool_tx(conn, &tx);
+            let _ = crate::storage::insert_mempool_tx(conn, &tx, received_at);
+        }
+        Ok(admission)
+    }
+
+    /// `lone_cancellation` 为 true 时取消交易不要求池中有原交易：
+    /// 交易来自 mempool 表（原交易已被替换掉）或来自对端
+    fn insert(
+        &mut self,
+        tx: Transaction,
+        received_at: u64,
+        state: &AccountState,
+        conn: Option<&Connection>,
+        lone_cancellation: bool,
+    ) -> Result<Admission, RejectReason> {
+        let hash = tx.hash();
+        let replaced = self.validate(&tx, &hash, state, lone_cancellation)?;
+        if let Some(old) = &replaced {
+            self.entries.remove(old);
+            if let Some(conn) = conn {
+                let _ = crate::storage::remove_mempool_tx(conn, old);
+            }
+            println!("🔁 交易 {} 被更高手续费的交易 {} 替换", old, hash);
+        } else if self.entries.len() >= self.config.max_txs && !self.evict_lowest_fee(&tx, conn) {
+            return Err(RejectReason::PoolFull);
+        }
+        let ready_slot = self.next_nonce(state, &tx.from);
+        let sender = self.senders.entry(tx.from.clone()).or_default();
+        let queued = if sender.ready.contains_key(&tx.nonce) || tx.nonce == ready_slot {
+            sender.ready.insert(tx.nonce, hash.clone());
+            false
+        } else {
+            sender.queued.insert(tx.nonce, hash.clone());
+            true
+        };
+        let from = tx.from.clone();
+        self.entries
+            .insert(hash.clone(), PoolEntry { tx, received_at });
+        if !queued {
+            self.promote(&from, state);
+        }
+        Ok(Admission {
+            hash,
+            replaced,
+            queued,
+        })
+    }
+
+    /// 校验通过时返回将被替换的同 nonce 交易哈希
+    fn validate(
+        &self,
+        tx: &Transaction,
+        hash: &str,
+        state: &AccountState,
+        lone_cancellation: bool,
+    ) -> Result<Option<String>, RejectReason> {
+        let size = tx.encoded_size();
+        if size > self.config.max_tx_size {
+            return Err(RejectReason::TooLarge {
+                size,
+                max: self.config.max_tx_size,
+            });
+        }
+        let replacing = self
+            .senders
+            .get(&tx.from)
+            .and_then(|s| s.get(tx.nonce))
+            .and_then(|h| self.entries.get(h).map(|e| (h.clone(), e)));
+        if tx.amount == 0 && !tx.is_cancellation() {
+            return Err(RejectReason::ZeroAmount);
+        }
+        // 本地提交的取消交易没有可替换的原交易时只会白白消耗一个 nonce
+        if tx.is_cancellation() && replacing.is_none() && !lone_cancellation {
+            return Err(RejectReason::NothingToCancel { nonce: tx.nonce });
+        }
+        if tx.chain_id != self.config.chain_id {
+            return Err(RejectReason::WrongChain {
+                expected: self.config.chain_id.clone(),
+                got: tx.chain_id.clone(),
+            });
+        }
+        if !tx.verify_signature() {
+            return Err(RejectReason::InvalidSignature);
+        }
+        if self.entries.contains_key(hash) {
+            return Err(RejectReason::Duplicate);
+        }
+        if !state.exists(&tx.from) {
+            return Err(RejectReason::UnknownSender);
+        }
+        let mut pending = self.pending_spend(&tx.from);
+        if tx.nonce < state.nonce_of(&tx.from) {
+            return Err(RejectReason::NonceTooLow {
+                expected: state.nonce_of(&tx.from),
+                got: tx.nonce,
+            });
+        }
+        if let Some((_, old)) = &replacing {
+            let bump = (old
+                .tx
+                .fee
+                .saturating_mul(self.config.replace_fee_bump_percent)
+                / 100)
+                .max(1);
+            let min_fee = old.tx.fee.saturating_add(bump);
+            if tx.fee < min_fee {
+                return Err(RejectReason::ReplacementUnderpriced { min_fee });
+            }
+            pending = pending.saturating_sub(old.tx.amount.saturating_add(old.tx.fee));
+        } else {
+            let expected = self.next_nonce(state, &tx.from);
+            if tx.nonce < expected {
+                return Err(RejectReason::NonceTooLow {
+                    expected,
+                    got: tx.nonce,
+                });
+            }
+            if tx.nonce - expected > self.config.max_nonce_gap {
+                return Err(RejectReason::NonceGap {
+                    expected,
+                    got: tx.nonce,
+                });
+            }
+        }
+        let required = pending.saturating_add(tx.amount).saturating_add(tx.fee);
+        let balance = state.balance_of(&tx.from);
+        if balance < required {
+            return Err(RejectReason::InsufficientBalance { balance, required });
+        }
+        let sender_count = self.senders.get(&tx.from).map_or(0, |s| s.len());
+        if replacing.is_none() && sender_count >= self.config.max_per_sender {
+            return Err(RejectReason::SenderLimit {
+                max: self.config.max_per_sender,
+            });
+        }
+        Ok(replacing.map(|(h, _)| h))
+    }
+
+    fn pending_spend(&self, sender: &str) -> u64 {
+        self.senders
+            .get(sender)
+            .map(|s| {
+                s.hashes()
+                    .filter_map(|h| self.entries.get(h))
+                    .map(|e| e.tx.amount.saturating_add(e.tx.fee))
+                    .fold(0u64, |acc, v| acc.saturating_add(v))
+            })
+            .unwrap_or(0)
+    }
+
+    /// 把 nonce 已经连续的排队交易移入 ready
+    fn promote(&mut self, sender: &str, state: &AccountState) {
+        let mut next = self.next_nonce(state, sender);
+        let Some(txs) = self.senders.get_mut(sender) else {
+            return;
+        };
+        while let Some(hash) = txs.queued.remove(&next) {
+            txs.ready.insert(next, hash);
+            next += 1;
+        }
+    }
+
+    /// 池满时淘汰手续费最低的交易，只淘汰各发送方 nonce 最大的那笔以免产生空洞
+    fn evict_lowest_fee(&mut self, incoming: &Transaction, conn: Option<&Connection>) -> bool {
+        let victim = self
+            .senders
+            .iter()
+            .filter(|(sender, _)| **sender != incoming.from)
+            .filter_map(|(_, txs)| txs.tail())
+            .filter_map(|h| self.entries.get(h).map(|e| (h.clone(), e.tx.fee)))
+            .filter(|(_, fee)| *fee < incoming.fee)
+            .min_by_key(|(_, fee)| *fee);
+        match victim {
+            Some((hash, _)) => {
+                println!("🗑️ mempool 已满，淘汰低手续费交易 {}", hash);
+                self.remove(&hash, conn);
+                true
+            }
+            None => false,
         }
     }
 
-    pub fn collect_for_block(&mut self, max: usize, conn: Option<&Connection>) -> Vec<Transaction> {
-        let txs: Vec<_> = self.pool.drain(..max.min(self.pool.len())).collect();
+    /// 丢弃一笔交易；若它位于 ready 中间，其后的交易出现空洞，退回 queued
+    fn remove(&mut self, hash: &str, conn: Option<&Connection>) -> Option<Transaction> {
+        self.detach(hash, true, conn)
+    }
+
+    /// 取出一笔已被打包或已被链上 nonce 消耗的交易，其后的交易保持可执行
+    fn take(&mut self, hash: &str, conn: Option<&Connection>) -> Option<Transaction> {
+        self.detach(hash, false, conn)
+    }
+
+    fn detach(
+        &mut self,
+        hash: &str,
+        demote_followers: bool,
+        conn: Option<&Connection>,
+    ) -> Option<Transaction> {
+        let entry = self.entries.remove(hash)?;
+        if let Some(txs) = self.senders.get_mut(&entry.tx.from) {
+            if txs.ready.remove(&entry.tx.nonce).is_some() {
+                if demote_followers {
+                    let gapped = txs.ready.split_off(&entry.tx.nonce);
+                    txs.queued.extend(gapped);
+                }
+            } else {
+                txs.queued.remove(&entry.tx.nonce);
+            }
+            if txs.len() == 0 {
+                self.senders.remove(&entry.tx.from);
+            }
+        }
         if let Some(conn) = conn {
-            for tx in &txs {
-                let _ = crate::storage::remove_mempool_tx(conn, tx);
+            let _ = crate::storage::remove_mempool_tx(conn, hash);
+        }
+        Some(entry.tx)
+    }
+
+    /// 清理超过 TTL 的交易，排队交易使用更短的 `queued_ttl_secs`
+    pub fn expire(&mut self, now: u64, conn: Option<&Connection>) -> usize {
+        let mut stale: Vec<String> = Vec::new();
+        for txs in self.senders.values() {
+            for (hashes, ttl) in [
+                (&txs.ready, self.config.ttl_secs),
+                (&txs.queued, self.config.queued_ttl_secs),
+            ] {
+                stale.extend(
+                    hashes
+                        .values()
+                        .filter(|h| {
+                            self.entries
+                                .get(*h)
+                                .is_some_and(|e| now.saturating_sub(e.received_at) > ttl)
+                        })
+                        .cloned(),
+                );
+            }
+        }
+        stale
+            .iter()
+            .filter(|hash| self.remove(hash, conn).is_some())
+            .count()
+    }
+
+    /// 移除 nonce 已被链上状态消耗的交易，再从链上 nonce 起重建各发送方的队列：
+    /// 连续的交易可执行，空洞之后的交易降回排队。链顶变化或重组后调用
+    pub fn prune_stale(&mut self, state: &AccountState, conn: Option<&Connection>) {
+        let stale: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.tx.nonce < state.nonce_of(&e.tx.from))
+            .map(|(h, _)| h.clone())
+            .collect();
+        for hash in stale {
+            self.take(&hash, conn);
+        }
+        let senders: Vec<String> = self.senders.keys().cloned().collect();
+        for sender in senders {
+            self.rebuild(&sender, state);
+        }
+    }
+
+    fn rebuild(&mut self, sender: &str, state: &AccountState) {
+        let mut next = state.nonce_of(sender);
+        let Some(txs) = self.senders.get_mut(sender) else {
+            return;
+        };
+        let mut all = std::mem::take(&mut txs.ready);
+        all.append(&mut txs.queued);
+        for (nonce, hash) in all {
+            if nonce == next {
+                txs.ready.insert(nonce, hash);
+                next += 1;
+            } else {
+                txs.queued.insert(nonce, hash);
             }
         }
-        txs
     }
 
-    pub fn load_from_db(&mut self, conn: &Connection) {
-        if let Ok(txs) = crate::storage::load_all_mempool_txs(conn) {
-            self.pool = txs;
+    /// 按手续费从高到低挑选可执行交易，同一发送方按 nonce 顺序
+    pub fn collect_for_block(
+        &mut self,
+        max: usize,
+        state: &AccountState,
+        conn: Option<&Connection>,
+    ) -> Vec<Transaction> {
+        let expired = self.expire(self.clock.now_secs(), conn);
+        if expired > 0 {
+            println!("⌛ 清理过期交易 {} 笔", expired);
         }
+        self.prune_stale(state, conn);
+
+        let mut cursors: HashMap<String, u64> = self
+            .senders
+            .keys()
+            .map(|s| (s.clone(), state.nonce_of(s)))
+            .collect();
+        let mut selected = Vec::new();
+        while selected.len() < max {
+            let best = cursors
+                .iter()
+                .filter_map(|(sender, nonce)| {
+                    self.senders
+                        .get(sender)
+                        .and_then(|s| s.ready.get(nonce))
+                        .and_then(|h| {
+                            self.entries
+                                .get(h)
+                                .map(|e| (sender.clone(), h.clone(), e.tx.fee))
+                        })
+                })
+                // 手续费相同时取哈希较小的交易，打包顺序不受 HashMap 遍历顺序影响
+                .max_by(|a, b| a.2.cmp(&b.2).then_with(|| b.1.cmp(&a.1)));
+            match best {
+                Some((sender, hash, _)) => {
+                    *cursors.get_mut(&sender).unwrap() += 1;
+                    selected.push(hash);
+                }
+                None => break,
+            }
+        }
+        selected
+            .iter()
+            .filter_map(|hash| self.take(hash, conn))
+            .collect()
+    }
+
+    /// 下一个区块会按顺序打包的可执行交易；在池的副本上走出块的挑选路径，不修改本池
+    pub fn pending_transactions(&self, state: &AccountState) -> Vec<Transaction> {
+        self.clone().collect_for_block(usize::MAX, state, None)
+    }
+
+    pub fn load_from_db(&mut self, conn: &Connection, state: &AccountState) {
+        if let Ok(mut txs) = crate::storage::load_all_mempool_txs(conn) {
+            txs.sort_by_key(|(tx, _)| tx.nonce);
+            for (tx, received_at) in txs {
+                let hash = tx.hash();
+                if let Err(reason) = self.insert(tx, received_at, state, Some(conn), true) {
+                    if reason != RejectReason::Duplicate {
+                        let _ = crate::storage::remove_mempool_tx(conn, &hash);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 按 nonce 顺序重新校验并放回交易，不读写 mempool 表；只读地载入持久化的交易时使用
+    pub fn restore(&mut self, mut txs: Vec<(Transaction, u64)>, state: &AccountState) {
+        txs.sort_by_key(|(tx, _)| tx.nonce);
+        for (tx, received_at) in txs {
+            let _ = self.insert(tx, received_at, state, None, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+
+    fn funded_state() -> AccountState {
+        let mut state = AccountState::new();
+        state.credit(&keys::dev_address("Alice"), 100);
+        state
+    }
+
+    fn transfer(amount: u64, fee: u64, nonce: u64) -> Transaction {
+        Transaction::signed(&keys::dev_key("Alice"), "b", amount, fee, nonce)
+    }
+
+    fn ready_nonces(pool: &Mempool) -> Vec<u64> {
+        pool.senders
+            .get(&keys::dev_address("Alice"))
+            .map(|s| s.ready.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn admits_signed_transaction_from_known_sender() {
+        let mut pool = Mempool::default();
+        let admission = pool.add(transfer(10, 1, 0), &funded_state(), None).unwrap();
+        assert!(pool.entries.contains_key(&admission.hash));
+    }
+
+    #[test]
+    fn rejects_unsigned_and_forged_transactions() {
+        let mut pool = Mempool::default();
+        let state = funded_state();
+        let alice = keys::dev_address("Alice");
+        let unsigned = Transaction::new(&alice, "b", 10, 1, 0);
+        assert_eq!(
+            pool.add(unsigned, &state, None).err(),
+            Some(RejectReason::InvalidSignature)
+        );
+        let mut forged = Transaction::new(&alice, "b", 10, 1, 0);
+        forged.sign(&keys::dev_key("Bob"));
+        assert_eq!(
+            pool.add(forged, &state, None).err(),
+            Some(RejectReason::InvalidSignature)
+        );
+    }
+
+    fn cancellation(fee: u64, nonce: u64) -> Transaction {
+        let alice = keys::dev_address("Alice");
+        Transaction::signed(&keys::dev_key("Alice"), &alice, 0, fee, nonce)
+    }
+
+    #[test]
+    fn replacement_requires_fee_bump() {
+        let mut pool = Mempool::default();
+        let state = funded_state();
+        let original = pool.add(transfer(10, 10, 0), &state, None).unwrap();
+        assert_eq!(
+            pool.add(transfer(20, 10, 0), &state, None).err(),
+            Some(RejectReason::ReplacementUnderpriced { min_fee: 11 })
+        );
+        let replacement = pool.add(transfer(20, 11, 0), &state, None).unwrap();
+        assert_eq!(replacement.replaced, Some(original.hash.clone()));
+        assert!(!pool.entries.contains_key(&original.hash));
+        assert_eq!(pool.entries.len(), 1);
+    }
+
+    #[test]
+    fn replacement_only_counts_the_new_spend() {
+        let mut pool = Mempool::default();
+        let state = funded_state();
+        pool.add(transfer(80, 1, 0), &state, None).unwrap();
+        // 原交易的 81 不再计入，替换交易花费 90 + 2 仍在余额之内
+        assert!(pool.add(transfer(90, 2, 0), &state, None).is_ok());
+    }
+
+    #[test]
+    fn cancellation_needs_a_pending_transaction_to_replace() {
+        let mut pool = Mempool::default();
+        let state = funded_state();
+        assert_eq!(
+            pool.add(cancellation(5, 0), &state, None).err(),
+            Some(RejectReason::NothingToCancel { nonce: 0 })
+        );
+        let original = pool.add(transfer(10, 4, 0), &state, None).unwrap();
+        let cancel = pool.add(cancellation(5, 0), &state, None).unwrap();
+        assert_eq!(cancel.replaced, Some(original.hash));
+    }
+
+    #[test]
+    fn zero_amount_transfer_to_others_is_rejected() {
+        let mut pool = Mempool::default();
+        assert_eq!(
+            pool.add(transfer(0, 1, 0), &funded_state(), None).err(),
+            Some(RejectReason::ZeroAmount)
+        );
+    }
+
+    #[test]
+    fn rejects_transactions_signed_for_another_chain() {
+        let mut pool = Mempool::default();
+        let state = funded_state();
+        let mut tx = Transaction::new(&keys::dev_address("Alice"), "b", 10, 1, 0)
+            .with_chain_id("other-chain");
+        tx.sign(&keys::dev_key("Alice"));
+        assert!(tx.verify_signature());
+        assert_eq!(
+            pool.add(tx, &state, None).err().map(|r| r.code()),
+            Some("wrong_chain")
+        );
+    }
+
+    #[test]
+    fn admission_checks_nonce_and_cumulative_balance() {
+        let mut pool = Mempool::default();
+        let mut state = funded_state();
+        assert!(state.apply_transaction(&transfer(10, 0, 0)));
+        assert_eq!(
+            pool.add(transfer(10, 1, 0), &state, None).err(),
+            Some(RejectReason::NonceTooLow {
+                expected: 1,
+                got: 0
+            })
+        );
+        pool.add(transfer(60, 1, 1), &state, None).unwrap();
+        // 池中待打包交易的花费计入余额检查
+        assert_eq!(
+            pool.add(transfer(29, 1, 2), &state, None).err(),
+            Some(RejectReason::InsufficientBalance {
+                balance: 90,
+                required: 91
+            })
+        );
+        let mut stranger = Transaction::new("nobody", "b", 1, 0, 0);
+        stranger.sign(&keys::dev_key("Alice"));
+        assert_eq!(
+            pool.add(stranger, &state, None).err(),
+            Some(RejectReason::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn sender_limit_and_full_pool_eviction() {
+        let mut pool = Mempool::default();
+        pool.config.max_txs = 3;
+        pool.config.max_per_sender = 2;
+        let mut state = funded_state();
+        state.credit(&keys::dev_address("Bob"), 100);
+        let bob = |fee, nonce| Transaction::signed(&keys::dev_key("Bob"), "b", 1, fee, nonce);
+
+        let first = pool.add(transfer(1, 5, 0), &state, None).unwrap();
+        let second = pool.add(transfer(1, 5, 1), &state, None).unwrap();
+        assert_eq!(
+            pool.add(transfer(1, 5, 2), &state, None).err(),
+            Some(RejectReason::SenderLimit { max: 2 })
+        );
+        pool.add(bob(1, 0), &state, None).unwrap();
+        assert_eq!(
+            pool.add(bob(3, 1), &state, None).err(),
+            Some(RejectReason::PoolFull)
+        );
+        // 池满时淘汰其他发送方手续费更低的末尾交易，不在其 nonce 序列中留下空洞
+        pool.add(bob(9, 1), &state, None).unwrap();
+        assert!(pool.entries.contains_key(&first.hash));
+        assert!(!pool.entries.contains_key(&second.hash));
+        assert_eq!(pool.entries.len(), 3);
+    }
+
+    #[test]
+    fn pending_cancellations_survive_a_restart() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::storage::init_mempool_table(&conn).unwrap();
+        let state = funded_state();
+        let mut pool = Mempool::default();
+        let original = pool.add(transfer(10, 4, 0), &state, Some(&conn)).unwrap();
+        let cancel = pool.add(cancellation(5, 0), &state, Some(&conn)).unwrap();
+
+        let mut reloaded = Mempool::default();
+        reloaded.load_from_db(&conn, &state);
+        assert!(reloaded.entries.contains_key(&cancel.hash));
+        assert!(!reloaded.entries.contains_key(&original.hash));
+        let mut again = Mempool::default();
+        again.load_from_db(&conn, &state);
+        assert_eq!(again.entries.len(), 1);
+    }
+
+    #[test]
+    fn relayed_replacements_are_persisted() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::storage::init_mempool_table(&conn).unwrap();
+        let state = funded_state();
+        let mut pool = Mempool::default();
+        let original = pool
+            .add_relayed(transfer(10, 4, 0), &state, Some(&conn))
+            .unwrap();
+        let replacement = pool
+            .add_relayed(transfer(10, 5, 0), &state, Some(&conn))
+            .unwrap();
+        assert_eq!(replacement.replaced, Some(original.hash.clone()));
+
+        let mut reloaded = Mempool::default();
+        reloaded.load_from_db(&conn, &state);
+        assert!(reloaded.entries.contains_key(&replacement.hash));
+        assert!(!reloaded.entries.contains_key(&original.hash));
+    }
+
+    #[test]
+    fn relayed_cancellation_without_the_original_takes_its_nonce() {
+        let mut pool = Mempool::default();
+        let state = funded_state();
+        // 对端从未收到原交易，取消交易仍被接受并占用该 nonce，与发出它的节点一致
+        let cancel = pool.add_relayed(cancellation(5, 0), &state, None).unwrap();
+        assert!(!cancel.queued);
+        assert_eq!(ready_nonces(&pool), vec![0]);
+        // 之后到达的原交易手续费更低，不能替换取消交易
+        assert_eq!(
+            pool.add_relayed(transfer(10, 4, 0), &state, None).err(),
+            Some(RejectReason::ReplacementUnderpriced { min_fee: 6 })
+        );
+        assert!(pool.entries.contains_key(&cancel.hash));
+    }
+
+    #[test]
+    fn queued_transactions_promote_as_the_gap_fills() {
+        let mut pool = Mempool::default();
+        let state = funded_state();
+        assert!(pool.add(transfer(1, 1, 2), &state, None).unwrap().queued);
+        assert!(pool.add(transfer(1, 1, 3), &state, None).unwrap().queued);
+        assert!(ready_nonces(&pool).is_empty());
+        assert_eq!(pool.next_nonce(&state, &keys::dev_address("Alice")), 0);
+
+        assert!(!pool.add(transfer(1, 1, 0), &state, None).unwrap().queued);
+        assert_eq!(ready_nonces(&pool), vec![0]);
+        pool.add(transfer(1, 1, 1), &state, None).unwrap();
+        assert_eq!(ready_nonces(&pool), vec![0, 1, 2, 3]);
+        assert_eq!(pool.next_nonce(&state, &keys::dev_address("Alice")), 4);
+    }
+
+    #[test]
+    fn nonce_gap_is_bounded() {
+        let mut pool = Mempool::default();
+        pool.config.max_nonce_gap = 2;
+        let state = funded_state();
+        assert!(pool.add(transfer(1, 1, 2), &state, None).is_ok());
+        assert_eq!(
+            pool.add(transfer(1, 1, 3), &state, None).err(),
+            Some(RejectReason::NonceGap {
+                expected: 0,
+                got: 3
+            })
+        );
+    }
+
+    #[test]
+    fn queued_transactions_expire_sooner() {
+        let mut pool = Mempool::with_clock(Clock::new_virtual());
+        let state = funded_state();
+        let ready = pool.add(transfer(1, 1, 0), &state, None).unwrap();
+        let queued = pool.add(transfer(1, 1, 5), &state, None).unwrap();
+        let now = pool.config.queued_ttl_secs + 1;
+        assert_eq!(pool.expire(now, None), 1);
+        assert!(pool.entries.contains_key(&ready.hash));
+        assert!(!pool.entries.contains_key(&queued.hash));
+    }
+
+    #[test]
+    fn dropping_a_ready_transaction_requeues_its_followers() {
+        let mut pool = Mempool::default();
+        let state = funded_state();
+        let first = pool.add(transfer(1, 1, 0), &state, None).unwrap();
+        pool.add(transfer(1, 1, 1), &state, None).unwrap();
+        pool.remove(&first.hash, None);
+        assert!(ready_nonces(&pool).is_empty());
+        // 补上缺失的 nonce 后，退回排队的交易重新变为可执行
+        pool.add(transfer(1, 2, 0), &state, None).unwrap();
+        assert_eq!(ready_nonces(&pool), vec![0, 1]);
+    }
+
+    #[test]
+    fn block_inclusion_promotes_queued_transactions() {
+        let mut pool = Mempool::default();
+        let mut state = funded_state();
+        pool.add(transfer(1, 1, 1), &state, None).unwrap();
+        let included = transfer(1, 1, 0);
+        assert!(state.apply_transaction(&included));
+        let block = pool.collect_for_block(10, &state, None);
+        assert_eq!(block.len(), 1);
+        assert_eq!(block[0].nonce, 1);
+        assert!(pool.entries.is_empty());
+    }
+
+    #[test]
+    fn reorg_demotes_transactions_past_the_reverted_nonce() {
+        let mut pool = Mempool::default();
+        let before = funded_state();
+        let mut after = before.clone();
+        assert!(after.apply_transaction(&transfer(1, 1, 0)));
+        pool.add(transfer(1, 1, 1), &after, None).unwrap();
+        assert_eq!(ready_nonces(&pool), vec![1]);
+        // 包含 nonce 0 的区块被重组掉，链上 nonce 回退到 0
+        pool.prune_stale(&before, None);
+        assert!(ready_nonces(&pool).is_empty());
+        assert_eq!(pool.next_nonce(&before, &keys::dev_address("Alice")), 0);
+        // 重新放回被孤立的交易后，后续交易恢复可执行
+        pool.add(transfer(1, 1, 0), &before, None).unwrap();
+        pool.prune_stale(&before, None);
+        assert_eq!(ready_nonces(&pool), vec![0, 1]);
+    }
+
+    #[test]
+    fn pending_transactions_match_block_order_without_removing() {
+        let mut pool = Mempool::default();
+        let mut state = funded_state();
+        state.credit(&keys::dev_address("Bob"), 100);
+        let bob = |fee, nonce| Transaction::signed(&keys::dev_key("Bob"), "b", 1, fee, nonce);
+        pool.add(transfer(1, 1, 0), &state, None).unwrap();
+        pool.add(transfer(1, 5, 1), &state, None).unwrap();
+        pool.add(bob(3, 0), &state, None).unwrap();
+        pool.add(transfer(1, 9, 3), &state, None).unwrap();
+
+        let pending = pool.pending_transactions(&state);
+        assert_eq!(pool.transactions().count(), 4);
+        // 同一发送方按 nonce 顺序，排队中的交易不计入
+        let order: Vec<(u64, u64)> = pending.iter().map(|tx| (tx.fee, tx.nonce)).collect();
+        assert_eq!(order, vec![(3, 0), (1, 0), (5, 1)]);
+        let block: Vec<String> = pool
+            .collect_for_block(10, &state, None)
+            .iter()
+            .map(Transaction::hash)
+            .collect();
+        let pending: Vec<String> = pending.iter().map(Transaction::hash).collect();
+        assert_eq!(block, pending);
+    }
+
+    #[test]
+    fn restore_revalidates_in_nonce_order_without_a_database() {
+        let mut state = funded_state();
+        assert!(state.apply_transaction(&transfer(1, 1, 0)));
+        let mut pool = Mempool::default();
+        let txs = vec![
+            (transfer(1, 1, 2), 0),
+            (transfer(1, 1, 1), 0),
+            (transfer(2, 1, 0), 0),
+        ];
+        pool.restore(txs, &state);
+        // nonce 已被链上消耗的交易不再放回，其余交易按 nonce 顺序重新成为可执行
+        assert_eq!(pool.transactions().count(), 2);
+        assert_eq!(ready_nonces(&pool), vec![1, 2]);
     }
 }
@@ -0,0 +1,111 @@
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// 计算叶子哈希的 Merkle 根，奇数个节点时复制最后一个；没有叶子时为全零
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level[0]
+}
+
+/// 第 `index` 个叶子到根的路径上各层的兄弟节点，自底向上排列
+pub fn merkle_proof(leaves: &[Hash], mut index: usize) -> Option<Vec<Hash>> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut branch = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let sibling = if index.is_multiple_of(2) {
+            level.get(index + 1).unwrap_or(&level[index])
+        } else {
+            &level[index - 1]
+        };
+        branch.push(*sibling);
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        index /= 2;
+    }
+    Some(branch)
+}
+
+/// 用 `merkle_proof` 给出的路径重新计算根并与 `root` 比较
+pub fn verify_proof(leaf: &Hash, mut index: usize, branch: &[Hash], root: &Hash) -> bool {
+    let mut current = *leaf;
+    for sibling in branch {
+        current = if index.is_multiple_of(2) {
+            hash_pair(&current, sibling)
+        } else {
+            hash_pair(sibling, &current)
+        };
+        index /= 2;
+    }
+    index == 0 && current == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<Hash> {
+        (0..n).map(|i| Sha256::digest([i as u8]).into()).collect()
+    }
+
+    #[test]
+    fn proofs_verify_for_every_leaf() {
+        for n in 1..=9 {
+            let leaves = leaves(n);
+            let root = merkle_root(&leaves);
+            for (i, leaf) in leaves.iter().enumerate() {
+                let branch = merkle_proof(&leaves, i).unwrap();
+                assert!(verify_proof(leaf, i, &branch, &root), "n={} i={}", n, i);
+            }
+            assert!(merkle_proof(&leaves, n).is_none());
+        }
+    }
+
+    #[test]
+    fn proofs_fail_for_wrong_leaf_position_or_root() {
+        let leaves = leaves(5);
+        let root = merkle_root(&leaves);
+        let branch = merkle_proof(&leaves, 1).unwrap();
+        assert!(!verify_proof(&leaves[2], 1, &branch, &root));
+        assert!(!verify_proof(&leaves[1], 0, &branch, &root));
+        assert!(!verify_proof(
+            &leaves[1],
+            1,
+            &branch,
+            &merkle_root(&leaves[..4])
+        ));
+        assert!(!verify_proof(&leaves[1], 1, &branch[1..], &root));
+    }
+
+    #[test]
+    fn root_of_single_leaf_and_of_nothing() {
+        let leaves = leaves(1);
+        assert_eq!(merkle_root(&leaves), leaves[0]);
+        assert_eq!(merkle_root(&[]), [0u8; 32]);
+        assert_eq!(
+            merkle_root(&[leaves[0], leaves[0]]),
+            hash_pair(&leaves[0], &leaves[0])
+        );
+    }
+}
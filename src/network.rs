@@ -1,169 +1,629 @@
 use crate::block::block::Block;
-use crate::blockchain::Blockchain;
-use crate::mempool::Mempool;
-use crate::peers::PeerManager;
+use crate::blockchain::{BlockError, Blockchain};
+use crate::clock::Clock;
+use crate::compact::{self, CompactBlock, CompactRelay};
+use crate::discovery::{self, Discovery};
+use crate::events::{ChainEvent, EventBus};
+use crate::gossip::{self, Gossip};
+use crate::light;
+use crate::mempool::{Mempool, RejectReason};
+use crate::noise::{self, NodeKey, NoiseReader, NoiseWriter};
+use crate::peers::{self, BanList, PeerManager};
+use crate::protocol::{self, Handshake, InvItem, InvKind, Message, ProtocolError};
+use crate::ratelimit::{self, ConnectionLimiter};
+use crate::scoring::{self, Misbehavior};
+use crate::session::{self, SessionRegistry};
+use crate::storage;
+use crate::sync::{self, SyncManager};
 use crate::transaction::Transaction;
+use ed25519_dalek::SigningKey;
 use rusqlite::Connection;
 use std::sync::{Arc, Mutex};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::time::Duration;
+use tokio::io::{ReadHalf, WriteHalf};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
 
-pub async fn broadcast_transaction(tx: &Transaction, peers: &PeerManager) {
-    broadcast_to_peers(&serde_json::to_string(tx).unwrap(), peers).await;
-}
+pub type TcpReader = NoiseReader<ReadHalf<TcpStream>>;
+pub type TcpWriter = NoiseWriter<WriteHalf<TcpStream>>;
+
+pub const DEFAULT_CHAIN_ID: &str = "async-pos-devnet";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const DIAL_INTERVAL: Duration = Duration::from_secs(5);
+const PEER_STORE_INTERVAL: Duration = Duration::from_secs(60);
+pub const DEFAULT_MAX_INBOUND: usize = 32;
+pub const DEFAULT_MAX_OUTBOUND: usize = 8;
+pub const DEFAULT_MAX_PER_IP: usize = 4;
+/// 除已建立的入站会话外，允许同时进行握手的连接数
+const PENDING_HANDSHAKE_SLOTS: usize = 16;
 
-pub async fn broadcast_block(block: &Block, peers: &PeerManager) {
-    broadcast_to_peers(&serde_json::to_string(block).unwrap(), peers).await;
+/// 入站与出站会话数量上限
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionLimits {
+    pub max_inbound: usize,
+    pub max_outbound: usize,
+    /// 单个 IP 同时保持的入站连接数上限，包括握手中的连接
+    pub max_per_ip: usize,
 }
 
-async fn broadcast_to_peers(data: &str, peers: &PeerManager) {
-    for addr in peers.list() {
-        if let Ok(mut stream) = TcpStream::connect(&addr).await {
-            let _ = stream.write_all(data.as_bytes()).await;
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        ConnectionLimits {
+            max_inbound: DEFAULT_MAX_INBOUND,
+            max_outbound: DEFAULT_MAX_OUTBOUND,
+            max_per_ip: DEFAULT_MAX_PER_IP,
         }
     }
 }
 
-pub async fn start_server(port: u16, chain: Arc<Mutex<Blockchain>>, mempool: Arc<Mutex<Mempool>>) {
-    let listener = TcpListener::bind(("0.0.0.0", port)).await.unwrap();
-    println!("🌐 监听地址: 0.0.0.0:{}", port);
-    loop {
-        let (mut socket, _) = listener.accept().await.unwrap();
-        let chain = Arc::clone(&chain);
-        let mempool = Arc::clone(&mempool);
-        tokio::spawn(async move {
-            handle_incoming_connection(&mut socket, chain, mempool).await;
-        });
+/// 本节点在握手中公布的身份信息
+pub struct LocalNode {
+    pub chain_id: String,
+    /// 由 `key` 的公钥派生
+    pub node_id: String,
+    pub listen_addr: String,
+    pub key: NodeKey,
+    /// 出块使用的验证者密钥，未配置时只同步不出块
+    pub validator: Option<SigningKey>,
+}
+
+impl LocalNode {
+    pub fn handshake(&self, chain: &Blockchain) -> Handshake {
+        Handshake {
+            protocol_version: protocol::PROTOCOL_VERSION,
+            chain_id: self.chain_id.clone(),
+            genesis_hash: chain.genesis_hash(),
+            node_id: self.node_id.clone(),
+            listen_addr: self.listen_addr.clone(),
+            best_height: chain.height(),
+            best_hash: chain.get_last_hash(),
+        }
     }
 }
 
-async fn handle_incoming_connection(
-    socket: &mut TcpStream,
-    chain: Arc<Mutex<Blockchain>>,
-    mempool: Arc<Mutex<Mempool>>,
-) {
-    let mut buf = [0; 1024];
-    if let Ok(n) = socket.read(&mut buf).await {
-        if let Ok(text) = std::str::from_utf8(&buf[..n]) {
-            match parse_network_message(text) {
-                NetworkMessage::PeersRequest => {
-                    send_peers_response(socket).await;
-                }
-                NetworkMessage::PeersResponse(arr) => {
-                    update_peers_from_response(&arr).await;
-                }
-                NetworkMessage::Transaction(tx) => {
-                    println!("📥 接收到交易: {} -> {} [{}]", tx.from, tx.to, tx.amount);
-                    mempool.lock().unwrap().add(tx, None);
-                }
-                NetworkMessage::Block(block) => {
-                    println!("📥 接收到区块: {} from {}", block.index, block.proposer);
-                    chain.lock().unwrap().chain.push(block);
-                }
-                NetworkMessage::Unknown => {}
+/// 网络层共享的节点状态，会话任务与消息处理都通过它访问链、mempool 与对端
+#[derive(Clone)]
+pub struct NetworkContext {
+    pub local: Arc<LocalNode>,
+    pub chain: Arc<Mutex<Blockchain>>,
+    pub mempool: Arc<Mutex<Mempool>>,
+    pub peers: Arc<Mutex<PeerManager>>,
+    pub sessions: Arc<Mutex<SessionRegistry>>,
+    pub sync: Arc<Mutex<SyncManager>>,
+    pub gossip: Arc<Mutex<Gossip>>,
+    /// 等待对端补齐交易的压缩区块
+    pub compact: Arc<Mutex<CompactRelay>>,
+    pub discovery: Arc<Mutex<Discovery>>,
+    pub limits: ConnectionLimits,
+    pub db: Arc<Mutex<Connection>>,
+    /// 本节点的 peers.db 连接，节点记录与封禁列表都通过它读写
+    pub peer_db: Arc<Mutex<Connection>>,
+    /// 有效封禁的内存缓存，写入时同步落库
+    pub bans: Arc<Mutex<BanList>>,
+    /// 新区块与新交易的事件，供 RPC 订阅使用
+    pub events: EventBus,
+    /// 同步与 gossip 的超时判断使用的时间来源
+    pub clock: Clock,
+    /// 管理接口请求节点退出
+    pub shutdown: Arc<Notify>,
+}
+
+impl NetworkContext {
+    pub fn handshake(&self) -> Handshake {
+        self.local.handshake(&self.chain.lock().unwrap())
+    }
+
+    /// 校验来自其他节点的区块，通过后追加到本地链并写入 chain.db，再从 mempool 中移除已上链的交易
+    pub fn import_block(&self, block: Block) -> Result<(), BlockError> {
+        let mut chain = self.chain.lock().unwrap();
+        chain.import_block(block, self.clock.now_secs())?;
+        let mut mempool = self.mempool.lock().unwrap();
+        let conn = self.db.lock().unwrap();
+        storage::save_account_state(&conn, &chain.state).unwrap();
+        storage::save_block(&conn, chain.chain.last().unwrap()).unwrap();
+        mempool.prune_stale(&chain.state, Some(&conn));
+        self.events.block_added(&chain);
+        Ok(())
+    }
+
+    /// 切换到在 `base` 高度分叉、由 `blocks` 延续的链；新链不优于本地链时返回 Ok(false)。
+    /// 切换后 chain.db 中 `base` 以上的区块与账户表整体替换，mempool 移除新链上已执行的交易，
+    /// 被替换区块中的交易放回 mempool
+    pub fn reorg(&self, base: u64, blocks: Vec<Block>) -> Result<bool, BlockError> {
+        let mut chain = self.chain.lock().unwrap();
+        let Some(orphaned) = chain.reorg(base, blocks, self.clock.now_secs())? else {
+            return Ok(false);
+        };
+        println!(
+            "🔀 链重组: 在高度 {} 分叉，替换 {} 个区块，新链顶高度 {}",
+            base,
+            orphaned.len(),
+            chain.height()
+        );
+        {
+            let conn = self.db.lock().unwrap();
+            storage::delete_blocks_above(&conn, base).unwrap();
+            for block in &chain.chain[base as usize + 1..] {
+                storage::save_block(&conn, block).unwrap();
             }
+            storage::replace_account_state(&conn, &chain.state).unwrap();
         }
+        let mut mempool = self.mempool.lock().unwrap();
+        let conn = self.db.lock().unwrap();
+        // 先放回被孤立的交易，再按新链的 nonce 清理并重建队列
+        for tx in orphaned.iter().flat_map(|b| b.transactions.iter().cloned()) {
+            let _ = mempool.add_relayed(tx, &chain.state, Some(&conn));
+        }
+        mempool.prune_stale(&chain.state, Some(&conn));
+        self.events.reorganized(&chain, &orphaned, base);
+        Ok(true)
     }
 }
 
-enum NetworkMessage {
-    PeersRequest,
-    PeersResponse(Vec<String>),
-    Transaction(Transaction),
-    Block(Block),
-    Unknown,
-}
-
-fn parse_network_message(text: &str) -> NetworkMessage {
-    if let Ok(val) = serde_json::from_str::<serde_json::Value>(text) {
-        match val.get("type").and_then(|v| v.as_str()) {
-            Some("peers_request") => NetworkMessage::PeersRequest,
-            Some("peers_response") => {
-                let arr = val
-                    .get("peers")
-                    .and_then(|v| v.as_array())
-                    .map(|a| {
-                        a.iter()
-                            .filter_map(|p| p.as_str().map(|s| s.to_string()))
-                            .collect()
-                    })
-                    .unwrap_or_default();
-                NetworkMessage::PeersResponse(arr)
-            }
-            _ => NetworkMessage::Unknown,
+/// 一次性连接发送交易，供不维持会话的 CLI 使用
+pub async fn broadcast_transaction(
+    tx: &Transaction,
+    peers: &PeerManager,
+    key: &NodeKey,
+    hello: &Handshake,
+) {
+    let msg = Message::Transaction(tx.clone());
+    for addr in peers.list() {
+        if let Err(ProtocolError::Handshake(reason)) = send_message(&addr, &msg, key, hello).await {
+            println!("⚠️ 与节点 {} 握手失败: {}", addr, reason);
         }
-    } else if let Ok(tx) = serde_json::from_str::<Transaction>(text) {
-        NetworkMessage::Transaction(tx)
-    } else if let Ok(block) = serde_json::from_str::<Block>(text) {
-        NetworkMessage::Block(block)
-    } else {
-        NetworkMessage::Unknown
     }
 }
 
-async fn send_peers_response(socket: &mut TcpStream) {
-    let peer_conn = Connection::open("peers.db").unwrap();
-    let peers = PeerManager::load_from_db(&peer_conn).unwrap_or_default();
-    let resp = serde_json::json!({"type": "peers_response", "peers": peers.list()});
-    let _ = socket
-        .write_all(serde_json::to_string(&resp).unwrap().as_bytes())
-        .await;
+pub async fn send_message(
+    addr: &str,
+    msg: &Message,
+    key: &NodeKey,
+    hello: &Handshake,
+) -> Result<(), ProtocolError> {
+    let (_, mut writer, _) = connect(addr, key, hello).await?;
+    writer.write_message(msg).await
 }
 
-async fn update_peers_from_response(arr: &Vec<String>) {
-    let peer_conn = Connection::open("peers.db").unwrap();
-    let mut peers = PeerManager::load_from_db(&peer_conn).unwrap_or_default();
-    for addr in arr {
-        peers.add_peer(addr.to_string());
+/// 握手中声明的 node id 必须由 Noise 握手认证过的静态公钥派生
+fn verify_identity(remote: &Handshake, remote_static: &[u8]) -> Result<(), String> {
+    if remote.node_id != noise::node_id_from_public(remote_static) {
+        return Err("node id does not match static key".to_string());
     }
-    let _ = peers.save_to_db(&peer_conn);
+    Ok(())
 }
 
-pub async fn discover_peers(peers: &mut PeerManager) {
-    let peer_list = peers.list();
-    for addr in peer_list {
-        if let Ok(mut stream) = TcpStream::connect(&addr).await {
-            let req = serde_json::json!({"type": "peers_request"});
-            let _ = stream
-                .write_all(serde_json::to_string(&req).unwrap().as_bytes())
-                .await;
-            if let Err(e) = handle_stream(stream, peers).await {
-                eprintln!("Error handling stream: {}", e);
+/// 建立出站连接：先完成 Noise XX 握手，再在加密通道上交换握手信息
+pub async fn connect(
+    addr: &str,
+    key: &NodeKey,
+    hello: &Handshake,
+) -> Result<(TcpReader, TcpWriter, Handshake), ProtocolError> {
+    let stream = TcpStream::connect(addr).await?;
+    let handshake = async {
+        let (mut reader, mut writer, remote_static) = noise::initiate(stream, key).await?;
+        writer
+            .write_message(&Message::Handshake(hello.clone()))
+            .await?;
+        match reader.read_message().await? {
+            Some(Message::Handshake(remote)) => {
+                verify_identity(&remote, &remote_static).map_err(ProtocolError::Handshake)?;
+                hello
+                    .check_compatible(&remote)
+                    .map_err(ProtocolError::Handshake)?;
+                Ok((reader, writer, remote))
             }
+            Some(Message::Disconnect(reason)) => Err(ProtocolError::Handshake(reason)),
+            _ => Err(ProtocolError::Handshake("expected handshake".to_string())),
+        }
+    };
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .map_err(|_| ProtocolError::Handshake("timed out".to_string()))?
+}
+
+/// 入站连接先完成 Noise 握手，第一条加密消息必须是握手信息，不兼容的对端收到 Disconnect 后被断开
+async fn accept_handshake(
+    socket: TcpStream,
+    peer_addr: &str,
+    ctx: &NetworkContext,
+) -> Result<(TcpReader, TcpWriter, Handshake), ProtocolError> {
+    let handshake = async {
+        let (mut reader, mut writer, remote_static) =
+            noise::respond(socket, &ctx.local.key).await?;
+        let remote = match reader.read_message().await? {
+            Some(Message::Handshake(remote)) => remote,
+            _ => return Err(ProtocolError::Handshake("expected handshake".to_string())),
+        };
+        let hello = ctx.handshake();
+        let checked = verify_identity(&remote, &remote_static)
+            .and_then(|()| hello.check_compatible(&remote))
+            .and_then(|()| {
+                // 按实际连接的 IP 判断，对端自报的监听地址不可信
+                if scoring::is_banned(ctx, &remote.node_id, peer_addr) {
+                    Err("banned".to_string())
+                } else if ctx.sessions.lock().unwrap().count(false) >= ctx.limits.max_inbound {
+                    Err("too many inbound connections".to_string())
+                } else {
+                    Ok(())
+                }
+            });
+        if let Err(reason) = checked {
+            let _ = writer
+                .write_message(&Message::Disconnect(reason.clone()))
+                .await;
+            return Err(ProtocolError::Handshake(reason));
         }
+        writer.write_message(&Message::Handshake(hello)).await?;
+        Ok((reader, writer, remote))
+    };
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .map_err(|_| ProtocolError::Handshake("timed out".to_string()))?
+}
+
+/// 接受入站连接；超过全局或单 IP 连接上限的连接在握手前直接关闭
+pub async fn start_server(port: u16, ctx: NetworkContext) {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await.unwrap();
+    println!("🌐 监听地址: 0.0.0.0:{}", port);
+    let limiter = ConnectionLimiter::new(
+        ctx.limits.max_inbound + PENDING_HANDSHAKE_SLOTS,
+        ctx.limits.max_per_ip,
+    );
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("⚠️ 接受连接失败: {}", e);
+                tokio::time::sleep(ratelimit::ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
+        let permit = match limiter.try_acquire(peer.ip()) {
+            Ok(permit) => permit,
+            Err(reason) => {
+                println!("🚫 拒绝连接 {}: {}", peer, reason);
+                continue;
+            }
+        };
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            handle_incoming_connection(socket, ctx).await;
+            drop(permit);
+        });
     }
 }
 
-async fn handle_stream(
-    stream: TcpStream,
-    peers: &mut PeerManager,
-) -> Result<(), Box<dyn std::error::Error>> {
-    read_and_handle_json(stream, peers).await
+async fn handle_incoming_connection(socket: TcpStream, ctx: NetworkContext) {
+    let peer_addr = socket
+        .peer_addr()
+        .map(|a| a.to_string())
+        .unwrap_or_default();
+    let (reader, writer, remote) = match accept_handshake(socket, &peer_addr, &ctx).await {
+        Ok(accepted) => accepted,
+        Err(e) => {
+            println!("🚫 拒绝连接: {}", e);
+            return;
+        }
+    };
+    let addr = if remote.listen_addr.is_empty() {
+        peer_addr.clone()
+    } else {
+        remote.listen_addr.clone()
+    };
+    let now = chrono::Utc::now().timestamp() as u64;
+    ctx.peers
+        .lock()
+        .unwrap()
+        .record_success(&addr, &remote.node_id, now);
+    session::run_session(ctx, remote, addr, peer_addr, false, reader, writer).await;
 }
 
-async fn read_and_handle_json(
-    mut stream: TcpStream,
-    peers: &mut PeerManager,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut buf = [0; 2048];
-    if let Ok(n) = stream.read(&mut buf).await {
-        if let Ok(text) = std::str::from_utf8(&buf[..n]) {
-            if let Ok(val) = serde_json::from_str::<serde_json::Value>(text) {
-                handle_json_value(val, peers).await;
+/// 定期为已知但未连接的节点拨号，优先选择表现良好的节点；失败的地址按指数退避重试
+pub fn spawn_connection_manager(ctx: NetworkContext) {
+    tokio::spawn(async move {
+        loop {
+            let bans = scoring::active_bans(&ctx);
+            let scores = ctx.sessions.lock().unwrap().scores();
+            let known: Vec<String> = ctx
+                .peers
+                .lock()
+                .unwrap()
+                .preferred(|node_id| scores.get(node_id).copied().unwrap_or(0))
+                .into_iter()
+                .filter(|addr| *addr != ctx.local.listen_addr)
+                .filter(|addr| !bans.iter().any(|b| b.matches_addr(addr)))
+                .collect();
+            let now = chrono::Utc::now().timestamp() as u64;
+            let candidates = ctx.sessions.lock().unwrap().take_dial_candidates(
+                &known,
+                now,
+                ctx.limits.max_outbound,
+            );
+            for addr in candidates {
+                tokio::spawn(dial_peer(ctx.clone(), addr));
             }
+            tokio::time::sleep(DIAL_INTERVAL).await;
+        }
+    });
+}
+
+async fn dial_peer(ctx: NetworkContext, addr: String) {
+    let hello = ctx.handshake();
+    // 封禁按对端 node id 判断，需要握手后才能得知
+    let connected =
+        connect(&addr, &ctx.local.key, &hello)
+            .await
+            .and_then(|(reader, writer, remote)| {
+                if scoring::is_banned(&ctx, &remote.node_id, &addr) {
+                    Err(ProtocolError::Handshake("peer is banned".to_string()))
+                } else {
+                    Ok((reader, writer, remote))
+                }
+            });
+    match connected {
+        Ok((reader, writer, remote)) => {
+            ctx.sessions.lock().unwrap().dial_finished(&addr, true);
+            let now = chrono::Utc::now().timestamp() as u64;
+            ctx.peers
+                .lock()
+                .unwrap()
+                .record_success(&addr, &remote.node_id, now);
+            session::run_session(ctx, remote, addr.clone(), addr, true, reader, writer).await;
+        }
+        Err(e) => {
+            if let ProtocolError::Handshake(reason) = &e {
+                println!("⚠️ 与节点 {} 握手失败: {}", addr, e);
+                // 地址指向本节点自身，不再保留
+                if reason == "connected to self" {
+                    ctx.peers.lock().unwrap().remove(&addr);
+                    let _ = peers::delete_peers(
+                        &ctx.peer_db.lock().unwrap(),
+                        std::slice::from_ref(&addr),
+                    );
+                }
+            }
+            ctx.sessions.lock().unwrap().dial_finished(&addr, false);
+            ctx.peers.lock().unwrap().record_failure(&addr);
         }
     }
-    Ok(())
 }
 
-pub async fn handle_json_value(val: serde_json::Value, peers: &mut PeerManager) {
-    if val.get("type") == Some(&serde_json::Value::String("peers_response".to_string())) {
-        if let Some(arr) = val.get("peers").and_then(|v| v.as_array()) {
-            for p in arr {
-                if let Some(addr) = p.as_str() {
-                    peers.add_peer(addr.to_string());
+/// 定期合并 CLI 写入 peers.db 的新地址与封禁变更、清理失效节点，并把连接统计写回 peers.db
+pub fn spawn_peer_store_maintenance(ctx: NetworkContext) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(PEER_STORE_INTERVAL).await;
+            let now = ctx.clock.now_secs();
+            let (stored, bans) = {
+                let peer_conn = ctx.peer_db.lock().unwrap();
+                (
+                    PeerManager::load_from_db(&peer_conn),
+                    BanList::load(&peer_conn, now),
+                )
+            };
+            if let Ok(bans) = bans {
+                *ctx.bans.lock().unwrap() = bans;
+            }
+            // 先在 peers 锁内合并与清理，再在库锁内写回，两把锁不同时持有
+            let (pruned, records) = {
+                let mut peers = ctx.peers.lock().unwrap();
+                if let Ok(stored) = stored {
+                    peers.merge(stored);
+                }
+                let pruned = peers.prune(now);
+                (pruned, peers.records().to_vec())
+            };
+            let peer_conn = ctx.peer_db.lock().unwrap();
+            if !pruned.is_empty() {
+                println!("🧹 清理 {} 个长期无法连接的节点", pruned.len());
+                let _ = peers::delete_peers(&peer_conn, &pruned);
+            }
+            let _ = peers::save_records(&peer_conn, &records);
+        }
+    });
+}
+
+/// 处理会话收到的一条消息，回复通过会话表放入对端的发送队列
+pub fn handle_message(ctx: &NetworkContext, peer_id: &str, msg: Message) {
+    match msg {
+        Message::PeersRequest => discovery::on_peers_request(ctx, peer_id),
+        Message::PeersResponse(addrs) => discovery::on_peers_response(ctx, peer_id, addrs),
+        Message::Transaction(tx) => {
+            let hash = tx.hash();
+            if !ctx.gossip.lock().unwrap().mark_seen(Some(peer_id), &hash) {
+                return;
+            }
+            println!("📥 接收到交易: {} -> {} [{}]", tx.from, tx.to, tx.amount);
+            let admitted = {
+                let chain = ctx.chain.lock().unwrap();
+                let mut mempool = ctx.mempool.lock().unwrap();
+                let conn = ctx.db.lock().unwrap();
+                mempool.add_relayed(tx.clone(), &chain.state, Some(&conn))
+            };
+            match admitted {
+                Ok(admission) => {
+                    if admission.queued {
+                        println!("⏳ 交易 nonce {} 超前，进入排队队列", tx.nonce);
+                    }
+                    ctx.events.publish(ChainEvent::PendingTransaction(tx));
+                    gossip::announce(
+                        ctx,
+                        InvItem {
+                            kind: InvKind::Tx,
+                            hash,
+                        },
+                        Some(peer_id),
+                    );
                 }
+                Err(reason) => {
+                    println!("❌ 拒绝交易 [{}]: {}", reason.code(), reason);
+                    if matches!(
+                        reason,
+                        RejectReason::InvalidSignature
+                            | RejectReason::WrongChain { .. }
+                            | RejectReason::TooLarge { .. }
+                            | RejectReason::ZeroAmount
+                    ) {
+                        scoring::report(ctx, peer_id, Misbehavior::InvalidTransaction);
+                    }
+                }
+            }
+        }
+        Message::Block(block) => {
+            println!("📥 接收到区块: {} from {}", block.index, block.proposer);
+            ctx.gossip.lock().unwrap().mark_known(peer_id, &block.hash);
+            if ctx
+                .chain
+                .lock()
+                .unwrap()
+                .block_by_hash(&block.hash)
+                .is_some()
+            {
+                return;
+            }
+            import_relayed_block(ctx, peer_id, block);
+        }
+        Message::GetBlock(hash) => {
+            let block = ctx.chain.lock().unwrap().block_by_hash(&hash).cloned();
+            if let Some(block) = block {
+                ctx.sessions
+                    .lock()
+                    .unwrap()
+                    .send(peer_id, Message::Block(block));
             }
         }
+        Message::CompactBlock(block) => compact::on_compact_block(ctx, peer_id, block),
+        Message::GetBlockTxs(request) => compact::on_get_block_txs(ctx, peer_id, request),
+        Message::BlockTxs(txs) => compact::on_block_txs(ctx, peer_id, txs),
+        Message::GetHeaders(range) => {
+            let headers = sync::headers_in_range(&ctx.chain.lock().unwrap(), range);
+            ctx.sessions
+                .lock()
+                .unwrap()
+                .send(peer_id, Message::Headers(headers));
+        }
+        Message::GetBlocks(range) => {
+            let blocks = sync::blocks_in_range(&ctx.chain.lock().unwrap(), range);
+            ctx.sessions
+                .lock()
+                .unwrap()
+                .send(peer_id, Message::Blocks(blocks));
+        }
+        Message::Inventory(items) => {
+            if items.len() > gossip::MAX_INV_ITEMS {
+                scoring::report(ctx, peer_id, Misbehavior::OversizedInventory);
+            }
+            let now = ctx.clock.now_secs();
+            let wanted = ctx.gossip.lock().unwrap().on_inventory(peer_id, items, now);
+            // 已经在链上或 mempool 中的内容无需再拉取
+            let wanted: Vec<InvItem> = {
+                let chain = ctx.chain.lock().unwrap();
+                let mempool = ctx.mempool.lock().unwrap();
+                wanted
+                    .into_iter()
+                    .filter(|item| match item.kind {
+                        InvKind::Tx => mempool.get(&item.hash).is_none(),
+                        InvKind::Block => chain.block_by_hash(&item.hash).is_none(),
+                    })
+                    .collect()
+            };
+            if !wanted.is_empty() {
+                ctx.sessions
+                    .lock()
+                    .unwrap()
+                    .send(peer_id, Message::GetData(wanted));
+            }
+        }
+        Message::GetData(items) => {
+            if items.len() > gossip::MAX_INV_ITEMS {
+                scoring::report(ctx, peer_id, Misbehavior::OversizedInventory);
+            }
+            let replies: Vec<Message> = {
+                let chain = ctx.chain.lock().unwrap();
+                let mempool = ctx.mempool.lock().unwrap();
+                items
+                    .iter()
+                    .take(gossip::MAX_INV_ITEMS)
+                    .filter_map(|item| match item.kind {
+                        InvKind::Tx => mempool.get(&item.hash).cloned().map(Message::Transaction),
+                        InvKind::Block => chain
+                            .block_by_hash(&item.hash)
+                            .map(|b| Message::CompactBlock(CompactBlock::from_block(b))),
+                    })
+                    .collect()
+            };
+            let sessions = ctx.sessions.lock().unwrap();
+            for reply in replies {
+                sessions.send(peer_id, reply);
+            }
+        }
+        Message::Headers(headers) => {
+            ctx.sync.lock().unwrap().on_headers(ctx, peer_id, headers);
+        }
+        Message::Blocks(blocks) => {
+            ctx.sync.lock().unwrap().on_blocks(ctx, peer_id, blocks);
+        }
+        Message::Ping(nonce) => {
+            ctx.sessions
+                .lock()
+                .unwrap()
+                .send(peer_id, Message::Pong(nonce));
+        }
+        Message::GetTxProof(hash) => {
+            let proof = light::tx_proof(&ctx.chain.lock().unwrap(), &hash);
+            ctx.sessions
+                .lock()
+                .unwrap()
+                .send(peer_id, Message::TxProof(proof));
+        }
+        Message::GetAccountProof(address) => {
+            let proof = light::account_proof(&ctx.chain.lock().unwrap(), &address);
+            ctx.sessions
+                .lock()
+                .unwrap()
+                .send(peer_id, Message::AccountProof(proof));
+        }
+        Message::Pong(_)
+        | Message::Handshake(_)
+        | Message::Disconnect(_)
+        | Message::TxProof(_)
+        | Message::AccountProof(_) => {}
+    }
+}
+
+/// 导入 gossip 转发来的完整区块，成功后继续向其他对端公告
+/// 区块导入后才更新对端的链顶；领先或分叉的区块交给同步状态机下载验证
+pub fn import_relayed_block(ctx: &NetworkContext, peer_id: &str, block: Block) {
+    let (index, hash) = (block.index, block.hash.clone());
+    match ctx.import_block(block) {
+        Ok(()) => {
+            ctx.sessions
+                .lock()
+                .unwrap()
+                .update_best(peer_id, index, &hash);
+            gossip::announce(
+                ctx,
+                InvItem {
+                    kind: InvKind::Block,
+                    hash,
+                },
+                Some(peer_id),
+            );
+        }
+        // 领先本地多个高度的区块交给同步状态机补齐
+        Err(BlockError::UnexpectedIndex { expected, got }) if got > expected => {
+            println!("⏩ 区块 {} 领先本地高度 {}，等待同步", got, expected - 1);
+            ctx.sync.lock().unwrap().on_announced(peer_id, index, &hash);
+        }
+        // 与本地链竞争或分叉的区块，是否切换由同步状态机按分叉选择规则决定
+        Err(e) if !e.is_invalid() => {
+            println!("❌ 拒绝区块: {}", e);
+            ctx.sync.lock().unwrap().on_announced(peer_id, index, &hash);
+        }
+        Err(e) => {
+            println!("❌ 拒绝区块: {}", e);
+            scoring::report(ctx, peer_id, Misbehavior::InvalidBlock);
+        }
     }
 }
@@ -1,127 +1,277 @@
-use crate::blockchain::Blockchain;
+use crate::accounts::account::AccountState;
+use crate::admin::{self, AdminAuth, AdminConfig};
 use crate::block::block::Block;
-use crate::mempool::Mempool;
-use crate::network;
-use crate::peers::PeerManager;
+use crate::blockchain::{self, Blockchain};
+use crate::clock::Clock;
+use crate::compact::CompactRelay;
+use crate::discovery::{self, Discovery};
+use crate::dry_run::{self, Receipt, SignatureCheck, StateView, Transfer};
+use crate::events::{ChainEvent, EventBus};
+use crate::gossip::{self, Gossip};
+use crate::http::HttpConfig;
+use crate::keys;
+use crate::mempool::{Admission, Mempool, RejectReason};
+use crate::network::{self, ConnectionLimits, LocalNode, NetworkContext};
+use crate::noise::NodeKey;
+use crate::peers::{self, BanEntry, BanList, PeerManager, PeerSource};
+use crate::protocol::{Handshake, InvItem, InvKind, Message};
+use crate::scoring;
+use crate::session::SessionRegistry;
 use crate::storage;
+use crate::sync::{self, SyncManager};
 use crate::transaction::Transaction;
-use rusqlite::Connection;
+use ed25519_dalek::SigningKey;
+use rusqlite::{Connection, OpenFlags};
 use std::sync::{Arc, Mutex};
-use tokio::io::AsyncWriteExt;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// 收到关闭请求后留给管理接口发出响应的时间
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(200);
 
 tokio::task_local! {
     static NODE_LOG: String;
 }
 
-pub async fn run_node(port: u16) {
+/// 本节点所在的链、公布给对端的地址与出块使用的验证者密钥
+pub struct NodeIdentity {
+    pub chain_id: String,
+    /// 默认 127.0.0.1:<port>
+    pub advertise: Option<String>,
+    pub validator: Option<SigningKey>,
+}
+
+pub async fn run_node(
+    port: u16,
+    identity: NodeIdentity,
+    limits: ConnectionLimits,
+    seeds: Vec<String>,
+    rpc: HttpConfig,
+    admin: AdminConfig,
+) {
     println!("🚀 启动 PoS 节点，监听端口 {}", port);
+    let auth = match AdminAuth::load_or_create(&admin) {
+        Ok(auth) => Arc::new(auth),
+        Err(e) => {
+            println!("❌ 无法加载管理凭据 {}: {}", admin.token_file, e);
+            return;
+        }
+    };
     let conn_arc = Arc::new(Mutex::new(init_db_and_accounts()));
-    let _peers_arc = Arc::new(Mutex::new(load_peers()));
-    let chain_arc = Arc::new(Mutex::new(load_blockchain(&conn_arc)));
-    let mempool_arc = Arc::new(Mutex::new(load_mempool(&conn_arc)));
-
-    spawn_block_producer(
-        Arc::clone(&chain_arc),
-        Arc::clone(&mempool_arc),
-        Arc::clone(&_peers_arc),
-        Arc::clone(&conn_arc),
-    );
-    spawn_jsonrpc_server();
-    spawn_peer_discovery(Arc::clone(&_peers_arc));
-    network::start_server(port, chain_arc, mempool_arc).await;
+    let peer_db = Connection::open("peers.db").unwrap();
+    let listen_addr = identity
+        .advertise
+        .unwrap_or_else(|| format!("127.0.0.1:{}", port));
+    let local_addrs = [listen_addr.clone(), format!("0.0.0.0:{}", port)];
+    let _peers_arc = Arc::new(Mutex::new(load_peers(&peer_db, &seeds, &local_addrs)));
+    let key = peers::load_or_create_node_key(&peer_db).unwrap();
+    let bans = BanList::load(&peer_db, Clock::System.now_secs()).unwrap_or_default();
+    let local = Arc::new(LocalNode {
+        chain_id: identity.chain_id,
+        node_id: key.node_id(),
+        listen_addr,
+        key,
+        validator: identity.validator,
+    });
+    println!("🆔 节点 ID: {} | 链 ID: {}", local.node_id, local.chain_id);
+    match &local.validator {
+        Some(key) => println!(
+            "🔑 验证者地址: {}",
+            keys::address_of(key.verifying_key().as_bytes())
+        ),
+        None => println!("ℹ️ 未配置验证者密钥，本节点只同步不出块"),
+    }
+    let mut chain = load_blockchain(&conn_arc);
+    chain.chain_id = local.chain_id.clone();
+    let mempool_arc = Arc::new(Mutex::new(load_mempool(
+        &conn_arc,
+        &chain.state,
+        &chain.chain_id,
+    )));
+    let chain_arc = Arc::new(Mutex::new(chain));
+
+    let ctx = NetworkContext {
+        local,
+        chain: chain_arc,
+        mempool: mempool_arc,
+        peers: _peers_arc,
+        sessions: Arc::new(Mutex::new(SessionRegistry::default())),
+        sync: Arc::new(Mutex::new(SyncManager::new(Clock::System.now_secs()))),
+        gossip: Arc::new(Mutex::new(Gossip::new())),
+        compact: Arc::new(Mutex::new(CompactRelay::default())),
+        discovery: Arc::new(Mutex::new(Discovery::default())),
+        limits,
+        db: conn_arc,
+        peer_db: Arc::new(Mutex::new(peer_db)),
+        bans: Arc::new(Mutex::new(bans)),
+        events: EventBus::default(),
+        clock: Clock::System,
+        shutdown: Arc::new(Notify::new()),
+    };
+
+    sync::spawn_sync(ctx.clone());
+    if ctx.local.validator.is_some() {
+        spawn_block_producer(ctx.clone());
+    }
+    spawn_jsonrpc_server(ctx.clone(), rpc, admin.port, auth);
+    discovery::spawn_discovery(ctx.clone());
+    network::spawn_connection_manager(ctx.clone());
+    network::spawn_peer_store_maintenance(ctx.clone());
+    tokio::select! {
+        _ = network::start_server(port, ctx.clone()) => {}
+        _ = ctx.shutdown.notified() => {
+            let records = ctx.peers.lock().unwrap().records().to_vec();
+            let _ = peers::save_records(&ctx.peer_db.lock().unwrap(), &records);
+            tokio::time::sleep(SHUTDOWN_GRACE).await;
+            println!("👋 节点已关闭");
+        }
+    }
 }
 
 fn init_db_and_accounts() -> Connection {
     let conn = Connection::open("chain.db").unwrap();
-    storage::init_db(&conn).unwrap();
-    storage::init_account_table(&conn).unwrap();
-    storage::add_account(&conn, "admin", 1000000).unwrap();
-    storage::add_account(&conn, "Alice", 100).unwrap();
-    storage::add_account(&conn, "Bob", 100).unwrap();
+    // WAL 模式下快照等只读连接不会阻塞节点写入
+    conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+    init_chain_db(&conn);
     conn
 }
 
-fn load_peers() -> PeerManager {
-    let peer_conn = Connection::open("peers.db").unwrap();
-    PeerManager::load_from_db(&peer_conn).unwrap_or_default()
+/// 建表并写入初始账户
+pub fn init_chain_db(conn: &Connection) {
+    // 旧格式的 chain.db 无法迁移，只能删除后重新同步
+    if let Err(e) = storage::check_version(conn).unwrap() {
+        println!("❌ {}", e);
+        std::process::exit(1);
+    }
+    storage::init_db(conn).unwrap();
+    storage::init_account_table(conn).unwrap();
+    storage::init_mempool_table(conn).unwrap();
+    for (address, balance) in blockchain::genesis_accounts() {
+        storage::add_account(conn, &address, balance).unwrap();
+    }
 }
 
-fn load_blockchain(conn_arc: &Arc<Mutex<Connection>>) -> Blockchain {
-    let mut chain = Blockchain::new();
-    let conn = conn_arc.lock().unwrap();
-    let mut idx = 0u64;
-    loop {
-        let block_opt = storage::get_block_by_index(&conn, idx).unwrap();
-        if let Some(block) = block_opt {
-            chain.chain.push(block);
-            idx += 1;
-        } else {
-            break;
+/// 读取 peers.db，删除指向本节点的记录，并把命令行指定的种子节点加入已知节点
+fn load_peers(peer_conn: &Connection, seeds: &[String], local_addrs: &[String]) -> PeerManager {
+    let mut peers = PeerManager::load_from_db(peer_conn).unwrap_or_default();
+    let own = peers.set_local_addrs(local_addrs);
+    if !own.is_empty() {
+        let _ = peers::delete_peers(peer_conn, &own);
+    }
+    for seed in seeds {
+        if !peers.add_peer(seed.clone(), PeerSource::Seed) && peers.get(seed).is_none() {
+            println!("⚠️ 忽略无效的种子节点地址: {}", seed);
         }
     }
-    if chain.chain.is_empty() {
-        chain.create_genesis_block();
-        storage::save_block(&conn, chain.chain.last().unwrap()).unwrap();
+    let _ = peers.save_to_db(peer_conn);
+    peers
+}
+
+pub fn load_blockchain(conn_arc: &Arc<Mutex<Connection>>) -> Blockchain {
+    let conn = conn_arc.lock().unwrap();
+    let mut chain = read_blockchain(&conn).unwrap();
+    if storage::get_block_by_index(&conn, 0).unwrap().is_none() {
+        storage::save_block(&conn, &chain.chain[0]).unwrap();
     }
+    chain.state = storage::load_account_state(&conn).unwrap();
     chain
 }
 
-fn load_mempool(conn_arc: &Arc<Mutex<Connection>>) -> Mempool {
+/// 只读地载入 chain.db 中的区块与账户状态，库中没有区块时以内存中的创世区块开始
+fn read_blockchain(conn: &Connection) -> rusqlite::Result<Blockchain> {
+    let mut chain = Blockchain::genesis();
+    let mut blocks = Vec::new();
+    while let Some(block) = storage::get_block_by_index(conn, blocks.len() as u64)? {
+        blocks.push(block);
+    }
+    if !blocks.is_empty() {
+        chain.chain = blocks;
+        chain.state = storage::load_account_state(conn)?;
+        chain.replay_validators();
+    }
+    Ok(chain)
+}
+
+fn load_mempool(
+    conn_arc: &Arc<Mutex<Connection>>,
+    state: &AccountState,
+    chain_id: &str,
+) -> Mempool {
     let mut mempool = Mempool::default();
+    mempool.config.chain_id = chain_id.to_string();
     let conn = conn_arc.lock().unwrap();
-    mempool.load_from_db(&conn);
-    mempool.add(Transaction::new("Alice", "Bob", 10), Some(&conn));
-    mempool.add(Transaction::new("Bob", "Charlie", 5), Some(&conn));
+    mempool.load_from_db(&conn, state);
+    for (from_name, to, amount) in [("Alice", "Bob", 10), ("Bob", "Charlie", 5)] {
+        let from = keys::dev_address(from_name);
+        let nonce = mempool.next_nonce(state, &from);
+        let mut tx = Transaction::new(&from, &keys::dev_address(to), amount, 0, nonce)
+            .with_chain_id(chain_id);
+        tx.sign(&keys::dev_key(from_name));
+        if let Err(reason) = mempool.add(tx, state, Some(&conn)) {
+            println!("⚠️ 示例交易未加入 mempool: {}", reason);
+        }
+    }
     mempool
 }
 
-fn spawn_block_producer(
-    chain_arc: Arc<Mutex<Blockchain>>,
-    mempool_arc: Arc<Mutex<Mempool>>,
-    peers_arc: Arc<Mutex<PeerManager>>,
-    conn_arc: Arc<Mutex<Connection>>,
-) {
+/// 同步完成前不出块，避免在落后的链上分叉
+fn spawn_block_producer(ctx: NetworkContext) {
     tokio::spawn(async move {
+        let conn_arc = Arc::clone(&ctx.db);
+        let mut waiting_logged = false;
         loop {
             tokio::time::sleep(std::time::Duration::from_secs(3)).await;
-            let txs = {
-                let mut mempool = mempool_arc.lock().unwrap();
-                let conn = conn_arc.lock().unwrap();
-                mempool.collect_for_block(10, Some(&conn))
-            };
-            let (block, proposer) = {
-                let mut chain = chain_arc.lock().unwrap();
-                chain.add_block(txs.clone());
-                let block = chain.chain.last().unwrap().clone();
-                let proposer = block.proposer.clone();
-                (block, proposer)
-            };
-            {
-                let conn = conn_arc.lock().unwrap();
-                for tx in &block.transactions {
-                    let from_balance = storage::get_balance(&conn, &tx.from).unwrap_or(0);
-                    if from_balance >= tx.amount {
-                        storage::set_balance(&conn, &tx.from, from_balance - tx.amount).unwrap();
-                        let to_balance = storage::get_balance(&conn, &tx.to).unwrap_or(0);
-                        storage::set_balance(&conn, &tx.to, to_balance + tx.amount).unwrap();
-                    }
+            if !ctx.sync.lock().unwrap().is_synced() {
+                if !waiting_logged {
+                    println!("⏳ 等待区块同步完成后再出块");
+                    waiting_logged = true;
                 }
-                let reward = 50;
-                let proposer_balance = storage::get_balance(&conn, &proposer).unwrap_or(0);
-                storage::set_balance(&conn, &proposer, proposer_balance + reward).unwrap();
-                storage::save_block(&conn, &block).unwrap();
+                continue;
             }
+            waiting_logged = false;
+            let Some(block) = produce_block(&ctx) else {
+                continue;
+            };
             print_block_info(&block);
             print_account_balances(&conn_arc);
-            let peer_list = {
-                let peers = peers_arc.lock().unwrap();
-                peers.list()
-            };
-            network::broadcast_block(&block, &PeerManager { peers: peer_list }).await;
+            print_sessions(&ctx.sessions.lock().unwrap());
         }
     });
 }
 
+/// 本节点的验证者被选为链顶之上的提议者时，从 mempool 打包交易出一个新区块，
+/// 写入 chain.db 并向对端公告；否则不出块
+pub fn produce_block(ctx: &NetworkContext) -> Option<Block> {
+    let key = ctx.local.validator.as_ref()?;
+    let block = {
+        let mut chain = ctx.chain.lock().unwrap();
+        if !chain.is_proposer(key) {
+            return None;
+        }
+        let txs = {
+            let mut mempool = ctx.mempool.lock().unwrap();
+            let conn = ctx.db.lock().unwrap();
+            mempool.collect_for_block(10, &chain.state, Some(&conn))
+        };
+        chain.add_block(key, txs, ctx.clock.now_secs());
+        let block = chain.chain.last().unwrap().clone();
+        let conn = ctx.db.lock().unwrap();
+        storage::save_account_state(&conn, &chain.state).unwrap();
+        storage::save_block(&conn, &block).unwrap();
+        ctx.events.block_added(&chain);
+        block
+    };
+    gossip::announce(
+        ctx,
+        InvItem {
+            kind: InvKind::Block,
+            hash: block.hash.clone(),
+        },
+        None,
+    );
+    Some(block)
+}
+
 fn print_block_info(block: &Block) {
     println!(
         "[⛓️ 出块] 高度: {} | Hash: {} | 提议者: {} | 交易数: {}",
@@ -146,59 +296,238 @@ fn print_account_balances(conn_arc: &Arc<Mutex<Connection>>) {
     }
 }
 
-fn spawn_jsonrpc_server() {
+fn print_sessions(sessions: &SessionRegistry) {
+    println!("🔗 已连接节点: {}", sessions.len());
+    for peer in sessions.list() {
+        println!(
+            " - {} {} [{}] 高度: {} 扣分: {} 连接于: {}",
+            peer.node_id,
+            peer.addr,
+            if peer.outbound { "出站" } else { "入站" },
+            peer.best_height,
+            peer.score,
+            peer.connected_at
+        );
+    }
+}
+
+/// 指定管理端口时管理方法只在该端口开放，否则主端口凭管理凭据即可调用
+fn spawn_jsonrpc_server(
+    ctx: NetworkContext,
+    config: HttpConfig,
+    admin_port: Option<u16>,
+    auth: Arc<AdminAuth>,
+) {
+    let main_admin = match admin_port {
+        Some(port) => {
+            let (ctx, config) = (ctx.clone(), config.clone());
+            tokio::spawn(admin::start_admin_server(port, ctx, config, auth));
+            None
+        }
+        None => Some(auth),
+    };
     tokio::spawn(async move {
-        crate::rpc::start_jsonrpc_server(8545).await;
+        crate::rpc::start_jsonrpc_server(8545, Some(ctx), config, main_admin).await;
     });
 }
 
-fn spawn_peer_discovery(peers_arc: Arc<Mutex<PeerManager>>) {
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(std::time::Duration::from_secs(30)).await;
-            let peer_list = {
-                let peers = peers_arc.lock().unwrap();
-                peers.list()
-            };
-            let mut discovered = PeerManager::default();
-            for _ in peer_list {
-                network::discover_peers(&mut discovered).await;
-            }
-            let mut peers = peers_arc.lock().unwrap();
-            let before = peers.list().len();
-            for addr in discovered.list() {
-                peers.add_peer(addr);
-            }
-            let after = peers.list().len();
-            if after > before {
+/// 按运行中节点的链状态与 mempool 校验交易，入池后写入 chain.db 并向对端公告
+pub fn submit_to_node(
+    ctx: &NetworkContext,
+    tx: Transaction,
+) -> Result<(Transaction, Admission), RejectReason> {
+    let admission = {
+        let chain = ctx.chain.lock().unwrap();
+        let mut mempool = ctx.mempool.lock().unwrap();
+        let conn = ctx.db.lock().unwrap();
+        mempool.add(tx.clone(), &chain.state, Some(&conn))?
+    };
+    ctx.events
+        .publish(ChainEvent::PendingTransaction(tx.clone()));
+    gossip::announce(
+        ctx,
+        InvItem {
+            kind: InvKind::Tx,
+            hash: admission.hash.clone(),
+        },
+        None,
+    );
+    Ok((tx, admission))
+}
+
+/// 基于本地 chain.db 的账户状态校验交易并写入 mempool 表；`build` 在载入 mempool 后构造交易，
+/// 可据此取得发送方下一个可用的 nonce。指定已在 mempool 中的 nonce 并提高手续费即可替换原交易
+pub fn submit_to_local_mempool(
+    chain_id: &str,
+    build: impl FnOnce(&Mempool, &AccountState) -> Transaction,
+) -> Result<(Transaction, Admission), RejectReason> {
+    let conn = init_db_and_accounts();
+    let state = storage::load_account_state(&conn).unwrap();
+    let mut mempool = Mempool::default();
+    mempool.config.chain_id = chain_id.to_string();
+    mempool.load_from_db(&conn, &state);
+    let tx = build(&mempool, &state);
+    let admission = mempool.add(tx.clone(), &state, Some(&conn))?;
+    Ok((tx, admission))
+}
+
+/// 基于运行中节点的链状态与 mempool 模拟转账
+pub fn simulate_on_node(ctx: &NetworkContext, view: StateView, transfer: Transfer) -> Receipt {
+    let chain = ctx.chain.lock().unwrap();
+    let mempool = ctx.mempool.lock().unwrap();
+    let tx = transfer.into_transaction(&chain, &mempool, view);
+    dry_run::simulate(&chain, &mempool, view, tx)
+}
+
+/// 基于本地 chain.db 的链状态与 mempool 表模拟转账；以只读方式打开数据库，不建表也不写入，
+/// chain.db 不存在或无法读取时基于创世状态
+pub fn simulate_local(view: StateView, transfer: Transfer) -> Receipt {
+    let (chain, mempool) =
+        read_local_state("chain.db").unwrap_or_else(|| (Blockchain::genesis(), Mempool::default()));
+    let tx = transfer.into_transaction(&chain, &mempool, view);
+    dry_run::simulate(&chain, &mempool, view, tx)
+}
+
+fn read_local_state(path: &str) -> Option<(Blockchain, Mempool)> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok()?;
+    let version: u32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .ok()?;
+    if version != storage::CHAIN_DB_VERSION {
+        println!(
+            "⚠️ {}，改为基于创世状态模拟",
+            storage::IncompatibleDb { found: version }
+        );
+        return None;
+    }
+    let chain = read_blockchain(&conn).ok()?;
+    let mut mempool = Mempool::default();
+    mempool.restore(
+        storage::load_all_mempool_txs(&conn).unwrap_or_default(),
+        &chain.state,
+    );
+    Some((chain, mempool))
+}
+
+pub fn dry_run(
+    view: StateView,
+    from: String,
+    to: String,
+    amount: u64,
+    fee: u64,
+    nonce: Option<u64>,
+) {
+    let transfer = Transfer {
+        from: keys::resolve(&from),
+        to: keys::resolve(&to),
+        amount,
+        fee,
+        nonce,
+        ..Transfer::default()
+    };
+    let receipt = simulate_local(view, transfer);
+    let tx = &receipt.transaction;
+    println!(
+        "🧪 模拟交易: {} -> {} [{}] fee: {} nonce: {} hash: {}",
+        tx.from, tx.to, tx.amount, tx.fee, tx.nonce, receipt.tx_hash
+    );
+    if receipt.signature == SignatureCheck::NotChecked {
+        println!("交易未签名，未检查签名");
+    }
+    let base = match receipt.state {
+        StateView::Latest => "链顶状态",
+        StateView::Pending => "计入待打包交易后的状态",
+    };
+    println!("基于{} (链高度 {})", base, receipt.tip_height);
+    match &receipt.error {
+        None => {
+            println!("✅ 执行成功，手续费: {}", receipt.fee);
+            for change in &receipt.balance_changes {
                 println!(
-                    "[发现节点] 新增 {} 个节点，当前已知节点总数: {}",
-                    after - before,
-                    after
+                    " - {}: {} -> {}",
+                    change.address, change.before, change.after
                 );
-            } else {
-                println!("[发现节点] 未发现新节点，当前已知节点总数: {}", after);
             }
-            let peer_conn = Connection::open("peers.db").unwrap();
-            let _ = peers.save_to_db(&peer_conn);
         }
-    });
+        Some(error) => println!("❌ 执行失败 [{}]: {}", error.code, error.message),
+    }
 }
 
-pub async fn submit_tx(from: String, to: String, amount: u64) {
-    let tx = Transaction::new(&from, &to, amount);
-    println!("💸 交易提交: {} -> {} [{}]", from, to, amount);
+pub fn sign_tx(from: String, to: String, amount: u64, fee: u64, nonce: u64, chain_id: String) {
+    let Some(key) = keys::dev_signer(&from) else {
+        println!("❌ 没有账户 {} 的签名密钥", from);
+        return;
+    };
+    let from = keys::address_of(key.verifying_key().as_bytes());
+    let mut tx =
+        Transaction::new(&from, &keys::resolve(&to), amount, fee, nonce).with_chain_id(&chain_id);
+    tx.sign(&key);
+    println!("{}", serde_json::to_string(&tx).unwrap());
+}
+
+/// `from` 为开发账户名或地址，交易以该账户的开发密钥签名并绑定 `chain_id`
+pub async fn submit_tx(
+    from: String,
+    to: String,
+    amount: u64,
+    fee: u64,
+    nonce: Option<u64>,
+    chain_id: String,
+) {
+    let Some(key) = keys::dev_signer(&from) else {
+        println!("❌ 没有账户 {} 的签名密钥", from);
+        return;
+    };
+    let to = keys::resolve(&to);
+    let submitted = submit_to_local_mempool(&chain_id, |mempool, state| {
+        let from = keys::address_of(key.verifying_key().as_bytes());
+        let nonce = nonce.unwrap_or_else(|| mempool.next_nonce(state, &from));
+        let mut tx = Transaction::new(&from, &to, amount, fee, nonce).with_chain_id(&chain_id);
+        tx.sign(&key);
+        tx
+    });
+    let (tx, admission) = match submitted {
+        Ok(admitted) => admitted,
+        Err(reason) => {
+            println!("❌ 交易被拒绝 [{}]: {}", reason.code(), reason);
+            return;
+        }
+    };
+    println!(
+        "💸 交易提交: {} -> {} [{}] fee: {} nonce: {} hash: {}",
+        tx.from, tx.to, amount, tx.fee, tx.nonce, admission.hash
+    );
+    if let Some(replaced) = admission.replaced {
+        println!("🔁 已替换待打包交易 {}", replaced);
+    }
+    if admission.queued {
+        println!("⏳ nonce {} 之前存在空洞，交易已排队等待", tx.nonce);
+    }
     let peer_conn = Connection::open("peers.db").unwrap();
     let peers = PeerManager::load_from_db(&peer_conn).unwrap_or_default();
-    network::broadcast_transaction(&tx, &peers).await;
-    let data = serde_json::to_string(&tx).unwrap();
-    if let Ok(mut stream) = tokio::net::TcpStream::connect("127.0.0.1:8000").await {
-        let _ = stream.write_all(data.as_bytes()).await;
+    let (key, hello) = cli_handshake(&chain_id);
+    network::broadcast_transaction(&tx, &peers, &key, &hello).await;
+    if let Err(e) =
+        network::send_message("127.0.0.1:8000", &Message::Transaction(tx), &key, &hello).await
+    {
+        println!("⚠️ 无法发送到本地节点: {}", e);
     }
-    let conn = Connection::open("chain.db").unwrap();
-    let mut mempool = Mempool::default();
-    mempool.load_from_db(&conn);
-    mempool.add(tx, Some(&conn));
+}
+
+/// CLI 以临时生成的静态密钥与节点握手，链信息取自本地 chain.db，链 ID 需与节点的 `--chain-id` 一致
+fn cli_handshake(chain_id: &str) -> (NodeKey, Handshake) {
+    let conn_arc = Arc::new(Mutex::new(init_db_and_accounts()));
+    let chain = load_blockchain(&conn_arc);
+    let key = NodeKey::generate();
+    let local = LocalNode {
+        chain_id: chain_id.to_string(),
+        node_id: key.node_id(),
+        listen_addr: String::new(),
+        key,
+        validator: None,
+    };
+    let hello = local.handshake(&chain);
+    (local.key, hello)
 }
 
 pub fn query_block(index: u64) {
@@ -218,6 +547,7 @@ pub fn query_block(index: u64) {
 }
 
 pub fn query_balance(address: String) {
+    let address = keys::resolve(&address);
     let conn = Connection::open("chain.db").unwrap();
     match storage::get_balance(&conn, &address) {
         Ok(balance) => println!("{} 余额: {}", address, balance),
@@ -225,10 +555,31 @@ pub fn query_balance(address: String) {
     }
 }
 
+pub fn print_addresses(name: Option<String>) {
+    let names: Vec<&str> = match &name {
+        Some(name) if !keys::DEV_ACCOUNTS.contains(&name.as_str()) => {
+            println!("❌ 未知的开发账户: {}", name);
+            return;
+        }
+        Some(name) => vec![name.as_str()],
+        None => keys::DEV_ACCOUNTS.to_vec(),
+    };
+    for name in names {
+        println!("{}: {}", name, keys::dev_address(name));
+    }
+}
+
 pub fn add_peer(addr: String) {
     let peer_conn = Connection::open("peers.db").unwrap();
     let mut peers = PeerManager::load_from_db(&peer_conn).unwrap_or_default();
-    peers.add_peer(addr.clone());
+    if !peers.add_peer(addr.clone(), PeerSource::Manual) {
+        if peers.get(&addr).is_some() {
+            println!("节点已存在: {}", addr);
+        } else {
+            println!("无效的节点地址: {}", addr);
+        }
+        return;
+    }
     peers.save_to_db(&peer_conn).unwrap();
     println!("已添加节点: {}", addr);
 }
@@ -246,11 +597,125 @@ pub fn query_tx(hash: String) {
             println!("交易哈希: {}", hash);
             println!("区块高度: {}", block_idx);
             println!(
-                "交易详情: from: {} -> to: {} amount: {}",
-                tx.from, tx.to, tx.amount
+                "交易详情: from: {} -> to: {} amount: {} fee: {} nonce: {}",
+                tx.from, tx.to, tx.amount, tx.fee, tx.nonce
             );
         }
         Ok(None) => println!("未找到该交易"),
         Err(e) => println!("查询出错: {}", e),
     }
 }
+
+pub fn list_bans() {
+    let peer_conn = Connection::open("peers.db").unwrap();
+    let now = chrono::Utc::now().timestamp() as u64;
+    let bans = peers::load_bans(&peer_conn, now).unwrap_or_default();
+    if bans.is_empty() {
+        println!("没有封禁的节点");
+        return;
+    }
+    println!("封禁节点列表:");
+    for ban in bans {
+        let until = match ban.banned_until {
+            Some(until) => until.to_string(),
+            None => "永久".to_string(),
+        };
+        println!(
+            "  {} {} 原因: {} 封禁至: {}",
+            ban.node_id, ban.addr, ban.reason, until
+        );
+    }
+}
+
+/// 目标是地址时同时封禁其 IP，否则只按 node id 封禁
+pub fn ban(target: String, duration: Option<u64>, permanent: bool) {
+    let now = chrono::Utc::now().timestamp() as u64;
+    let addr = if peers::is_valid_peer_addr(&target) {
+        peers::ban_host(&target)
+    } else {
+        String::new()
+    };
+    let ban = BanEntry {
+        node_id: target.clone(),
+        addr,
+        reason: "admin".to_string(),
+        banned_at: now,
+        banned_until: if permanent {
+            None
+        } else {
+            Some(now.saturating_add(duration.unwrap_or(scoring::BAN_DURATION_SECS)))
+        },
+    };
+    let peer_conn = Connection::open("peers.db").unwrap();
+    match peers::save_ban(&peer_conn, &ban) {
+        Ok(()) if permanent => println!("已永久封禁: {}", target),
+        Ok(()) => println!("已封禁 {} 至 {}", target, ban.banned_until.unwrap_or(0)),
+        Err(e) => println!("封禁出错: {}", e),
+    }
+}
+
+pub fn unban(target: String) {
+    let peer_conn = Connection::open("peers.db").unwrap();
+    match peers::remove_ban(&peer_conn, &target) {
+        Ok(0) => println!("未找到对 {} 的封禁", target),
+        Ok(n) => println!("已解除 {} 条封禁: {}", n, target),
+        Err(e) => println!("解除封禁出错: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dry_run_state_is_read_without_writing() {
+        let path = std::env::temp_dir().join(format!("dry-run-{}.db", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+        {
+            let conn = Connection::open(&path).unwrap();
+            init_chain_db(&conn);
+            // 无效的 mempool 交易在正常载入时会被删除，只读载入必须保留
+            let stale = Transaction::new("nobody", "b", 1, 0, 0);
+            storage::insert_mempool_tx(&conn, &stale, 0).unwrap();
+        }
+        let (chain, mempool) = read_local_state(&path).unwrap();
+        assert_eq!(chain.height(), 0);
+        assert_eq!(mempool.transactions().count(), 0);
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(storage::load_all_mempool_txs(&conn).unwrap().len(), 1);
+        assert!(storage::get_block_by_index(&conn, 0).unwrap().is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn dry_run_without_database_uses_genesis_state() {
+        assert!(read_local_state("/nonexistent/chain.db").is_none());
+        let receipt = {
+            let chain = Blockchain::genesis();
+            let mempool = Mempool::default();
+            let from = keys::dev_address("Alice");
+            let tx = Transaction::new(&from, "b", 10, 1, 0);
+            dry_run::simulate(&chain, &mempool, StateView::Latest, tx)
+        };
+        assert!(receipt.success);
+    }
+
+    #[test]
+    fn reloaded_chain_replays_proposer_stake() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_chain_db(&conn);
+        let mut chain = Blockchain::genesis();
+        for _ in 0..3 {
+            chain.add_dev_block(vec![], 1);
+        }
+        for block in &chain.chain {
+            storage::save_block(&conn, block).unwrap();
+        }
+        storage::save_account_state(&conn, &chain.state).unwrap();
+        let reloaded = read_blockchain(&conn).unwrap();
+        assert_eq!(reloaded.height(), 3);
+        assert_eq!(reloaded.validators, chain.validators);
+        assert_ne!(reloaded.validators, blockchain::genesis_validators());
+    }
+}
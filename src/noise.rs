@@ -0,0 +1,267 @@
+use crate::protocol::{self, Message, ProtocolError, HEADER_LEN, MAX_MESSAGE_SIZE};
+use sha2::{Digest, Sha256};
+use snow::{Builder, HandshakeState, StatelessTransportState};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+
+// 连接建立后先完成 Noise XX 握手：
+//   -> e
+//   <- e, ee, s, es
+//   -> s, se
+// 握手消息与之后的加密记录都以 2 字节大端长度为前缀；每个协议帧被切分为若干条加密记录。
+pub const NOISE_PATTERN: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+/// 双方必须一致的握手前言，绑定协议版本
+const PROLOGUE: &[u8] = b"APOS noise v3";
+const MAX_NOISE_MESSAGE: usize = 65535;
+const TAG_LEN: usize = 16;
+const MAX_CHUNK: usize = MAX_NOISE_MESSAGE - TAG_LEN;
+
+/// 节点长期使用的 X25519 静态密钥，node id 由公钥派生
+#[derive(Clone)]
+pub struct NodeKey {
+    pub private: Vec<u8>,
+    pub public: Vec<u8>,
+}
+
+impl NodeKey {
+    pub fn generate() -> Self {
+        let keypair = Builder::new(NOISE_PATTERN.parse().unwrap())
+            .generate_keypair()
+            .unwrap();
+        NodeKey {
+            private: keypair.private,
+            public: keypair.public,
+        }
+    }
+
+    pub fn node_id(&self) -> String {
+        node_id_from_public(&self.public)
+    }
+}
+
+/// node id 为静态公钥 SHA-256 的前 16 字节
+pub fn node_id_from_public(public: &[u8]) -> String {
+    hex::encode(&Sha256::digest(public)[..16])
+}
+
+pub struct NoiseReader<R> {
+    inner: R,
+    state: Arc<StatelessTransportState>,
+    nonce: u64,
+}
+
+pub struct NoiseWriter<W> {
+    inner: W,
+    state: Arc<StatelessTransportState>,
+    nonce: u64,
+}
+
+/// 握手完成后的读写两端，以及对端经过认证的静态公钥
+pub type SecureChannel<S> = (NoiseReader<ReadHalf<S>>, NoiseWriter<WriteHalf<S>>, Vec<u8>);
+
+fn builder(key: &NodeKey) -> Builder<'_> {
+    Builder::new(NOISE_PATTERN.parse().unwrap())
+        .local_private_key(&key.private)
+        .prologue(PROLOGUE)
+}
+
+async fn send_handshake<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    hs: &mut HandshakeState,
+) -> Result<(), ProtocolError> {
+    let mut buf = vec![0u8; MAX_NOISE_MESSAGE];
+    let n = hs.write_message(&[], &mut buf)?;
+    stream.write_all(&(n as u16).to_be_bytes()).await?;
+    stream.write_all(&buf[..n]).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn recv_handshake<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    hs: &mut HandshakeState,
+) -> Result<(), ProtocolError> {
+    let msg = read_record(stream).await?;
+    let mut buf = vec![0u8; MAX_NOISE_MESSAGE];
+    hs.read_message(&msg, &mut buf)?;
+    Ok(())
+}
+
+async fn read_record<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, ProtocolError> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut record = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut record).await?;
+    Ok(record)
+}
+
+fn finish<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    hs: HandshakeState,
+) -> Result<SecureChannel<S>, ProtocolError> {
+    let remote_static = hs
+        .get_remote_static()
+        .ok_or_else(|| ProtocolError::Noise("missing remote static key".to_string()))?
+        .to_vec();
+    let state = Arc::new(hs.into_stateless_transport_mode()?);
+    let (reader, writer) = tokio::io::split(stream);
+    Ok((
+        NoiseReader {
+            inner: reader,
+            state: Arc::clone(&state),
+            nonce: 0,
+        },
+        NoiseWriter {
+            inner: writer,
+            state,
+            nonce: 0,
+        },
+        remote_static,
+    ))
+}
+
+/// 以发起方身份完成握手
+pub async fn initiate<S>(mut stream: S, key: &NodeKey) -> Result<SecureChannel<S>, ProtocolError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut hs = builder(key).build_initiator()?;
+    send_handshake(&mut stream, &mut hs).await?;
+    recv_handshake(&mut stream, &mut hs).await?;
+    send_handshake(&mut stream, &mut hs).await?;
+    finish(stream, hs)
+}
+
+/// 以响应方身份完成握手
+pub async fn respond<S>(mut stream: S, key: &NodeKey) -> Result<SecureChannel<S>, ProtocolError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut hs = builder(key).build_responder()?;
+    recv_handshake(&mut stream, &mut hs).await?;
+    send_handshake(&mut stream, &mut hs).await?;
+    recv_handshake(&mut stream, &mut hs).await?;
+    finish(stream, hs)
+}
+
+impl<R: AsyncRead + Unpin> NoiseReader<R> {
+    async fn read_chunk(&mut self) -> Result<Vec<u8>, ProtocolError> {
+        let record = read_record(&mut self.inner).await?;
+        let mut plain = vec![0u8; record.len()];
+        let n = self.state.read_message(self.nonce, &record, &mut plain)?;
+        self.nonce += 1;
+        plain.truncate(n);
+        Ok(plain)
+    }
+
+    /// 读取并解密下一条消息；对端在帧边界关闭连接时返回 `Ok(None)`
+    pub async fn read_message(&mut self) -> Result<Option<Message>, ProtocolError> {
+        let mut frame = match self.read_chunk().await {
+            Ok(chunk) => chunk,
+            Err(ProtocolError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        while frame.len() < HEADER_LEN {
+            frame.extend(self.read_chunk().await?);
+        }
+        let header: [u8; HEADER_LEN] = frame[..HEADER_LEN].try_into().unwrap();
+        let (_, len) = protocol::decode_header(&header)?;
+        while frame.len() < HEADER_LEN + len {
+            frame.extend(self.read_chunk().await?);
+        }
+        protocol::decode(&frame).map(Some)
+    }
+}
+
+impl<W: AsyncWrite + Unpin> NoiseWriter<W> {
+    /// 加密并发送一条消息，超过 Noise 单条消息上限的帧被切分为多条记录
+    pub async fn write_message(&mut self, msg: &Message) -> Result<(), ProtocolError> {
+        let frame = protocol::encode(msg)?;
+        debug_assert!(frame.len() <= HEADER_LEN + MAX_MESSAGE_SIZE);
+        let mut out =
+            Vec::with_capacity(frame.len() + (frame.len() / MAX_CHUNK + 1) * (2 + TAG_LEN));
+        let mut buf = vec![0u8; MAX_NOISE_MESSAGE];
+        for chunk in frame.chunks(MAX_CHUNK) {
+            let n = self.state.write_message(self.nonce, chunk, &mut buf)?;
+            self.nonce += 1;
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+            out.extend_from_slice(&buf[..n]);
+        }
+        self.inner.write_all(&out).await?;
+        self.inner.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, DuplexStream};
+
+    async fn pair(
+        a: &NodeKey,
+        b: &NodeKey,
+    ) -> (SecureChannel<DuplexStream>, SecureChannel<DuplexStream>) {
+        let (left, right) = duplex(1 << 20);
+        let (initiator, responder) = tokio::join!(initiate(left, a), respond(right, b));
+        (initiator.unwrap(), responder.unwrap())
+    }
+
+    #[tokio::test]
+    async fn handshake_authenticates_both_static_keys() {
+        let (a, b) = (NodeKey::generate(), NodeKey::generate());
+        let ((_, _, seen_by_a), (_, _, seen_by_b)) = pair(&a, &b).await;
+        assert_eq!(seen_by_a, b.public);
+        assert_eq!(seen_by_b, a.public);
+        assert_eq!(node_id_from_public(&seen_by_b), a.node_id());
+    }
+
+    #[tokio::test]
+    async fn messages_round_trip_including_multi_record_frames() {
+        let (a, b) = (NodeKey::generate(), NodeKey::generate());
+        let ((_, mut a_writer, _), (mut b_reader, _, _)) = pair(&a, &b).await;
+        // 超过单条 Noise 记录上限的帧被切分后重新拼接
+        let addrs: Vec<String> = (0..20_000)
+            .map(|i| format!("10.0.{}.{}:8000", i / 256, i % 256))
+            .collect();
+        assert!(
+            protocol::encode(&Message::PeersResponse(addrs.clone()))
+                .unwrap()
+                .len()
+                > MAX_CHUNK
+        );
+        a_writer.write_message(&Message::Ping(7)).await.unwrap();
+        a_writer
+            .write_message(&Message::PeersResponse(addrs.clone()))
+            .await
+            .unwrap();
+        match b_reader.read_message().await.unwrap() {
+            Some(Message::Ping(7)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match b_reader.read_message().await.unwrap() {
+            Some(Message::PeersResponse(got)) => assert_eq!(got, addrs),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn tampered_records_and_clean_close() {
+        let (a, b) = (NodeKey::generate(), NodeKey::generate());
+        let ((a_reader, a_writer, _), (mut b_reader, _, _)) = pair(&a, &b).await;
+        let mut raw = a_reader.inner.unsplit(a_writer.inner);
+        raw.write_all(&[0, 20]).await.unwrap();
+        raw.write_all(&[0xAB; 20]).await.unwrap();
+        assert!(matches!(
+            b_reader.read_message().await,
+            Err(ProtocolError::Noise(_))
+        ));
+
+        let (c, d) = (NodeKey::generate(), NodeKey::generate());
+        let ((c_reader, c_writer, _), (mut d_reader, _, _)) = pair(&c, &d).await;
+        drop((c_reader, c_writer));
+        assert!(d_reader.read_message().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,592 @@
+use crate::admin;
+use crate::dry_run;
+use crate::network::NetworkContext;
+use crate::rpc::{self, Params, RpcError};
+use serde_json::{json, Map, Value};
+
+const OPENRPC_VERSION: &str = "1.2.6";
+
+/// 方法参数与返回值的类型：生成 OpenRPC 文档中的 JSON Schema，也用于校验请求参数
+#[derive(Debug, Clone, Copy)]
+pub enum Schema {
+    String,
+    /// 非负整数
+    Integer,
+    Number,
+    Boolean,
+    /// 取值限定的字符串
+    Enum(&'static [&'static str]),
+    Array(&'static Schema),
+    Object(&'static [Field]),
+    /// 值可能为 null
+    Nullable(&'static Schema),
+    /// 引用 `COMPONENTS` 中的同名类型
+    Ref(&'static str),
+    /// 不限定结构的 JSON 值
+    Any,
+}
+
+/// 对象的字段，非必需字段可能不出现
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub schema: Schema,
+    pub required: bool,
+}
+
+/// 方法的参数，顺序即位置参数的顺序，名称即命名参数的键
+#[derive(Debug, Clone, Copy)]
+pub struct Param {
+    pub name: &'static str,
+    pub schema: Schema,
+    pub required: bool,
+}
+
+impl Param {
+    pub const fn required(name: &'static str, schema: Schema) -> Self {
+        Param {
+            name,
+            schema,
+            required: true,
+        }
+    }
+
+    pub const fn optional(name: &'static str, schema: Schema) -> Self {
+        Param {
+            name,
+            schema,
+            required: false,
+        }
+    }
+}
+
+impl Field {
+    pub const fn required(name: &'static str, schema: Schema) -> Self {
+        Field {
+            name,
+            schema,
+            required: true,
+        }
+    }
+
+    pub const fn optional(name: &'static str, schema: Schema) -> Self {
+        Field {
+            name,
+            schema,
+            required: false,
+        }
+    }
+}
+
+impl Schema {
+    /// 值是否符合该类型；对象必须带有全部必需字段，出现的字段都要符合各自的类型，
+    /// 引用类型按 `COMPONENTS` 中的定义检查
+    pub fn accepts(&self, value: &Value) -> bool {
+        match self {
+            Schema::String => value.is_string(),
+            Schema::Integer => value.is_u64(),
+            Schema::Number => value.is_number(),
+            Schema::Boolean => value.is_boolean(),
+            Schema::Enum(values) => value.as_str().is_some_and(|v| values.contains(&v)),
+            Schema::Array(item) => value
+                .as_array()
+                .is_some_and(|items| items.iter().all(|v| item.accepts(v))),
+            Schema::Object(fields) => value.as_object().is_some_and(|object| {
+                fields.iter().all(|f| match object.get(f.name) {
+                    Some(v) => f.schema.accepts(v),
+                    None => !f.required,
+                })
+            }),
+            Schema::Nullable(inner) => value.is_null() || inner.accepts(value),
+            Schema::Ref(name) => COMPONENTS
+                .iter()
+                .find(|(n, _)| n == name)
+                .is_some_and(|(_, schema)| schema.accepts(value)),
+            Schema::Any => true,
+        }
+    }
+
+    /// 参数类型错误时提示的期望类型
+    pub fn describe(&self) -> String {
+        match self {
+            Schema::String => "a string".to_string(),
+            Schema::Integer => "an unsigned integer".to_string(),
+            Schema::Number => "a number".to_string(),
+            Schema::Boolean => "a boolean".to_string(),
+            Schema::Enum(values) => format!("one of {}", values.join(", ")),
+            Schema::Array(item) => format!("an array of {}", item.describe()),
+            Schema::Object(fields) => {
+                let required: Vec<&str> = fields
+                    .iter()
+                    .filter(|f| f.required)
+                    .map(|f| f.name)
+                    .collect();
+                format!("an object with {}", required.join(", "))
+            }
+            Schema::Nullable(inner) => inner.describe(),
+            Schema::Ref(name) => format!("a {} object", name),
+            Schema::Any => "a JSON value".to_string(),
+        }
+    }
+
+    pub fn to_json(self) -> Value {
+        match self {
+            Schema::String => json!({"type": "string"}),
+            Schema::Integer => json!({"type": "integer", "minimum": 0}),
+            Schema::Number => json!({"type": "number"}),
+            Schema::Boolean => json!({"type": "boolean"}),
+            Schema::Enum(values) => json!({"type": "string", "enum": values}),
+            Schema::Array(item) => json!({"type": "array", "items": item.to_json()}),
+            Schema::Object(fields) => {
+                let properties: Map<String, Value> = fields
+                    .iter()
+                    .map(|f| (f.name.to_string(), f.schema.to_json()))
+                    .collect();
+                let required: Vec<&str> = fields
+                    .iter()
+                    .filter(|f| f.required)
+                    .map(|f| f.name)
+                    .collect();
+                json!({"type": "object", "properties": properties, "required": required})
+            }
+            Schema::Nullable(inner) => json!({"oneOf": [inner.to_json(), {"type": "null"}]}),
+            Schema::Ref(name) => json!({ "$ref": format!("#/components/schemas/{}", name) }),
+            Schema::Any => json!({}),
+        }
+    }
+}
+
+const TRANSACTION: Schema = Schema::Object(&[
+    Field::required("from", Schema::String),
+    Field::required("to", Schema::String),
+    Field::required("amount", Schema::Integer),
+    Field::required("fee", Schema::Integer),
+    Field::required("nonce", Schema::Integer),
+    Field::optional("chain_id", Schema::String),
+    Field::optional("public_key", Schema::String),
+    Field::optional("signature", Schema::String),
+]);
+
+/// `send_transaction` 只接受带签名的交易
+const SIGNED_TRANSACTION: Schema = Schema::Object(&[
+    Field::required("from", Schema::String),
+    Field::required("to", Schema::String),
+    Field::required("amount", Schema::Integer),
+    Field::required("fee", Schema::Integer),
+    Field::required("nonce", Schema::Integer),
+    Field::optional("chain_id", Schema::String),
+    Field::required("public_key", Schema::String),
+    Field::required("signature", Schema::String),
+]);
+
+const BLOCK: Schema = Schema::Object(&[
+    Field::required("index", Schema::Integer),
+    Field::required("previous_hash", Schema::String),
+    Field::required("timestamp", Schema::Integer),
+    Field::required("transactions", Schema::Array(&Schema::Ref("Transaction"))),
+    Field::required("proposer", Schema::String),
+    Field::required("hash", Schema::String),
+    Field::required("state_root", Schema::String),
+    Field::required("proposer_key", Schema::String),
+    Field::required("signature", Schema::String),
+]);
+
+const BLOCK_HEADER: Schema = Schema::Object(&[
+    Field::required("index", Schema::Integer),
+    Field::required("previous_hash", Schema::String),
+    Field::required("timestamp", Schema::Integer),
+    Field::required("tx_root", Schema::String),
+    Field::required("proposer", Schema::String),
+    Field::required("hash", Schema::String),
+    Field::required("state_root", Schema::String),
+    Field::required("proposer_key", Schema::String),
+    Field::required("signature", Schema::String),
+]);
+
+const TRANSACTION_STATUS: Schema = Schema::Object(&[
+    Field::required("status", Schema::Enum(&["confirmed", "pending"])),
+    Field::required("block_height", Schema::Nullable(&Schema::Integer)),
+    Field::required("block_hash", Schema::Nullable(&Schema::String)),
+    Field::required("transaction", Schema::Ref("Transaction")),
+]);
+
+const PENDING_TRANSACTION: Schema = Schema::Object(&[
+    Field::required("hash", Schema::String),
+    Field::required("transaction", Schema::Ref("Transaction")),
+]);
+
+const ACCOUNT: Schema = Schema::Object(&[
+    Field::required("address", Schema::String),
+    Field::required("balance", Schema::Integer),
+    Field::required("nonce", Schema::Integer),
+    Field::required("pending_nonce", Schema::Integer),
+]);
+
+const SYNC_STATUS: Schema = Schema::Object(&[
+    Field::required(
+        "phase",
+        Schema::Enum(&["waiting_for_peers", "headers", "blocks", "synced"]),
+    ),
+    Field::required("current_height", Schema::Integer),
+    Field::required("start_height", Schema::Integer),
+    Field::required("target_height", Schema::Integer),
+    Field::required("pending_headers", Schema::Integer),
+    Field::required("downloaded", Schema::Integer),
+    Field::required("in_flight", Schema::Integer),
+    Field::required("progress_percent", Schema::Number),
+]);
+
+const TX_PROOF: Schema = Schema::Object(&[
+    Field::required("header", Schema::Ref("BlockHeader")),
+    Field::required("tx", Schema::Ref("Transaction")),
+    Field::required("index", Schema::Integer),
+    Field::required("branch", Schema::Array(&Schema::String)),
+]);
+
+const ACCOUNT_PROOF: Schema = Schema::Object(&[
+    Field::required("height", Schema::Integer),
+    Field::required("block_hash", Schema::String),
+    Field::required("address", Schema::String),
+    Field::required("balance", Schema::Integer),
+    Field::required("nonce", Schema::Integer),
+    Field::required("index", Schema::Integer),
+    Field::required("branch", Schema::Array(&Schema::String)),
+]);
+
+const PEER_INFO: Schema = Schema::Object(&[
+    Field::required("node_id", Schema::String),
+    Field::required("addr", Schema::String),
+    Field::required("remote_addr", Schema::String),
+    Field::required("outbound", Schema::Boolean),
+    Field::required("best_height", Schema::Integer),
+    Field::required("best_hash", Schema::String),
+    Field::required("connected_at", Schema::Integer),
+    Field::required("last_seen", Schema::Integer),
+    Field::required("score", Schema::Integer),
+]);
+
+const PEER_RECORD: Schema = Schema::Object(&[
+    Field::required("addr", Schema::String),
+    Field::required("node_id", Schema::String),
+    Field::required("source", Schema::Enum(&["manual", "seed", "exchange"])),
+    Field::required("added_at", Schema::Integer),
+    Field::required("last_seen", Schema::Integer),
+    Field::required("failures", Schema::Integer),
+]);
+
+const BAN: Schema = Schema::Object(&[
+    Field::required("node_id", Schema::String),
+    Field::required("addr", Schema::String),
+    Field::required("reason", Schema::String),
+    Field::required("banned_at", Schema::Integer),
+    Field::required("banned_until", Schema::Nullable(&Schema::Integer)),
+]);
+
+const SIMULATION_RECEIPT: Schema = Schema::Object(&[
+    Field::required("tx_hash", Schema::String),
+    Field::required("transaction", Schema::Ref("Transaction")),
+    Field::required("state", Schema::Enum(dry_run::STATE_VIEWS)),
+    Field::required("tip_height", Schema::Integer),
+    Field::required("signature", Schema::Enum(dry_run::SIGNATURE_CHECKS)),
+    Field::required("success", Schema::Boolean),
+    Field::required(
+        "error",
+        Schema::Nullable(&Schema::Object(&[
+            Field::required("code", Schema::Enum(dry_run::ERROR_CODES)),
+            Field::required("message", Schema::String),
+        ])),
+    ),
+    Field::required("fee", Schema::Integer),
+    Field::required(
+        "balance_changes",
+        Schema::Array(&Schema::Object(&[
+            Field::required("address", Schema::String),
+            Field::required("before", Schema::Integer),
+            Field::required("after", Schema::Integer),
+        ])),
+    ),
+]);
+
+/// 多个方法共用的类型，方法表中以 `Schema::Ref` 引用
+pub const COMPONENTS: &[(&str, Schema)] = &[
+    ("Transaction", TRANSACTION),
+    ("SignedTransaction", SIGNED_TRANSACTION),
+    ("Block", BLOCK),
+    ("BlockHeader", BLOCK_HEADER),
+    ("TransactionStatus", TRANSACTION_STATUS),
+    ("PendingTransaction", PENDING_TRANSACTION),
+    ("Account", ACCOUNT),
+    ("SyncStatus", SYNC_STATUS),
+    ("TxProof", TX_PROOF),
+    ("AccountProof", ACCOUNT_PROOF),
+    ("PeerInfo", PEER_INFO),
+    ("PeerRecord", PEER_RECORD),
+    ("Ban", BAN),
+    ("SimulationReceipt", SIMULATION_RECEIPT),
+];
+
+const ERRORS: &[(&str, i64, &str)] = &[
+    ("ParseError", rpc::PARSE_ERROR, "parse error"),
+    ("InvalidRequest", rpc::INVALID_REQUEST, "invalid request"),
+    ("MethodNotFound", rpc::METHOD_NOT_FOUND, "method not found"),
+    ("InvalidParams", rpc::INVALID_PARAMS, "invalid params"),
+    ("InternalError", rpc::INTERNAL_ERROR, "internal error"),
+    (
+        "NodeNotRunning",
+        rpc::NODE_NOT_RUNNING,
+        "node is not running",
+    ),
+    (
+        "TransactionRejected",
+        rpc::TX_REJECTED,
+        "transaction rejected",
+    ),
+    (
+        "WebSocketRequired",
+        rpc::WEBSOCKET_REQUIRED,
+        "subscriptions are only available over WebSocket",
+    ),
+    (
+        "TooManySubscriptions",
+        rpc::TOO_MANY_SUBSCRIPTIONS,
+        "too many subscriptions",
+    ),
+    (
+        "Unauthorized",
+        rpc::UNAUTHORIZED,
+        "admin methods require a valid admin token",
+    ),
+];
+
+/// 由方法表生成的 OpenRPC 文档，包括需要管理凭据的方法
+pub fn document() -> Value {
+    let methods: Vec<Value> = rpc::METHODS
+        .iter()
+        .chain(admin::METHODS)
+        .map(|method| {
+            let params: Vec<Value> = method
+                .params
+                .iter()
+                .map(|p| json!({"name": p.name, "required": p.required, "schema": p.schema.to_json()}))
+                .collect();
+            let mut doc = json!({
+                "name": method.name,
+                "summary": method.summary,
+                "paramStructure": "either",
+                "params": params,
+                "result": {"name": "result", "schema": method.result.to_json()},
+            });
+            if method.name.starts_with(admin::PREFIX) {
+                doc["tags"] = json!([{"name": "admin"}]);
+            }
+            doc
+        })
+        .collect();
+    let schemas: Map<String, Value> = COMPONENTS
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.to_json()))
+        .collect();
+    let errors: Map<String, Value> = ERRORS
+        .iter()
+        .map(|(name, code, message)| (name.to_string(), json!({"code": code, "message": message})))
+        .collect();
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "async-pos-chain JSON-RPC",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "methods": methods,
+        "components": {"schemas": schemas, "errors": errors},
+    })
+}
+
+pub fn handle_discover(_: &Params, _: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    Ok(document())
+}
+
+/// 输出 OpenRPC 文档，未指定文件时打印到标准输出
+pub fn dump(output: Option<String>) {
+    let doc = serde_json::to_string_pretty(&document()).unwrap();
+    match output {
+        Some(path) => match std::fs::write(&path, doc + "\n") {
+            Ok(()) => println!("已写入 OpenRPC 文档: {}", path),
+            Err(e) => println!("写入 {} 出错: {}", path, e),
+        },
+        None => println!("{}", doc),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn refs(value: &Value, out: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(r)) = map.get("$ref") {
+                    out.push(r.clone());
+                }
+                map.values().for_each(|v| refs(v, out));
+            }
+            Value::Array(items) => items.iter().for_each(|v| refs(v, out)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn document_lists_every_method_once() {
+        let doc = document();
+        let names: Vec<&str> = doc["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        let unique: HashSet<&str> = names.iter().copied().collect();
+        assert_eq!(unique.len(), names.len());
+        for method in rpc::METHODS.iter().chain(admin::METHODS) {
+            assert!(unique.contains(method.name), "{} missing", method.name);
+            assert!(rpc::find_method(method.name).is_some());
+        }
+        let admin_tagged = doc["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|m| m["tags"][0]["name"] == "admin")
+            .count();
+        assert_eq!(admin_tagged, admin::METHODS.len());
+    }
+
+    #[test]
+    fn every_schema_reference_resolves() {
+        let doc = document();
+        let mut found = Vec::new();
+        refs(&doc, &mut found);
+        assert!(!found.is_empty());
+        for r in found {
+            let name = r.strip_prefix("#/components/schemas/").unwrap();
+            assert!(doc["components"]["schemas"].get(name).is_some(), "{}", r);
+        }
+    }
+
+    /// 每个方法的示例参数，新增方法时需要在这里补充
+    fn sample_params(name: &str, block_hash: &str, tx_hash: &str, subscription: &str) -> Value {
+        let alice = crate::keys::dev_address("Alice");
+        let admin = crate::keys::dev_address("admin");
+        match name {
+            "send_transaction" => {
+                let key = crate::keys::dev_key("admin");
+                json!([crate::transaction::Transaction::signed(
+                    &key, &alice, 1, 1, 2
+                )])
+            }
+            "simulate_transaction" => json!([admin, alice, 1]),
+            "get_headers" => json!([0]),
+            "get_tx_proof" | "get_transaction" => json!([tx_hash]),
+            "get_block" => json!([1]),
+            "get_block_by_hash" => json!([block_hash]),
+            "get_account_proof" | "get_balance" | "get_nonce" | "get_account" => json!([alice]),
+            "subscribe" => json!(["new_heads"]),
+            "unsubscribe" => json!([subscription]),
+            "admin_add_peer" => json!(["127.0.0.1:9999"]),
+            "admin_ban" | "admin_unban" => json!(["sim-other"]),
+            "sync_status" | "list_bans" | "get_height" | "get_blocks" | "get_latest_block"
+            | "get_mempool" | "get_validators" | "get_peers" | "rpc_discover" | "admin_peers"
+            | "admin_snapshot" | "admin_shutdown" => json!([]),
+            other => panic!("no sample params for {}", other),
+        }
+    }
+
+    #[test]
+    fn every_method_result_matches_its_schema() {
+        let dir = std::env::temp_dir().join(format!("openrpc-results-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let ctx = crate::simulator::sim_node(0, crate::clock::Clock::new_virtual(), 0);
+        let conn = rusqlite::Connection::open(dir.join("chain.db")).unwrap();
+        crate::node::init_chain_db(&conn);
+        *ctx.db.lock().unwrap() = conn;
+
+        // 链上一笔交易、mempool 中一笔交易，使查询方法返回非空结果
+        let key = crate::keys::dev_key("admin");
+        let alice = crate::keys::dev_address("Alice");
+        let confirmed = crate::transaction::Transaction::signed(&key, &alice, 1, 1, 0);
+        let block_hash = {
+            let mut chain = ctx.chain.lock().unwrap();
+            chain.add_dev_block(vec![confirmed.clone()], 1);
+            chain.chain[1].hash.clone()
+        };
+        let pending = crate::transaction::Transaction::signed(&key, &alice, 1, 1, 1);
+        crate::node::submit_to_node(&ctx, pending).unwrap();
+
+        let mut subscription = String::new();
+        for method in rpc::METHODS.iter().chain(admin::METHODS) {
+            let params = sample_params(method.name, &block_hash, &confirmed.hash(), &subscription);
+            let result = match method.name {
+                // 订阅只能通过 WebSocket 连接
+                "subscribe" | "unsubscribe" => {
+                    let text =
+                        json!({"jsonrpc": "2.0", "method": method.name, "params": params, "id": 1});
+                    let mut subs = std::collections::HashMap::new();
+                    if !subscription.is_empty() {
+                        subs.insert(subscription.clone(), crate::ws::Topic::NewHeads);
+                    }
+                    let reply = crate::ws::handle_text(
+                        &text.to_string(),
+                        Some(&ctx),
+                        rpc::Access::Public,
+                        &mut subs,
+                    )
+                    .unwrap();
+                    reply["result"].clone()
+                }
+                _ => rpc::call(method.name, Some(&params), Some(&ctx), rpc::Access::Admin)
+                    .unwrap_or_else(|e| panic!("{} failed: {:?}", method.name, e)),
+            };
+            if method.name == "subscribe" {
+                subscription = result.as_str().unwrap_or_default().to_string();
+            }
+            assert!(
+                method.result.accepts(&result),
+                "{} returned {} which does not match {}",
+                method.name,
+                result,
+                method.result.to_json()
+            );
+        }
+        drop(_guard);
+        drop(runtime);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn schemas_accept_matching_values_only() {
+        static ITEM: Schema = Schema::Integer;
+        assert!(Schema::Integer.accepts(&json!(3)));
+        assert!(!Schema::Integer.accepts(&json!(-3)));
+        assert!(Schema::Enum(&["latest", "pending"]).accepts(&json!("pending")));
+        assert!(!Schema::Enum(&["latest", "pending"]).accepts(&json!("final")));
+        assert!(Schema::Array(&ITEM).accepts(&json!([1, 2])));
+        assert!(!Schema::Array(&ITEM).accepts(&json!([1, "2"])));
+        assert!(Schema::Nullable(&ITEM).accepts(&Value::Null));
+        // 对象缺少必需字段或字段类型不符时不接受，可选字段可以缺省
+        let account = Schema::Ref("Account");
+        let full = json!({"address": "a", "balance": 1, "nonce": 0, "pending_nonce": 0});
+        assert!(account.accepts(&full));
+        let mut missing = full.clone();
+        missing.as_object_mut().unwrap().remove("balance");
+        assert!(!account.accepts(&missing));
+        assert!(!account
+            .accepts(&json!({"address": "a", "balance": "1", "nonce": 0, "pending_nonce": 0})));
+        let tx = json!({"from": "a", "to": "b", "amount": 1, "fee": 0, "nonce": 0});
+        assert!(TRANSACTION.accepts(&tx));
+        assert!(!SIGNED_TRANSACTION.accepts(&tx));
+        assert_eq!(
+            Schema::Array(&ITEM).describe(),
+            "an array of an unsigned integer"
+        );
+    }
+}
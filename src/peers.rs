@@ -1,44 +1,740 @@
+use crate::noise::NodeKey;
+use rand::seq::SliceRandom;
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+
+/// 连续失败达到该次数、且长时间没有连通过的节点会被清理
+pub const MAX_FAILURES: u32 = 10;
+pub const PRUNE_AFTER_SECS: u64 = 3 * 24 * 60 * 60;
+/// 已知节点数量上限，超出时淘汰质量最差的非手动节点
+pub const MAX_KNOWN_PEERS: usize = 1000;
+
+/// 节点地址的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerSource {
+    /// 通过 add-peer 手动添加，不会被自动清理
+    Manual,
+    Seed,
+    /// 从其他节点的地址交换中得到
+    Exchange,
+}
+
+impl PeerSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PeerSource::Manual => "manual",
+            PeerSource::Seed => "seed",
+            PeerSource::Exchange => "exchange",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "seed" => PeerSource::Seed,
+            "exchange" => PeerSource::Exchange,
+            _ => PeerSource::Manual,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerRecord {
+    pub addr: String,
+    /// 最近一次握手得到的 node id，从未连通时为空
+    pub node_id: String,
+    pub source: PeerSource,
+    pub added_at: u64,
+    /// 最近一次成功建立会话的时间，从未连通时为 0
+    pub last_seen: u64,
+    /// 自上次连通以来连续失败的拨号次数
+    pub failures: u32,
+}
+
+/// `host:port` 形式且端口非 0；IP 地址不能是未指定、组播或广播地址
+pub fn is_valid_peer_addr(addr: &str) -> bool {
+    if let Ok(sock) = addr.parse::<SocketAddr>() {
+        let ip_ok = match sock.ip() {
+            IpAddr::V4(ip) => !ip.is_unspecified() && !ip.is_multicast() && !ip.is_broadcast(),
+            IpAddr::V6(ip) => !ip.is_unspecified() && !ip.is_multicast(),
+        };
+        return ip_ok && sock.port() != 0;
+    }
+    match addr.rsplit_once(':') {
+        Some((host, port)) => {
+            !host.is_empty()
+                && host.len() <= 253
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                && port.parse::<u16>().map(|p| p != 0).unwrap_or(false)
+        }
+        None => false,
+    }
+}
+
+/// 端口相同且主机为 `local` 的主机、localhost、回环或未指定地址时，`addr` 指向本节点
+fn is_local_alias(local: &str, addr: &str) -> bool {
+    if local == addr {
+        return true;
+    }
+    let (Some((local_host, local_port)), Some((host, port))) =
+        (local.rsplit_once(':'), addr.rsplit_once(':'))
+    else {
+        return false;
+    };
+    if local_port != port {
+        return false;
+    }
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let local_host = local_host.trim_start_matches('[').trim_end_matches(']');
+    if host == local_host || host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    host.parse::<IpAddr>()
+        .is_ok_and(|ip| ip.is_loopback() || ip.is_unspecified())
+}
+
+fn now_secs() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
 #[derive(Debug, Default)]
 pub struct PeerManager {
-    pub peers: Vec<String>,
+    peers: Vec<PeerRecord>,
+    /// 本节点公布的地址与实际监听的地址，指向它们或其回环别名的地址不会被加入
+    local_addrs: Vec<String>,
 }
 
-use rusqlite::{Connection, Result};
-
 impl PeerManager {
-    pub fn add_peer(&mut self, addr: String) {
-        if !self.peers.contains(&addr) {
-            self.peers.push(addr);
+    /// 设置本节点地址，移除并返回已保存的指向本节点的记录
+    pub fn set_local_addrs(&mut self, addrs: &[String]) -> Vec<String> {
+        self.local_addrs = addrs.to_vec();
+        let (local, others): (Vec<PeerRecord>, Vec<PeerRecord>) = std::mem::take(&mut self.peers)
+            .into_iter()
+            .partition(|p| self.is_local(&p.addr));
+        self.peers = others;
+        local.into_iter().map(|p| p.addr).collect()
+    }
+
+    /// 地址是否指向本节点：与本节点地址相同，或是同一端口上的回环与未指定地址
+    pub fn is_local(&self, addr: &str) -> bool {
+        self.local_addrs
+            .iter()
+            .any(|local| is_local_alias(local, addr))
+    }
+
+    /// 添加节点地址，地址无效、指向本节点或已存在时返回 false
+    pub fn add_peer(&mut self, addr: String, source: PeerSource) -> bool {
+        if !is_valid_peer_addr(&addr) || self.is_local(&addr) || self.get(&addr).is_some() {
+            return false;
         }
+        self.peers.push(PeerRecord {
+            addr,
+            node_id: String::new(),
+            source,
+            added_at: now_secs(),
+            last_seen: 0,
+            failures: 0,
+        });
+        true
     }
 
     pub fn list(&self) -> Vec<String> {
-        self.peers.clone()
+        self.peers.iter().map(|p| p.addr.clone()).collect()
+    }
+
+    pub fn records(&self) -> &[PeerRecord] {
+        &self.peers
+    }
+
+    pub fn get(&self, addr: &str) -> Option<&PeerRecord> {
+        self.peers.iter().find(|p| p.addr == addr)
+    }
+
+    pub fn remove(&mut self, addr: &str) -> bool {
+        let before = self.peers.len();
+        self.peers.retain(|p| p.addr != addr);
+        self.peers.len() != before
+    }
+
+    pub fn record_success(&mut self, addr: &str, node_id: &str, now: u64) {
+        if let Some(peer) = self.peers.iter_mut().find(|p| p.addr == addr) {
+            peer.node_id = node_id.to_string();
+            peer.last_seen = now;
+            peer.failures = 0;
+        }
+    }
+
+    pub fn record_failure(&mut self, addr: &str) {
+        if let Some(peer) = self.peers.iter_mut().find(|p| p.addr == addr) {
+            peer.failures = peer.failures.saturating_add(1);
+        }
+    }
+
+    /// 随机抽取最多 `n` 个近期成功连通、且之后没有失败过的地址
+    pub fn sample_reachable(&self, n: usize) -> Vec<String> {
+        let reachable: Vec<&PeerRecord> = self
+            .peers
+            .iter()
+            .filter(|p| p.last_seen > 0 && p.failures == 0)
+            .collect();
+        reachable
+            .choose_multiple(&mut rand::thread_rng(), n)
+            .map(|p| p.addr.clone())
+            .collect()
+    }
+
+    /// 按出站优先级排序的地址：扣分少、失败少、最近连通过的节点优先
+    pub fn preferred<F: Fn(&str) -> u32>(&self, misbehavior: F) -> Vec<String> {
+        let mut peers: Vec<&PeerRecord> = self.peers.iter().collect();
+        peers.sort_by_key(|p| {
+            (
+                misbehavior(&p.node_id),
+                p.failures,
+                std::cmp::Reverse(p.last_seen),
+            )
+        });
+        peers.into_iter().map(|p| p.addr.clone()).collect()
+    }
+
+    /// 清理长期无法连通的节点，以及超过容量上限的最差节点；手动添加的节点保留
+    pub fn prune(&mut self, now: u64) -> Vec<String> {
+        let dead = |p: &PeerRecord| {
+            p.source != PeerSource::Manual
+                && p.failures >= MAX_FAILURES
+                && p.last_seen.max(p.added_at) + PRUNE_AFTER_SECS <= now
+        };
+        let mut removed: Vec<String> = self
+            .peers
+            .iter()
+            .filter(|p| dead(p))
+            .map(|p| p.addr.clone())
+            .collect();
+        self.peers.retain(|p| !dead(p));
+        if self.peers.len() > MAX_KNOWN_PEERS {
+            let mut evictable: Vec<&PeerRecord> = self
+                .peers
+                .iter()
+                .filter(|p| p.source != PeerSource::Manual)
+                .collect();
+            evictable.sort_by_key(|p| (std::cmp::Reverse(p.failures), p.last_seen));
+            let excess = self.peers.len() - MAX_KNOWN_PEERS;
+            let evicted: Vec<String> = evictable
+                .into_iter()
+                .take(excess)
+                .map(|p| p.addr.clone())
+                .collect();
+            self.peers.retain(|p| !evicted.contains(&p.addr));
+            removed.extend(evicted);
+        }
+        removed
     }
 
     pub fn display_peers(&self) {
         println!("已知节点列表:");
-        for (i, addr) in self.peers.iter().enumerate() {
-            println!("  [{}] {}", i, addr);
+        for (i, peer) in self.peers.iter().enumerate() {
+            println!(
+                "  [{}] {} node: {} 来源: {} 最近连通: {} 连续失败: {}",
+                i,
+                peer.addr,
+                if peer.node_id.is_empty() {
+                    "-"
+                } else {
+                    &peer.node_id
+                },
+                peer.source.as_str(),
+                peer.last_seen,
+                peer.failures
+            );
         }
     }
 
     pub fn save_to_db(&self, conn: &Connection) -> Result<()> {
-        conn.execute_batch("CREATE TABLE IF NOT EXISTS peers (addr TEXT PRIMARY KEY);")?;
-        for addr in &self.peers {
-            conn.execute("INSERT OR IGNORE INTO peers (addr) VALUES (?1)", (addr,))?;
-        }
-        Ok(())
+        save_records(conn, &self.peers)
     }
 
     pub fn load_from_db(conn: &Connection) -> Result<Self> {
-        conn.execute_batch("CREATE TABLE IF NOT EXISTS peers (addr TEXT PRIMARY KEY);")?;
-        let mut stmt = conn.prepare("SELECT addr FROM peers")?;
-        let rows = stmt.query_map([], |row| row.get(0))?;
+        init_peer_table(conn)?;
+        let mut stmt = conn.prepare(
+            "SELECT addr, node_id, source, added_at, last_seen, failures FROM peers ORDER BY added_at",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(PeerRecord {
+                addr: row.get(0)?,
+                node_id: row.get(1)?,
+                source: PeerSource::parse(&row.get::<_, String>(2)?),
+                added_at: row.get(3)?,
+                last_seen: row.get(4)?,
+                failures: row.get(5)?,
+            })
+        })?;
         let mut peers = Vec::new();
         for row in rows {
-            peers.push(row?);
+            let peer = row?;
+            if is_valid_peer_addr(&peer.addr) {
+                peers.push(peer);
+            }
+        }
+        Ok(PeerManager {
+            peers,
+            local_addrs: Vec::new(),
+        })
+    }
+
+    /// 合并从 peers.db 读出的新地址，例如节点运行期间通过 CLI 添加的节点
+    pub fn merge(&mut self, stored: PeerManager) -> usize {
+        let mut added = 0;
+        for peer in stored.peers {
+            if self.get(&peer.addr).is_none() && !self.is_local(&peer.addr) {
+                self.peers.push(peer);
+                added += 1;
+            }
+        }
+        added
+    }
+}
+
+/// 写入或更新节点记录；调用方应先复制记录再释放 `PeerManager` 的锁，避免持锁写库
+pub fn save_records(conn: &Connection, records: &[PeerRecord]) -> Result<()> {
+    init_peer_table(conn)?;
+    for peer in records {
+        conn.execute(
+            "INSERT INTO peers (addr, node_id, source, added_at, last_seen, failures)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(addr) DO UPDATE SET node_id = excluded.node_id,
+                last_seen = excluded.last_seen, failures = excluded.failures",
+            (
+                &peer.addr,
+                &peer.node_id,
+                peer.source.as_str(),
+                peer.added_at,
+                peer.last_seen,
+                peer.failures,
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+fn init_peer_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS peers (
+            addr TEXT PRIMARY KEY,
+            node_id TEXT NOT NULL DEFAULT '',
+            source TEXT NOT NULL DEFAULT 'manual',
+            added_at INTEGER NOT NULL DEFAULT 0,
+            last_seen INTEGER NOT NULL DEFAULT 0,
+            failures INTEGER NOT NULL DEFAULT 0
+        );",
+    )?;
+    // 旧版本的 peers 表只有 addr 列
+    for (column, definition) in [
+        ("node_id", "TEXT NOT NULL DEFAULT ''"),
+        ("source", "TEXT NOT NULL DEFAULT 'manual'"),
+        ("added_at", "INTEGER NOT NULL DEFAULT 0"),
+        ("last_seen", "INTEGER NOT NULL DEFAULT 0"),
+        ("failures", "INTEGER NOT NULL DEFAULT 0"),
+    ] {
+        let exists = conn
+            .prepare("SELECT 1 FROM pragma_table_info('peers') WHERE name = ?1")?
+            .exists((column,))?;
+        if !exists {
+            conn.execute_batch(&format!(
+                "ALTER TABLE peers ADD COLUMN {} {};",
+                column, definition
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn delete_peers(conn: &Connection, addrs: &[String]) -> Result<()> {
+    init_peer_table(conn)?;
+    for addr in addrs {
+        conn.execute("DELETE FROM peers WHERE addr = ?1", (addr,))?;
+    }
+    Ok(())
+}
+
+/// 读取本节点持久化的 Noise 静态密钥，首次运行时随机生成
+pub fn load_or_create_node_key(conn: &Connection) -> Result<NodeKey> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS local_node (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+    )?;
+    let load = |name: &str| -> Option<Vec<u8>> {
+        conn.query_row(
+            "SELECT value FROM local_node WHERE key = ?1",
+            (name,),
+            |row| row.get::<_, String>(0),
+        )
+        .ok()
+        .and_then(|v| hex::decode(v).ok())
+    };
+    if let (Some(private), Some(public)) = (load("noise_private"), load("noise_public")) {
+        return Ok(NodeKey { private, public });
+    }
+    let key = NodeKey::generate();
+    for (name, value) in [("noise_private", &key.private), ("noise_public", &key.public)] {
+        conn.execute(
+            "INSERT OR REPLACE INTO local_node (key, value) VALUES (?1, ?2)",
+            (name, hex::encode(value)),
+        )?;
+    }
+    Ok(key)
+}
+
+/// 因行为不当被封禁的节点，node id 或 IP 任一匹配即拒绝连接
+#[derive(Debug, Clone, Serialize)]
+pub struct BanEntry {
+    pub node_id: String,
+    /// 被封禁的 IP，覆盖该主机的所有端口；为空时只按 node id 封禁
+    pub addr: String,
+    pub reason: String,
+    pub banned_at: u64,
+    /// None 表示永久封禁，peers.db 中存为 0
+    pub banned_until: Option<u64>,
+}
+
+impl BanEntry {
+    /// `addr` 为对端的实际套接字地址或拨号地址，只比较其中的主机部分
+    pub fn matches(&self, node_id: &str, addr: &str) -> bool {
+        self.node_id == node_id || self.matches_addr(addr)
+    }
+
+    pub fn matches_addr(&self, addr: &str) -> bool {
+        !self.addr.is_empty() && !addr.is_empty() && ban_host(&self.addr) == ban_host(addr)
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        self.banned_until.is_none_or(|until| until > now)
+    }
+}
+
+/// 地址的主机部分，`1.2.3.4:8000`、`[::1]:8000` 与不带端口的 IP 都归一化为 IP 字符串
+pub fn ban_host(addr: &str) -> String {
+    if let Ok(socket) = addr.parse::<SocketAddr>() {
+        return socket.ip().to_string();
+    }
+    if let Ok(ip) = addr.parse::<IpAddr>() {
+        return ip.to_string();
+    }
+    match addr.rsplit_once(':') {
+        Some((host, _)) => host.to_string(),
+        None => addr.to_string(),
+    }
+}
+
+/// 内存中的封禁列表，修改时同步写入 peers.db；节点维护任务定期从 peers.db 重新加载，
+/// 以便读到 CLI 在节点运行期间写入或解除的封禁
+#[derive(Debug, Default)]
+pub struct BanList {
+    entries: Vec<BanEntry>,
+}
+
+impl BanList {
+    pub fn load(conn: &Connection, now: u64) -> Result<Self> {
+        Ok(BanList {
+            entries: load_bans(conn, now)?,
+        })
+    }
+
+    pub fn active(&self, now: u64) -> Vec<BanEntry> {
+        self.entries
+            .iter()
+            .filter(|ban| ban.is_active(now))
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_banned(&self, node_id: &str, addr: &str, now: u64) -> bool {
+        self.entries
+            .iter()
+            .any(|ban| ban.is_active(now) && ban.matches(node_id, addr))
+    }
+
+    pub fn insert(&mut self, conn: &Connection, ban: BanEntry) -> Result<()> {
+        save_ban(conn, &ban)?;
+        self.entries.retain(|b| b.node_id != ban.node_id);
+        self.entries.push(ban);
+        Ok(())
+    }
+
+    /// 按 node id 或地址解除封禁，返回解除的条数
+    pub fn remove(&mut self, conn: &Connection, target: &str) -> Result<usize> {
+        let removed = remove_ban(conn, target)?;
+        self.entries
+            .retain(|ban| ban.node_id != target && !ban.matches_addr(target));
+        Ok(removed)
+    }
+}
+
+fn init_ban_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS bans (
+            node_id TEXT PRIMARY KEY,
+            addr TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            banned_at INTEGER NOT NULL,
+            banned_until INTEGER NOT NULL
+        );",
+    )
+}
+
+pub fn save_ban(conn: &Connection, ban: &BanEntry) -> Result<()> {
+    init_ban_table(conn)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO bans (node_id, addr, reason, banned_at, banned_until)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        (
+            &ban.node_id,
+            &ban.addr,
+            &ban.reason,
+            ban.banned_at,
+            ban.banned_until.unwrap_or(0),
+        ),
+    )?;
+    Ok(())
+}
+
+/// 读取有效的封禁并删除已过期的记录，永久封禁不会过期
+pub fn load_bans(conn: &Connection, now: u64) -> Result<Vec<BanEntry>> {
+    init_ban_table(conn)?;
+    conn.execute(
+        "DELETE FROM bans WHERE banned_until != 0 AND banned_until <= ?1",
+        (now,),
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT node_id, addr, reason, banned_at, banned_until FROM bans ORDER BY banned_at",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let until: u64 = row.get(4)?;
+        Ok(BanEntry {
+            node_id: row.get(0)?,
+            addr: row.get(1)?,
+            reason: row.get(2)?,
+            banned_at: row.get(3)?,
+            banned_until: (until != 0).then_some(until),
+        })
+    })?;
+    rows.collect()
+}
+
+/// 按 node id 或地址解除封禁，返回解除的条数；地址按主机部分匹配
+pub fn remove_ban(conn: &Connection, target: &str) -> Result<usize> {
+    init_ban_table(conn)?;
+    conn.execute(
+        "DELETE FROM bans WHERE node_id = ?1 OR addr = ?1 OR (addr != '' AND addr = ?2)",
+        (target, ban_host(target)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_records_merge_back_without_duplicates() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut running = PeerManager::default();
+        assert!(running.add_peer("10.0.0.1:8000".to_string(), PeerSource::Seed));
+        running.record_success("10.0.0.1:8000", "node-a", 42);
+        save_records(&conn, running.records()).unwrap();
+
+        // 另一个进程（例如 CLI）写入的新地址
+        let mut cli = PeerManager::default();
+        cli.add_peer("10.0.0.2:8000".to_string(), PeerSource::Manual);
+        cli.save_to_db(&conn).unwrap();
+
+        let stored = PeerManager::load_from_db(&conn).unwrap();
+        assert_eq!(running.merge(stored), 1);
+        assert_eq!(running.list().len(), 2);
+        assert_eq!(running.get("10.0.0.1:8000").unwrap().last_seen, 42);
+    }
+
+    fn ban(node_id: &str, addr: &str, until: Option<u64>) -> BanEntry {
+        BanEntry {
+            node_id: node_id.to_string(),
+            addr: addr.to_string(),
+            reason: "test".to_string(),
+            banned_at: 10,
+            banned_until: until,
+        }
+    }
+
+    #[test]
+    fn bans_match_by_ip_regardless_of_port() {
+        let entry = ban("node-a", "10.0.0.1", Some(100));
+        assert!(entry.matches("other", "10.0.0.1:53211"));
+        assert!(entry.matches("node-a", ""));
+        assert!(!entry.matches("other", "10.0.0.2:8000"));
+        assert!(ban("node-b", "[::1]:8000", None).matches_addr("[::1]:9000"));
+        assert!(!ban("node-c", "", None).matches_addr("10.0.0.1:8000"));
+    }
+
+    #[test]
+    fn permanent_bans_survive_reload_and_timed_bans_expire() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut bans = BanList::default();
+        bans.insert(&conn, ban("forever", "10.0.0.1", None))
+            .unwrap();
+        bans.insert(&conn, ban("brief", "10.0.0.2", Some(50)))
+            .unwrap();
+        assert!(bans.is_banned("brief", "", 49));
+        assert!(!bans.is_banned("brief", "", 50));
+
+        let reloaded = BanList::load(&conn, 1_000_000).unwrap();
+        let active = reloaded.active(1_000_000);
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].node_id, "forever");
+        assert_eq!(active[0].banned_until, None);
+        assert!(reloaded.is_banned("x", "10.0.0.1:8000", 1_000_000));
+    }
+
+    #[test]
+    fn unban_by_address_clears_cache_and_store() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut bans = BanList::default();
+        bans.insert(&conn, ban("node-a", "10.0.0.1", None)).unwrap();
+        assert_eq!(bans.remove(&conn, "10.0.0.1:8000").unwrap(), 1);
+        assert!(!bans.is_banned("node-a", "10.0.0.1:8000", 0));
+        assert!(load_bans(&conn, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn own_address_and_loopback_aliases_are_rejected() {
+        let mut peers = PeerManager::default();
+        peers.add_peer("127.0.0.1:8000".to_string(), PeerSource::Manual);
+        let own = peers.set_local_addrs(&["10.0.0.1:8000".to_string()]);
+        assert_eq!(own, vec!["127.0.0.1:8000".to_string()]);
+        for addr in [
+            "10.0.0.1:8000",
+            "localhost:8000",
+            "[::1]:8000",
+            "127.0.0.1:8000",
+        ] {
+            assert!(
+                !peers.add_peer(addr.to_string(), PeerSource::Manual),
+                "{}",
+                addr
+            );
+        }
+        assert!(peers.add_peer("127.0.0.1:8001".to_string(), PeerSource::Manual));
+        assert!(peers.add_peer("10.0.0.2:8000".to_string(), PeerSource::Manual));
+    }
+
+    #[test]
+    fn prune_drops_dead_peers_but_keeps_manual_ones() {
+        let mut peers = PeerManager::default();
+        for (addr, source) in [
+            ("10.0.0.1:8000", PeerSource::Exchange),
+            ("10.0.0.2:8000", PeerSource::Manual),
+            ("10.0.0.3:8000", PeerSource::Exchange),
+        ] {
+            peers.add_peer(addr.to_string(), source);
+        }
+        for _ in 0..MAX_FAILURES {
+            peers.record_failure("10.0.0.1:8000");
+            peers.record_failure("10.0.0.2:8000");
+        }
+        let now = now_secs();
+        assert!(peers.prune(now).is_empty(), "failures alone are not enough");
+        let removed = peers.prune(now + PRUNE_AFTER_SECS + 1);
+        assert_eq!(removed, vec!["10.0.0.1:8000".to_string()]);
+        assert_eq!(peers.list().len(), 2);
+    }
+
+    #[test]
+    fn prune_evicts_worst_peers_over_capacity() {
+        let mut peers = PeerManager::default();
+        for i in 0..=MAX_KNOWN_PEERS {
+            peers.add_peer(
+                format!("10.{}.{}.1:8000", i / 256, i % 256),
+                PeerSource::Exchange,
+            );
+        }
+        peers.record_failure("10.0.7.1:8000");
+        assert_eq!(peers.prune(0), vec!["10.0.7.1:8000".to_string()]);
+        assert_eq!(peers.list().len(), MAX_KNOWN_PEERS);
+    }
+
+    #[test]
+    fn preferred_orders_by_score_failures_and_recency() {
+        let mut peers = PeerManager::default();
+        for i in 1..=4 {
+            peers.add_peer(format!("10.0.0.{}:8000", i), PeerSource::Seed);
+        }
+        peers.record_success("10.0.0.1:8000", "bad", 50);
+        peers.record_success("10.0.0.2:8000", "old", 10);
+        peers.record_success("10.0.0.3:8000", "new", 20);
+        peers.record_failure("10.0.0.4:8000");
+        let order = peers.preferred(|node_id| if node_id == "bad" { 30 } else { 0 });
+        assert_eq!(
+            order,
+            [
+                "10.0.0.3:8000",
+                "10.0.0.2:8000",
+                "10.0.0.4:8000",
+                "10.0.0.1:8000"
+            ]
+        );
+    }
+
+    #[test]
+    fn peer_stats_survive_save_and_load() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut peers = PeerManager::default();
+        peers.add_peer("10.0.0.1:8000".to_string(), PeerSource::Seed);
+        peers.record_success("10.0.0.1:8000", "node-a", 42);
+        peers.record_failure("10.0.0.1:8000");
+        peers.save_to_db(&conn).unwrap();
+        let loaded = PeerManager::load_from_db(&conn).unwrap();
+        let record = loaded.get("10.0.0.1:8000").unwrap();
+        assert_eq!(
+            (
+                record.node_id.as_str(),
+                record.last_seen,
+                record.failures,
+                record.source
+            ),
+            ("node-a", 42, 1, PeerSource::Seed)
+        );
+    }
+
+    #[test]
+    fn peer_addresses_need_a_usable_host_and_port() {
+        for addr in [
+            "10.0.0.1:8000",
+            "[2001:db8::1]:8000",
+            "node-1.example.org:30303",
+        ] {
+            assert!(is_valid_peer_addr(addr), "{}", addr);
+        }
+        for addr in [
+            "10.0.0.1",
+            "10.0.0.1:0",
+            "0.0.0.0:8000",
+            "224.0.0.1:8000",
+            "255.255.255.255:8000",
+            "[::]:8000",
+            ":8000",
+            "bad host:8000",
+        ] {
+            assert!(!is_valid_peer_addr(addr), "{}", addr);
+        }
+    }
+
+    #[test]
+    fn shared_addresses_are_reachable_ones_only() {
+        let mut peers = PeerManager::default();
+        for i in 1..=3 {
+            peers.add_peer(format!("10.0.0.{}:8000", i), PeerSource::Exchange);
         }
-        Ok(PeerManager { peers })
+        peers.record_success("10.0.0.1:8000", "a", 10);
+        peers.record_success("10.0.0.2:8000", "b", 10);
+        peers.record_failure("10.0.0.2:8000");
+        assert_eq!(peers.sample_reachable(8), vec!["10.0.0.1:8000".to_string()]);
+        assert!(peers.sample_reachable(0).is_empty());
     }
 }
@@ -0,0 +1,495 @@
+use crate::block::block::{Block, BlockHeader};
+use crate::compact::{BlockTxs, BlockTxsRequest, CompactBlock};
+use crate::light::{AccountProof, TxProof};
+use crate::transaction::Transaction;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// 帧格式: | magic(4) | version(1) | type(1) | length(4, 大端) | payload(JSON) |
+pub const MAGIC: [u8; 4] = *b"APOS";
+pub const PROTOCOL_VERSION: u8 = 6;
+/// 能够互通的最低协议版本，v5 及更早的节点不认识 GetBlock
+pub const MIN_PROTOCOL_VERSION: u8 = 6;
+pub const HEADER_LEN: usize = 10;
+pub const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+    PeersRequest = 1,
+    PeersResponse = 2,
+    Transaction = 3,
+    Block = 4,
+    Handshake = 5,
+    Disconnect = 6,
+    Ping = 7,
+    Pong = 8,
+    GetHeaders = 9,
+    Headers = 10,
+    GetBlocks = 11,
+    Blocks = 12,
+    Inventory = 13,
+    GetData = 14,
+    GetTxProof = 15,
+    TxProof = 16,
+    GetAccountProof = 17,
+    AccountProof = 18,
+    CompactBlock = 19,
+    GetBlockTxs = 20,
+    BlockTxs = 21,
+    GetBlock = 22,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = ProtocolError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(MessageType::PeersRequest),
+            2 => Ok(MessageType::PeersResponse),
+            3 => Ok(MessageType::Transaction),
+            4 => Ok(MessageType::Block),
+            5 => Ok(MessageType::Handshake),
+            6 => Ok(MessageType::Disconnect),
+            7 => Ok(MessageType::Ping),
+            8 => Ok(MessageType::Pong),
+            9 => Ok(MessageType::GetHeaders),
+            10 => Ok(MessageType::Headers),
+            11 => Ok(MessageType::GetBlocks),
+            12 => Ok(MessageType::Blocks),
+            13 => Ok(MessageType::Inventory),
+            14 => Ok(MessageType::GetData),
+            15 => Ok(MessageType::GetTxProof),
+            16 => Ok(MessageType::TxProof),
+            17 => Ok(MessageType::GetAccountProof),
+            18 => Ok(MessageType::AccountProof),
+            19 => Ok(MessageType::CompactBlock),
+            20 => Ok(MessageType::GetBlockTxs),
+            21 => Ok(MessageType::BlockTxs),
+            22 => Ok(MessageType::GetBlock),
+            other => Err(ProtocolError::UnknownType(other)),
+        }
+    }
+}
+
+/// 每条连接建立后双方首先交换的节点信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handshake {
+    pub protocol_version: u8,
+    pub chain_id: String,
+    pub genesis_hash: String,
+    pub node_id: String,
+    /// 对端可被连接的地址，CLI 等不监听端口的客户端为空
+    pub listen_addr: String,
+    pub best_height: u64,
+    pub best_hash: String,
+}
+
+impl Handshake {
+    /// 检查对端是否与本节点处于同一条链、协议版本是否兼容
+    pub fn check_compatible(&self, remote: &Handshake) -> Result<(), String> {
+        if remote.protocol_version < MIN_PROTOCOL_VERSION {
+            return Err(format!(
+                "protocol version {} is older than {}",
+                remote.protocol_version, MIN_PROTOCOL_VERSION
+            ));
+        }
+        if remote.chain_id != self.chain_id {
+            return Err(format!(
+                "chain id mismatch: expected {}, got {}",
+                self.chain_id, remote.chain_id
+            ));
+        }
+        if remote.genesis_hash != self.genesis_hash {
+            return Err(format!(
+                "genesis hash mismatch: expected {}, got {}",
+                self.genesis_hash, remote.genesis_hash
+            ));
+        }
+        if remote.node_id == self.node_id {
+            return Err("connected to self".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 按高度请求一段连续的区块头或区块
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BlockRange {
+    pub start: u64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvKind {
+    Tx,
+    Block,
+}
+
+/// 按哈希公告的交易或区块，对端缺少时通过 GetData 拉取完整内容
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct InvItem {
+    pub kind: InvKind,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    PeersRequest,
+    PeersResponse(Vec<String>),
+    Transaction(Transaction),
+    Block(Block),
+    Handshake(Handshake),
+    /// 断开连接前告知对端原因
+    Disconnect(String),
+    /// 会话保活，对端以相同的随机数回复 Pong
+    Ping(u64),
+    Pong(u64),
+    GetHeaders(BlockRange),
+    /// 从请求起点开始的连续区块头，对端没有的部分被截断
+    Headers(Vec<BlockHeader>),
+    GetBlocks(BlockRange),
+    Blocks(Vec<Block>),
+    Inventory(Vec<InvItem>),
+    /// 请求对端发送公告过的交易或区块，分别以 Transaction 与 CompactBlock 消息回复
+    GetData(Vec<InvItem>),
+    /// 轻客户端按交易哈希请求包含证明，交易不在链上时回复 None
+    GetTxProof(String),
+    TxProof(Option<TxProof>),
+    /// 轻客户端按地址请求针对链顶状态根的账户证明
+    GetAccountProof(String),
+    AccountProof(Option<AccountProof>),
+    /// 区块头加交易短 id，接收方用 mempool 还原区块
+    CompactBlock(CompactBlock),
+    /// 请求还原压缩区块时缺少的交易，以 BlockTxs 回复
+    GetBlockTxs(BlockTxsRequest),
+    BlockTxs(BlockTxs),
+    /// 压缩区块无法还原时按哈希请求完整区块，以 Block 回复
+    GetBlock(String),
+}
+
+impl Message {
+    pub fn kind(&self) -> MessageType {
+        match self {
+            Message::PeersRequest => MessageType::PeersRequest,
+            Message::PeersResponse(_) => MessageType::PeersResponse,
+            Message::Transaction(_) => MessageType::Transaction,
+            Message::Block(_) => MessageType::Block,
+            Message::Handshake(_) => MessageType::Handshake,
+            Message::Disconnect(_) => MessageType::Disconnect,
+            Message::Ping(_) => MessageType::Ping,
+            Message::Pong(_) => MessageType::Pong,
+            Message::GetHeaders(_) => MessageType::GetHeaders,
+            Message::Headers(_) => MessageType::Headers,
+            Message::GetBlocks(_) => MessageType::GetBlocks,
+            Message::Blocks(_) => MessageType::Blocks,
+            Message::Inventory(_) => MessageType::Inventory,
+            Message::GetData(_) => MessageType::GetData,
+            Message::GetTxProof(_) => MessageType::GetTxProof,
+            Message::TxProof(_) => MessageType::TxProof,
+            Message::GetAccountProof(_) => MessageType::GetAccountProof,
+            Message::AccountProof(_) => MessageType::AccountProof,
+            Message::CompactBlock(_) => MessageType::CompactBlock,
+            Message::GetBlockTxs(_) => MessageType::GetBlockTxs,
+            Message::BlockTxs(_) => MessageType::BlockTxs,
+            Message::GetBlock(_) => MessageType::GetBlock,
+        }
+    }
+
+    fn payload(&self) -> serde_json::Result<Vec<u8>> {
+        match self {
+            Message::PeersRequest => Ok(b"null".to_vec()),
+            Message::PeersResponse(peers) => serde_json::to_vec(peers),
+            Message::Transaction(tx) => serde_json::to_vec(tx),
+            Message::Block(block) => serde_json::to_vec(block),
+            Message::Handshake(hello) => serde_json::to_vec(hello),
+            Message::Disconnect(reason) => serde_json::to_vec(reason),
+            Message::Ping(nonce) | Message::Pong(nonce) => serde_json::to_vec(nonce),
+            Message::GetHeaders(range) | Message::GetBlocks(range) => serde_json::to_vec(range),
+            Message::Headers(headers) => serde_json::to_vec(headers),
+            Message::Blocks(blocks) => serde_json::to_vec(blocks),
+            Message::Inventory(items) | Message::GetData(items) => serde_json::to_vec(items),
+            Message::GetTxProof(key) | Message::GetAccountProof(key) | Message::GetBlock(key) => {
+                serde_json::to_vec(key)
+            }
+            Message::TxProof(proof) => serde_json::to_vec(proof),
+            Message::AccountProof(proof) => serde_json::to_vec(proof),
+            Message::CompactBlock(block) => serde_json::to_vec(block),
+            Message::GetBlockTxs(request) => serde_json::to_vec(request),
+            Message::BlockTxs(txs) => serde_json::to_vec(txs),
+        }
+    }
+
+    fn from_payload(kind: MessageType, payload: &[u8]) -> Result<Self, ProtocolError> {
+        Ok(match kind {
+            MessageType::PeersRequest => Message::PeersRequest,
+            MessageType::PeersResponse => Message::PeersResponse(parse(payload)?),
+            MessageType::Transaction => Message::Transaction(parse(payload)?),
+            MessageType::Block => Message::Block(parse(payload)?),
+            MessageType::Handshake => Message::Handshake(parse(payload)?),
+            MessageType::Disconnect => Message::Disconnect(parse(payload)?),
+            MessageType::Ping => Message::Ping(parse(payload)?),
+            MessageType::Pong => Message::Pong(parse(payload)?),
+            MessageType::GetHeaders => Message::GetHeaders(parse(payload)?),
+            MessageType::Headers => Message::Headers(parse(payload)?),
+            MessageType::GetBlocks => Message::GetBlocks(parse(payload)?),
+            MessageType::Blocks => Message::Blocks(parse(payload)?),
+            MessageType::Inventory => Message::Inventory(parse(payload)?),
+            MessageType::GetData => Message::GetData(parse(payload)?),
+            MessageType::GetTxProof => Message::GetTxProof(parse(payload)?),
+            MessageType::TxProof => Message::TxProof(parse(payload)?),
+            MessageType::GetAccountProof => Message::GetAccountProof(parse(payload)?),
+            MessageType::AccountProof => Message::AccountProof(parse(payload)?),
+            MessageType::CompactBlock => Message::CompactBlock(parse(payload)?),
+            MessageType::GetBlockTxs => Message::GetBlockTxs(parse(payload)?),
+            MessageType::BlockTxs => Message::BlockTxs(parse(payload)?),
+            MessageType::GetBlock => Message::GetBlock(parse(payload)?),
+        })
+    }
+}
+
+fn parse<T: DeserializeOwned>(payload: &[u8]) -> Result<T, ProtocolError> {
+    serde_json::from_slice(payload).map_err(|e| ProtocolError::Malformed(e.to_string()))
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownType(u8),
+    TooLarge(usize),
+    Malformed(String),
+    Handshake(String),
+    /// Noise 握手或加解密失败
+    Noise(String),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(f, "io error: {}", e),
+            ProtocolError::BadMagic => write!(f, "bad frame magic"),
+            ProtocolError::UnsupportedVersion(v) => write!(f, "unsupported protocol version {}", v),
+            ProtocolError::UnknownType(t) => write!(f, "unknown message type {}", t),
+            ProtocolError::TooLarge(n) => {
+                write!(
+                    f,
+                    "message of {} bytes exceeds limit {}",
+                    n, MAX_MESSAGE_SIZE
+                )
+            }
+            ProtocolError::Malformed(e) => write!(f, "malformed payload: {}", e),
+            ProtocolError::Handshake(reason) => write!(f, "handshake failed: {}", reason),
+            ProtocolError::Noise(e) => write!(f, "noise error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<std::io::Error> for ProtocolError {
+    fn from(e: std::io::Error) -> Self {
+        ProtocolError::Io(e)
+    }
+}
+
+impl From<snow::Error> for ProtocolError {
+    fn from(e: snow::Error) -> Self {
+        ProtocolError::Noise(e.to_string())
+    }
+}
+
+pub fn encode(msg: &Message) -> Result<Vec<u8>, ProtocolError> {
+    let payload = msg
+        .payload()
+        .map_err(|e| ProtocolError::Malformed(e.to_string()))?;
+    if payload.len() > MAX_MESSAGE_SIZE {
+        return Err(ProtocolError::TooLarge(payload.len()));
+    }
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&MAGIC);
+    frame.push(PROTOCOL_VERSION);
+    frame.push(msg.kind() as u8);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// 校验帧头，返回消息类型与 payload 长度
+pub fn decode_header(header: &[u8; HEADER_LEN]) -> Result<(MessageType, usize), ProtocolError> {
+    if header[..4] != MAGIC {
+        return Err(ProtocolError::BadMagic);
+    }
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&header[4]) {
+        return Err(ProtocolError::UnsupportedVersion(header[4]));
+    }
+    let kind = MessageType::try_from(header[5])?;
+    let len = u32::from_be_bytes([header[6], header[7], header[8], header[9]]) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(ProtocolError::TooLarge(len));
+    }
+    Ok((kind, len))
+}
+
+/// 解析一个完整的帧
+pub fn decode(frame: &[u8]) -> Result<Message, ProtocolError> {
+    if frame.len() < HEADER_LEN {
+        return Err(ProtocolError::Malformed("truncated frame".to_string()));
+    }
+    let header: [u8; HEADER_LEN] = frame[..HEADER_LEN].try_into().unwrap();
+    let (kind, len) = decode_header(&header)?;
+    if frame.len() != HEADER_LEN + len {
+        return Err(ProtocolError::Malformed(format!(
+            "frame length {} does not match header {}",
+            frame.len() - HEADER_LEN,
+            len
+        )));
+    }
+    Message::from_payload(kind, &frame[HEADER_LEN..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+
+    fn round_trip(msg: &Message) -> Message {
+        decode(&encode(msg).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let tx = Transaction::signed(&keys::dev_key("admin"), "b", 10, 1, 0);
+        let messages = [
+            Message::PeersRequest,
+            Message::PeersResponse(vec!["127.0.0.1:8001".to_string()]),
+            Message::Transaction(tx),
+            Message::Ping(42),
+            Message::GetHeaders(BlockRange { start: 3, count: 7 }),
+            Message::Inventory(vec![InvItem {
+                kind: InvKind::Block,
+                hash: "ab".repeat(32),
+            }]),
+            Message::Disconnect("bye".to_string()),
+            Message::GetBlock("cd".repeat(32)),
+        ];
+        for msg in &messages {
+            let decoded = round_trip(msg);
+            assert_eq!(decoded.kind(), msg.kind());
+            assert_eq!(encode(&decoded).unwrap(), encode(msg).unwrap());
+        }
+        match round_trip(&messages[3]) {
+            Message::Ping(n) => assert_eq!(n, 42),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn frame_header_layout() {
+        let frame = encode(&Message::Pong(7)).unwrap();
+        assert_eq!(frame[..4], MAGIC);
+        assert_eq!(frame[4], PROTOCOL_VERSION);
+        assert_eq!(frame[5], MessageType::Pong as u8);
+        let len = u32::from_be_bytes(frame[6..10].try_into().unwrap()) as usize;
+        assert_eq!(len, frame.len() - HEADER_LEN);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let frame = encode(&Message::Ping(1)).unwrap();
+        let header = |edit: fn(&mut [u8; HEADER_LEN])| {
+            let mut h: [u8; HEADER_LEN] = frame[..HEADER_LEN].try_into().unwrap();
+            edit(&mut h);
+            decode_header(&h)
+        };
+        assert!(matches!(
+            header(|h| h[0] = b'X'),
+            Err(ProtocolError::BadMagic)
+        ));
+        assert!(matches!(
+            header(|h| h[4] = MIN_PROTOCOL_VERSION - 1),
+            Err(ProtocolError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            header(|h| h[5] = 0),
+            Err(ProtocolError::UnknownType(0))
+        ));
+        assert!(matches!(
+            header(|h| h[6..10].copy_from_slice(&(MAX_MESSAGE_SIZE as u32 + 1).to_be_bytes())),
+            Err(ProtocolError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_or_padded_frames() {
+        let frame = encode(&Message::Ping(1)).unwrap();
+        assert!(matches!(
+            decode(&frame[..HEADER_LEN - 1]),
+            Err(ProtocolError::Malformed(_))
+        ));
+        assert!(matches!(
+            decode(&frame[..frame.len() - 1]),
+            Err(ProtocolError::Malformed(_))
+        ));
+        let mut padded = frame.clone();
+        padded.push(0);
+        assert!(matches!(decode(&padded), Err(ProtocolError::Malformed(_))));
+    }
+
+    #[test]
+    fn rejects_payload_of_wrong_shape() {
+        let mut frame = encode(&Message::Ping(1)).unwrap();
+        frame[5] = MessageType::GetHeaders as u8;
+        assert!(matches!(decode(&frame), Err(ProtocolError::Malformed(_))));
+    }
+
+    fn hello(node_id: &str) -> Handshake {
+        Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            chain_id: "apos-dev".to_string(),
+            genesis_hash: "00".repeat(32),
+            node_id: node_id.to_string(),
+            listen_addr: String::new(),
+            best_height: 0,
+            best_hash: String::new(),
+        }
+    }
+
+    #[test]
+    fn handshake_requires_same_chain_and_compatible_version() {
+        let local = hello("local");
+        local.check_compatible(&hello("remote")).unwrap();
+
+        let mut old = hello("remote");
+        old.protocol_version = MIN_PROTOCOL_VERSION - 1;
+        let mut other_chain = hello("remote");
+        other_chain.chain_id = "other".to_string();
+        let mut other_genesis = hello("remote");
+        other_genesis.genesis_hash = "11".repeat(32);
+        for (remote, reason) in [
+            (old, "protocol version"),
+            (other_chain, "chain id mismatch"),
+            (other_genesis, "genesis hash mismatch"),
+            (hello("local"), "connected to self"),
+        ] {
+            let err = local.check_compatible(&remote).unwrap_err();
+            assert!(err.contains(reason), "{}", err);
+        }
+    }
+
+    #[test]
+    fn handshake_round_trips_as_a_frame() {
+        let mut local = hello("local");
+        local.best_height = 9;
+        match round_trip(&Message::Handshake(local)) {
+            Message::Handshake(h) => {
+                assert_eq!(h.node_id, "local");
+                assert_eq!(h.best_height, 9);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// accept 出错（如文件描述符耗尽）后暂停接受新连接的时间
+pub const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// 入站连接准入：限制同时存在的连接总数与单个 IP 的连接数
+pub struct ConnectionLimiter {
+    slots: Arc<Semaphore>,
+    per_ip: Mutex<HashMap<IpAddr, usize>>,
+    max_per_ip: usize,
+}
+
+/// 连接占用的名额，连接结束时随之释放
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+    _slot: OwnedSemaphorePermit,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_connections: usize, max_per_ip: usize) -> Arc<Self> {
+        Arc::new(ConnectionLimiter {
+            slots: Arc::new(Semaphore::new(max_connections)),
+            per_ip: Mutex::new(HashMap::new()),
+            max_per_ip,
+        })
+    }
+
+    /// 为来自 `ip` 的连接申请名额，总数或该 IP 的连接数已满时返回原因
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Result<ConnectionPermit, &'static str> {
+        let slot = Arc::clone(&self.slots)
+            .try_acquire_owned()
+            .map_err(|_| "too many connections")?;
+        let mut per_ip = self.per_ip.lock().unwrap();
+        let count = per_ip.entry(ip).or_insert(0);
+        if *count >= self.max_per_ip {
+            return Err("too many connections from this address");
+        }
+        *count += 1;
+        Ok(ConnectionPermit {
+            limiter: Arc::clone(self),
+            ip,
+            _slot: slot,
+        })
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut per_ip = self.limiter.per_ip.lock().unwrap();
+        if let Some(count) = per_ip.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// 令牌桶：以固定速率补充令牌，允许不超过容量的突发
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, refill_per_sec: u32) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            refill_per_sec: refill_per_sec as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// 消耗一个令牌，桶已空时返回 false
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    /// 以 `now` 为当前时间补充并消耗令牌，时间早于上次补充时不补充
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = self.last_refill.max(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last])
+    }
+
+    /// 在桶创建 `secs` 秒后的时刻取完所有令牌，时间只向后推进，不依赖系统时钟
+    fn drain_at(bucket: &mut TokenBucket, start: Instant, secs: u64) -> usize {
+        let now = start + Duration::from_secs(secs);
+        std::iter::from_fn(|| bucket.try_take_at(now).then_some(())).count()
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_then_refills_at_the_rate() {
+        let mut bucket = TokenBucket::new(5, 2);
+        let start = bucket.last_refill;
+        assert_eq!(drain_at(&mut bucket, start, 0), 5);
+        assert_eq!(drain_at(&mut bucket, start, 1), 2);
+        // 长时间空闲后最多恢复到容量
+        assert_eq!(drain_at(&mut bucket, start, 61), 5);
+        // 时间回退时不补充令牌
+        assert_eq!(drain_at(&mut bucket, start, 30), 0);
+    }
+
+    #[test]
+    fn limiter_caps_per_ip_and_total_connections() {
+        let limiter = ConnectionLimiter::new(3, 2);
+        let a1 = limiter.try_acquire(ip(1)).unwrap();
+        let _a2 = limiter.try_acquire(ip(1)).unwrap();
+        assert_eq!(
+            limiter.try_acquire(ip(1)).err(),
+            Some("too many connections from this address")
+        );
+        let _b1 = limiter.try_acquire(ip(2)).unwrap();
+        assert_eq!(
+            limiter.try_acquire(ip(3)).err(),
+            Some("too many connections")
+        );
+        // 连接结束后名额与该 IP 的计数一起释放
+        drop(a1);
+        let _c1 = limiter.try_acquire(ip(3)).unwrap();
+        assert_eq!(limiter.per_ip.lock().unwrap().get(&ip(1)), Some(&1));
+    }
+}
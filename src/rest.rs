@@ -0,0 +1,162 @@
+use crate::http::{self, HttpResponse, HttpState};
+use crate::openrpc::Schema;
+use crate::rpc::{self, Access, RpcError};
+use hyper::body::Incoming;
+use hyper::{Method, Request, StatusCode};
+use serde_json::{json, Map, Value};
+
+/// REST 资源到 RPC 方法的映射：路径与查询参数转换为命名参数后交给同一套 RPC 处理函数
+pub async fn route(req: Request<Incoming>, state: &HttpState) -> HttpResponse {
+    let path = req.uri().path().trim_matches('/').to_string();
+    let segments: Vec<String> = path.split('/').map(percent_decode).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let (allow, method, path_params) = match segments.as_slice() {
+        ["blocks"] => (Method::GET, "get_blocks", json!({})),
+        ["blocks", "latest"] => (Method::GET, "get_latest_block", json!({})),
+        ["blocks", id] => match id.parse::<u64>() {
+            Ok(height) => (Method::GET, "get_block", json!({ "height": height })),
+            Err(_) => (Method::GET, "get_block_by_hash", json!({ "hash": id })),
+        },
+        ["tx"] => (Method::POST, "send_transaction", json!({})),
+        ["tx", hash] => (Method::GET, "get_transaction", json!({ "hash": hash })),
+        ["accounts", address] => (Method::GET, "get_account", json!({ "address": address })),
+        ["mempool"] => (Method::GET, "get_mempool", json!({})),
+        ["validators"] => (Method::GET, "get_validators", json!({})),
+        ["peers"] => (Method::GET, "get_peers", json!({})),
+        ["sync"] => (Method::GET, "sync_status", json!({})),
+        _ => return http::error(StatusCode::NOT_FOUND, "not found"),
+    };
+    if req.method() != allow {
+        return http::method_not_allowed(if allow == Method::POST {
+            "POST, OPTIONS"
+        } else {
+            "GET, OPTIONS"
+        });
+    }
+    let Some(mut params) = query_params(req.uri().query(), method) else {
+        return http::error(StatusCode::BAD_REQUEST, "malformed query string");
+    };
+    if allow == Method::POST {
+        let body = match http::read_body(req, state.config.max_body_bytes).await {
+            Ok(body) => body,
+            Err(resp) => return resp,
+        };
+        // POST /tx 的请求体就是已签名的交易
+        match serde_json::from_slice::<Value>(&body) {
+            Ok(tx @ Value::Object(_)) => {
+                params.insert("transaction".to_string(), tx);
+            }
+            _ => return http::error(StatusCode::BAD_REQUEST, "body must be a JSON object"),
+        }
+    }
+    if let Value::Object(fields) = path_params {
+        params.extend(fields);
+    }
+    match rpc::call(
+        method,
+        Some(&Value::Object(params)),
+        state.node.as_ref(),
+        Access::Public,
+    ) {
+        Ok(Value::Null) => http::error(StatusCode::NOT_FOUND, "not found"),
+        Ok(result) if allow == Method::POST => http::json_response(StatusCode::ACCEPTED, &result),
+        Ok(result) => http::json_response(StatusCode::OK, &result),
+        Err(e) => error_response(e),
+    }
+}
+
+fn error_response(error: RpcError) -> HttpResponse {
+    let status = match error.code {
+        rpc::INVALID_PARAMS | rpc::INVALID_REQUEST => StatusCode::BAD_REQUEST,
+        rpc::TX_REJECTED => StatusCode::UNPROCESSABLE_ENTITY,
+        rpc::NODE_NOT_RUNNING => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    http::json_response(
+        status,
+        &json!({"error": error.message, "code": error.code, "data": error.data}),
+    )
+}
+
+/// 按方法声明的参数类型转换查询参数：声明为整数或布尔值的参数按该类型解析，
+/// 其余保持字符串，例如纯数字的地址不会被当成整数
+fn query_params(query: Option<&str>, method: &str) -> Option<Map<String, Value>> {
+    let decls = rpc::find_method(method).map_or(&[][..], |m| m.params);
+    let mut params = Map::new();
+    for pair in query.unwrap_or("").split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=')?;
+        let key = percent_decode(key);
+        let value = percent_decode(&value.replace('+', " "));
+        let schema = decls.iter().find(|d| d.name == key).map(|d| d.schema);
+        params.insert(key, coerce(value, schema));
+    }
+    Some(params)
+}
+
+/// 无法按声明类型解析的值保持字符串，由参数校验报告类型错误
+fn coerce(value: String, schema: Option<Schema>) -> Value {
+    let schema = match schema {
+        Some(Schema::Nullable(inner)) => Some(*inner),
+        other => other,
+    };
+    match schema {
+        Some(Schema::Integer) => value.parse::<u64>().map_or(json!(value), |n| json!(n)),
+        Some(Schema::Number) => value.parse::<f64>().map_or(json!(value), |n| json!(n)),
+        Some(Schema::Boolean) => value.parse::<bool>().map_or(json!(value), |b| json!(b)),
+        _ => json!(value),
+    }
+}
+
+/// 解码 `%XX` 转义，无效的转义原样保留
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| s.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_values_follow_the_method_param_types() {
+        let params = query_params(Some("before=12&limit=5"), "get_blocks").unwrap();
+        assert_eq!(params["before"], json!(12));
+        assert_eq!(params["limit"], json!(5));
+    }
+
+    #[test]
+    fn string_params_stay_strings_even_when_numeric() {
+        let params = query_params(Some("address=1234"), "get_account").unwrap();
+        assert_eq!(params["address"], json!("1234"));
+    }
+
+    #[test]
+    fn unparsable_integers_are_left_for_param_validation() {
+        let params = query_params(Some("limit=ten"), "get_blocks").unwrap();
+        assert_eq!(params["limit"], json!("ten"));
+        assert!(query_params(Some("limit"), "get_blocks").is_none());
+    }
+
+    #[test]
+    fn percent_escapes_are_decoded() {
+        assert_eq!(percent_decode("a%20b%2"), "a b%2");
+    }
+}
@@ -1,87 +1,1057 @@
+use crate::admin::{self, AdminAuth};
+use crate::dry_run::{self, StateView, Transfer};
+use crate::http::{self, HttpConfig, HttpState};
+use crate::network::NetworkContext;
+use crate::openrpc::{self, Field, Param, Schema};
+use crate::ratelimit::{self, ConnectionLimiter};
 use crate::transaction::Transaction;
-use serde_json::json;
-use sha2::{Digest, Sha256};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::ws;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
 use tokio::net::TcpListener;
 
-pub async fn start_jsonrpc_server(port: u16) {
+/// 同时处理的 RPC 连接总数与单个 IP 的连接数上限
+const MAX_RPC_CONNECTIONS: usize = 128;
+const MAX_RPC_CONNECTIONS_PER_IP: usize = 16;
+/// 单个批量请求最多包含的调用数
+const MAX_BATCH_SIZE: usize = 100;
+/// 列表类方法的默认与最大分页大小
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+
+// JSON-RPC 2.0 规定的错误码，-32000 起为本节点定义的应用错误
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+pub const NODE_NOT_RUNNING: i64 = -32000;
+pub const TX_REJECTED: i64 = -32001;
+pub const WEBSOCKET_REQUIRED: i64 = -32002;
+pub const TOO_MANY_SUBSCRIPTIONS: i64 = -32003;
+pub const UNAUTHORIZED: i64 = -32004;
+
+/// JSON-RPC 错误对象
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    fn invalid_request(detail: &str) -> Self {
+        RpcError::new(INVALID_REQUEST, "invalid request").with_data(json!(detail))
+    }
+
+    pub fn invalid_params(detail: String) -> Self {
+        RpcError::new(INVALID_PARAMS, "invalid params").with_data(json!(detail))
+    }
+
+    pub fn node_not_running() -> Self {
+        RpcError::new(NODE_NOT_RUNNING, "node is not running")
+    }
+}
+
+/// 调用方的权限，`admin_*` 方法只对通过管理凭据认证的请求开放
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Public,
+    Admin,
+}
+
+/// 按方法声明的参数，把位置参数与命名参数统一为位置形式
+pub struct Params {
+    decls: &'static [Param],
+    values: Vec<Value>,
+}
+
+impl Params {
+    pub fn parse(params: Option<&Value>, decls: &'static [Param]) -> Result<Self, RpcError> {
+        let values = match params {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(values)) => {
+                if values.len() > decls.len() {
+                    return Err(RpcError::invalid_params(format!(
+                        "expected at most {} params, got {}",
+                        decls.len(),
+                        values.len()
+                    )));
+                }
+                values.clone()
+            }
+            Some(Value::Object(map)) => {
+                if let Some(unknown) = map
+                    .keys()
+                    .find(|k| !decls.iter().any(|d| d.name == k.as_str()))
+                {
+                    return Err(RpcError::invalid_params(format!(
+                        "unknown param `{}`",
+                        unknown
+                    )));
+                }
+                decls
+                    .iter()
+                    .map(|d| map.get(d.name).cloned().unwrap_or(Value::Null))
+                    .collect()
+            }
+            Some(_) => {
+                return Err(RpcError::invalid_request(
+                    "params must be an array or an object",
+                ))
+            }
+        };
+        let params = Params { decls, values };
+        for (index, decl) in decls.iter().enumerate() {
+            match params.get(index) {
+                None if decl.required => return Err(params.missing(index)),
+                Some(value) if !decl.schema.accepts(value) => {
+                    return Err(params.type_error(index, &decl.schema.describe()))
+                }
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+
+    /// 缺省与 null 都视为未提供
+    fn get(&self, index: usize) -> Option<&Value> {
+        self.values.get(index).filter(|v| !v.is_null())
+    }
+
+    fn required(&self, index: usize) -> Result<&Value, RpcError> {
+        self.get(index).ok_or_else(|| self.missing(index))
+    }
+
+    fn missing(&self, index: usize) -> RpcError {
+        RpcError::invalid_params(format!("missing `{}`", self.decls[index].name))
+    }
+
+    fn type_error(&self, index: usize, expected: &str) -> RpcError {
+        RpcError::invalid_params(format!("`{}` must be {}", self.decls[index].name, expected))
+    }
+
+    pub fn str(&self, index: usize) -> Result<&str, RpcError> {
+        self.required(index)?
+            .as_str()
+            .ok_or_else(|| self.type_error(index, "a string"))
+    }
+
+    pub fn opt_str(&self, index: usize) -> Result<Option<&str>, RpcError> {
+        match self.get(index) {
+            None => Ok(None),
+            Some(v) => v
+                .as_str()
+                .map(Some)
+                .ok_or_else(|| self.type_error(index, "a string")),
+        }
+    }
+
+    pub fn u64(&self, index: usize) -> Result<u64, RpcError> {
+        self.required(index)?
+            .as_u64()
+            .ok_or_else(|| self.type_error(index, "an unsigned integer"))
+    }
+
+    pub fn transaction(&self, index: usize) -> Result<Transaction, RpcError> {
+        serde_json::from_value(self.required(index)?.clone())
+            .map_err(|e| RpcError::invalid_params(format!("`{}`: {}", self.decls[index].name, e)))
+    }
+
+    pub fn opt_bool(&self, index: usize) -> Result<Option<bool>, RpcError> {
+        match self.get(index) {
+            None => Ok(None),
+            Some(v) => v
+                .as_bool()
+                .map(Some)
+                .ok_or_else(|| self.type_error(index, "a boolean")),
+        }
+    }
+
+    pub fn opt_u64(&self, index: usize) -> Result<Option<u64>, RpcError> {
+        match self.get(index) {
+            None => Ok(None),
+            Some(v) => v
+                .as_u64()
+                .map(Some)
+                .ok_or_else(|| self.type_error(index, "an unsigned integer")),
+        }
+    }
+}
+
+pub type Handler = fn(&Params, Option<&NetworkContext>) -> Result<Value, RpcError>;
+
+/// RPC 方法表，处理请求时按 `params` 校验参数，OpenRPC 文档也由它生成
+pub struct Method {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub params: &'static [Param],
+    pub result: Schema,
+    pub handler: Handler,
+}
+
+pub const METHODS: &[Method] = &[
+    Method {
+        name: "send_transaction",
+        summary: "Submit a transaction signed by the sender to the mempool",
+        params: &[Param::required(
+            "transaction",
+            Schema::Ref("SignedTransaction"),
+        )],
+        result: Schema::Object(&[
+            Field::required("status", Schema::Enum(&["ok"])),
+            Field::required("tx_hash", Schema::String),
+            Field::required("nonce", Schema::Integer),
+            Field::required("replaced", Schema::Nullable(&Schema::String)),
+            Field::required("queued", Schema::Boolean),
+        ]),
+        handler: handle_send_transaction,
+    },
+    Method {
+        name: "simulate_transaction",
+        summary: "Execute a transfer against the latest or pending state without submitting it",
+        params: &[
+            Param::required("from", Schema::String),
+            Param::required("to", Schema::String),
+            Param::required("amount", Schema::Integer),
+            Param::optional("fee", Schema::Integer),
+            Param::optional("nonce", Schema::Integer),
+            Param::optional("state", Schema::Enum(dry_run::STATE_VIEWS)),
+            Param::optional("chain_id", Schema::String),
+            Param::optional("public_key", Schema::String),
+            Param::optional("signature", Schema::String),
+        ],
+        result: Schema::Ref("SimulationReceipt"),
+        handler: handle_simulate_transaction,
+    },
+    Method {
+        name: "sync_status",
+        summary: "Block synchronization phase and progress",
+        params: &[],
+        result: Schema::Ref("SyncStatus"),
+        handler: handle_sync_status,
+    },
+    Method {
+        name: "list_bans",
+        summary: "Active peer bans",
+        params: &[],
+        result: Schema::Array(&Schema::Ref("Ban")),
+        handler: handle_list_bans,
+    },
+    Method {
+        name: "get_headers",
+        summary: "Block headers starting at a height, for light clients",
+        params: &[
+            Param::required("start", Schema::Integer),
+            Param::optional("count", Schema::Integer),
+        ],
+        result: Schema::Array(&Schema::Ref("BlockHeader")),
+        handler: handle_get_headers,
+    },
+    Method {
+        name: "get_tx_proof",
+        summary: "Merkle inclusion proof of a confirmed transaction",
+        params: &[Param::required("hash", Schema::String)],
+        result: Schema::Nullable(&Schema::Ref("TxProof")),
+        handler: handle_get_tx_proof,
+    },
+    Method {
+        name: "get_account_proof",
+        summary: "Proof of an account's balance and nonce against the tip state root",
+        params: &[Param::required("address", Schema::String)],
+        result: Schema::Nullable(&Schema::Ref("AccountProof")),
+        handler: handle_get_account_proof,
+    },
+    Method {
+        name: "get_height",
+        summary: "Current chain height",
+        params: &[],
+        result: Schema::Integer,
+        handler: handle_get_height,
+    },
+    Method {
+        name: "get_block",
+        summary: "Block at a height",
+        params: &[Param::required("height", Schema::Integer)],
+        result: Schema::Nullable(&Schema::Ref("Block")),
+        handler: handle_get_block,
+    },
+    Method {
+        name: "get_block_by_hash",
+        summary: "Block with a hash",
+        params: &[Param::required("hash", Schema::String)],
+        result: Schema::Nullable(&Schema::Ref("Block")),
+        handler: handle_get_block_by_hash,
+    },
+    Method {
+        name: "get_blocks",
+        summary: "Page of blocks below `before`, newest first",
+        params: &[
+            Param::optional("before", Schema::Integer),
+            Param::optional("limit", Schema::Integer),
+        ],
+        result: Schema::Object(&[
+            Field::required("blocks", Schema::Array(&Schema::Ref("Block"))),
+            Field::required("next_before", Schema::Nullable(&Schema::Integer)),
+        ]),
+        handler: handle_get_blocks,
+    },
+    Method {
+        name: "get_latest_block",
+        summary: "Chain tip",
+        params: &[],
+        result: Schema::Ref("Block"),
+        handler: handle_get_latest_block,
+    },
+    Method {
+        name: "get_balance",
+        summary: "Account balance in the tip state",
+        params: &[Param::required("address", Schema::String)],
+        result: Schema::Object(&[
+            Field::required("address", Schema::String),
+            Field::required("balance", Schema::Integer),
+        ]),
+        handler: handle_get_balance,
+    },
+    Method {
+        name: "get_nonce",
+        summary: "Confirmed nonce and the next nonce counting pending transactions",
+        params: &[Param::required("address", Schema::String)],
+        result: Schema::Object(&[
+            Field::required("address", Schema::String),
+            Field::required("nonce", Schema::Integer),
+            Field::required("pending_nonce", Schema::Integer),
+        ]),
+        handler: handle_get_nonce,
+    },
+    Method {
+        name: "get_transaction",
+        summary: "Confirmed or pending transaction by hash",
+        params: &[Param::required("hash", Schema::String)],
+        result: Schema::Nullable(&Schema::Ref("TransactionStatus")),
+        handler: handle_get_transaction,
+    },
+    Method {
+        name: "get_account",
+        summary: "Balance with confirmed and pending nonce",
+        params: &[Param::required("address", Schema::String)],
+        result: Schema::Ref("Account"),
+        handler: handle_get_account,
+    },
+    Method {
+        name: "get_mempool",
+        summary: "Page of pending transactions ordered by sender and nonce",
+        params: &[
+            Param::optional("offset", Schema::Integer),
+            Param::optional("limit", Schema::Integer),
+        ],
+        result: Schema::Object(&[
+            Field::required("count", Schema::Integer),
+            Field::required("offset", Schema::Integer),
+            Field::required(
+                "transactions",
+                Schema::Array(&Schema::Ref("PendingTransaction")),
+            ),
+        ]),
+        handler: handle_get_mempool,
+    },
+    Method {
+        name: "get_validators",
+        summary: "Validators ordered by stake",
+        params: &[],
+        result: Schema::Array(&Schema::Object(&[
+            Field::required("address", Schema::String),
+            Field::required("public_key", Schema::String),
+            Field::required("stake", Schema::Integer),
+        ])),
+        handler: handle_get_validators,
+    },
+    Method {
+        name: "get_peers",
+        summary: "Connected sessions and known peers",
+        params: &[],
+        result: Schema::Object(&[
+            Field::required("connected", Schema::Array(&Schema::Ref("PeerInfo"))),
+            Field::required("known", Schema::Array(&Schema::Ref("PeerRecord"))),
+        ]),
+        handler: handle_get_peers,
+    },
+    Method {
+        name: "subscribe",
+        summary: "Subscribe to chain events (WebSocket only); returns the subscription id",
+        params: ws::SUBSCRIBE_PARAMS,
+        result: Schema::String,
+        handler: handle_subscription_over_http,
+    },
+    Method {
+        name: "unsubscribe",
+        summary: "Cancel a subscription (WebSocket only)",
+        params: ws::UNSUBSCRIBE_PARAMS,
+        result: Schema::Boolean,
+        handler: handle_subscription_over_http,
+    },
+    Method {
+        name: "rpc_discover",
+        summary: "OpenRPC document describing every method",
+        params: &[],
+        result: Schema::Any,
+        handler: openrpc::handle_discover,
+    },
+];
+
+/// `node` 为运行中节点的共享状态，独立启动的 RPC 服务为 None；
+/// `admin` 为 None 时该端口不接受管理方法
+pub async fn start_jsonrpc_server(
+    port: u16,
+    node: Option<NetworkContext>,
+    config: HttpConfig,
+    admin: Option<Arc<AdminAuth>>,
+) {
     println!("🚀 启动 JSON-RPC 服务，监听端口 {}", port);
     let listener = TcpListener::bind(("0.0.0.0", port)).await.unwrap();
+    serve(
+        listener,
+        HttpState {
+            node,
+            config,
+            admin,
+        },
+    )
+    .await;
+}
+
+/// 接受连接并逐个交给 HTTP 层处理，主端口与管理端口共用
+pub async fn serve(listener: TcpListener, state: HttpState) {
+    let limiter = ConnectionLimiter::new(MAX_RPC_CONNECTIONS, MAX_RPC_CONNECTIONS_PER_IP);
+    let state = Arc::new(state);
     loop {
-        let (mut socket, _) = listener.accept().await.unwrap();
-        tokio::spawn(async move {
-            let mut buf = [0; 4096];
-            if let Ok(n) = socket.read(&mut buf).await {
-                if let Ok(text) = std::str::from_utf8(&buf[..n]) {
-                    let resp_str = handle_jsonrpc_http(text);
-                    let _ = socket.write_all(resp_str.as_bytes()).await;
-                }
+        let (socket, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("⚠️ [JSON-RPC] 接受连接失败: {}", e);
+                tokio::time::sleep(ratelimit::ACCEPT_ERROR_BACKOFF).await;
+                continue;
             }
+        };
+        // 超过上限的连接直接关闭
+        let Ok(permit) = limiter.try_acquire(peer.ip()) else {
+            continue;
+        };
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            http::serve_connection(socket, state, permit).await;
         });
     }
 }
 
-fn handle_jsonrpc_http(text: &str) -> String {
-    if let Some(body_start) = text.find("\r\n\r\n") {
-        let body = &text[body_start + 4..];
-        let (status, resp) = handle_jsonrpc_body(body);
-        return format!(
-            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-            status,
-            resp.to_string().len(),
-            resp.to_string()
-        );
+pub fn response(id: Value, outcome: Result<Value, RpcError>) -> Value {
+    match outcome {
+        Ok(result) => json!({"jsonrpc":"2.0","result":result,"id":id}),
+        Err(error) => json!({"jsonrpc":"2.0","error":error,"id":id}),
     }
-    let resp = json!({"jsonrpc":"2.0","error":"invalid request","id":null});
-    format!(
-        "HTTP/1.1 400 Bad Request\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-        resp.to_string().len(),
-        resp.to_string()
-    )
 }
 
-fn handle_jsonrpc_body(body: &str) -> (&'static str, serde_json::Value) {
-    match serde_json::from_str::<serde_json::Value>(body) {
-        Ok(req) => {
-            let method = req.get("method").and_then(|m| m.as_str()).unwrap_or("");
-            match method {
-                "send_transaction" => handle_send_transaction(&req),
-                _ => (
-                    "400 Bad Request",
-                    json!({"jsonrpc":"2.0","error":"unknown method","id":req.get("id").cloned().unwrap_or(json!(1))}),
-                ),
-            }
+/// 处理单个或批量请求，应用错误放在响应的 error 对象中；没有需要回复的内容（全部是通知）时返回 None
+pub fn handle_jsonrpc_body(
+    body: &str,
+    node: Option<&NetworkContext>,
+    access: Access,
+) -> Option<Value> {
+    let req: Value = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, "parse error").with_data(json!(e.to_string()));
+            return Some(response(Value::Null, Err(error)));
         }
-        Err(_) => (
-            "400 Bad Request",
-            json!({"jsonrpc":"2.0","error":"invalid request","id":null}),
-        ),
-    }
-}
-
-fn handle_send_transaction(req: &serde_json::Value) -> (&'static str, serde_json::Value) {
-    if let Some(params) = req.get("params").and_then(|p| p.as_array()) {
-        if params.len() == 3 {
-            let from = params[0].as_str().unwrap_or("");
-            let to = params[1].as_str().unwrap_or("");
-            let amount = params[2].as_u64().unwrap_or(0);
-            let _tx = Transaction::new(from, to, amount);
-            println!("[JSON-RPC] 交易提交: {} -> {} [{}]", from, to, amount);
-            // 计算交易hash
-            let tx_str = format!("{}{}{}", from, to, amount);
-            let mut hasher = Sha256::new();
-            hasher.update(tx_str.as_bytes());
-            let tx_hash = format!("0x{:x}", hasher.finalize());
-            let resp = json!({
-                "jsonrpc": "2.0",
-                "result": {"status": "ok", "tx_hash": tx_hash},
-                "id": req.get("id").cloned().unwrap_or(json!(1))
-            });
-            return ("200 OK", resp);
+    };
+    let Value::Array(batch) = req else {
+        return handle_request(req, node, access);
+    };
+    if batch.is_empty() {
+        return Some(response(
+            Value::Null,
+            Err(RpcError::invalid_request("empty batch")),
+        ));
+    }
+    if batch.len() > MAX_BATCH_SIZE {
+        return Some(response(
+            Value::Null,
+            Err(RpcError::invalid_request("batch too large")),
+        ));
+    }
+    let responses: Vec<Value> = batch
+        .into_iter()
+        .filter_map(|req| handle_request(req, node, access))
+        .collect();
+    (!responses.is_empty()).then_some(Value::Array(responses))
+}
+
+/// 没有 id 的合法请求是通知，执行但不回复；格式错误的请求总是回复，id 无法确定时为 null
+fn handle_request(req: Value, node: Option<&NetworkContext>, access: Access) -> Option<Value> {
+    let Value::Object(req) = req else {
+        return Some(response(
+            Value::Null,
+            Err(RpcError::invalid_request("request must be an object")),
+        ));
+    };
+    let id = req.get("id").cloned();
+    if let Some(id) = &id {
+        if !(id.is_string() || id.is_number() || id.is_null()) {
+            return Some(response(
+                Value::Null,
+                Err(RpcError::invalid_request(
+                    "id must be a string, number or null",
+                )),
+            ));
         }
     }
-    (
-        "400 Bad Request",
-        json!({"jsonrpc":"2.0","error":"invalid params","id":req.get("id").cloned().unwrap_or(json!(1))}),
-    )
+    if req.get("jsonrpc").and_then(|v| v.as_str()) != Some("2.0") {
+        return Some(response(
+            id.unwrap_or(Value::Null),
+            Err(RpcError::invalid_request("jsonrpc must be \"2.0\"")),
+        ));
+    }
+    let Some(method) = req.get("method").and_then(|m| m.as_str()) else {
+        return Some(response(
+            id.unwrap_or(Value::Null),
+            Err(RpcError::invalid_request("method must be a string")),
+        ));
+    };
+    let outcome = call(method, req.get("params"), node, access);
+    id.map(|id| response(id, outcome))
+}
+
+/// 按名称查找公开方法或管理方法
+pub fn find_method(name: &str) -> Option<&'static Method> {
+    METHODS
+        .iter()
+        .chain(admin::METHODS)
+        .find(|m| m.name == name)
+}
+
+pub fn call(
+    method: &str,
+    params: Option<&Value>,
+    node: Option<&NetworkContext>,
+    access: Access,
+) -> Result<Value, RpcError> {
+    let Some(method) = find_method(method) else {
+        return Err(RpcError::new(METHOD_NOT_FOUND, "method not found").with_data(json!(method)));
+    };
+    if method.name.starts_with(admin::PREFIX) && access != Access::Admin {
+        return Err(RpcError::new(
+            UNAUTHORIZED,
+            "admin methods require a valid admin token",
+        ));
+    }
+    let params = Params::parse(params, method.params)?;
+    (method.handler)(&params, node)
+}
+
+fn live(node: Option<&NetworkContext>) -> Result<&NetworkContext, RpcError> {
+    node.ok_or_else(RpcError::node_not_running)
+}
+
+fn handle_sync_status(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let height = ctx.chain.lock().unwrap().height();
+    let status = ctx.sync.lock().unwrap().status(height);
+    Ok(json!(status))
+}
+
+/// 供轻客户端同步的区块头，参数为 [start, count?]
+fn handle_get_headers(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let start = params.u64(0)?;
+    let count = params
+        .opt_u64(1)?
+        .unwrap_or(crate::sync::MAX_HEADERS_PER_REQUEST);
+    let range = crate::protocol::BlockRange { start, count };
+    let headers = crate::sync::headers_in_range(&ctx.chain.lock().unwrap(), range);
+    Ok(json!(headers))
+}
+
+/// 交易包含证明，参数为 [tx_hash]，交易不在链上时结果为 null
+fn handle_get_tx_proof(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let hash = params.str(0)?;
+    let proof = crate::light::tx_proof(&ctx.chain.lock().unwrap(), hash);
+    Ok(json!(proof))
+}
+
+/// 针对链顶状态根的账户证明，参数为 [address]，账户不存在时结果为 null
+fn handle_get_account_proof(
+    params: &Params,
+    node: Option<&NetworkContext>,
+) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let address = params.str(0)?;
+    let proof = crate::light::account_proof(&ctx.chain.lock().unwrap(), address);
+    Ok(json!(proof))
+}
+
+fn handle_get_height(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let height = ctx.chain.lock().unwrap().height();
+    Ok(json!(height))
+}
+
+/// 按高度查询区块，参数为 [height]，超出链高时结果为 null
+fn handle_get_block(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let height = params.u64(0)?;
+    let chain = ctx.chain.lock().unwrap();
+    let block = usize::try_from(height)
+        .ok()
+        .and_then(|h| chain.chain.get(h));
+    Ok(json!(block))
+}
+
+/// 按哈希查询区块，参数为 [block_hash]
+fn handle_get_block_by_hash(
+    params: &Params,
+    node: Option<&NetworkContext>,
+) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let hash = params.str(0)?;
+    let chain = ctx.chain.lock().unwrap();
+    Ok(json!(chain.block_by_hash(hash)))
+}
+
+fn page_size(params: &Params, index: usize) -> Result<usize, RpcError> {
+    let limit = params.opt_u64(index)?.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(RpcError::invalid_params(format!(
+            "`limit` must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    Ok(limit as usize)
+}
+
+/// 从高到低分页列出区块，参数为 [before?, limit?]；`before` 缺省时从链顶开始，
+/// 结果中的 `next_before` 用作下一页的 `before`，没有更多区块时为 null
+fn handle_get_blocks(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let limit = page_size(params, 1)?;
+    let chain = ctx.chain.lock().unwrap();
+    let before = params
+        .opt_u64(0)?
+        .map_or(chain.chain.len(), |b| (b as usize).min(chain.chain.len()));
+    let start = before.saturating_sub(limit);
+    let blocks: Vec<_> = chain.chain[start..before].iter().rev().collect();
+    let next_before = (start > 0).then_some(start);
+    Ok(json!({"blocks": blocks, "next_before": next_before}))
+}
+
+fn handle_get_latest_block(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let chain = ctx.chain.lock().unwrap();
+    Ok(json!(chain.chain.last()))
+}
+
+/// 链顶状态中的余额，参数为 [address]，不存在的账户余额为 0
+fn handle_get_balance(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let address = params.str(0)?;
+    let balance = ctx.chain.lock().unwrap().state.balance_of(address);
+    Ok(json!({"address": address, "balance": balance}))
+}
+
+/// 链上 nonce 与计入 mempool 后下一笔交易应使用的 nonce，参数为 [address]
+fn handle_get_nonce(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let address = params.str(0)?;
+    let chain = ctx.chain.lock().unwrap();
+    let pending = ctx
+        .mempool
+        .lock()
+        .unwrap()
+        .next_nonce(&chain.state, address);
+    Ok(json!({
+        "address": address,
+        "nonce": chain.state.nonce_of(address),
+        "pending_nonce": pending
+    }))
+}
+
+/// 账户余额与 nonce，参数为 [address]
+fn handle_get_account(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let address = params.str(0)?;
+    let chain = ctx.chain.lock().unwrap();
+    let pending = ctx
+        .mempool
+        .lock()
+        .unwrap()
+        .next_nonce(&chain.state, address);
+    Ok(json!({
+        "address": address,
+        "balance": chain.state.balance_of(address),
+        "nonce": chain.state.nonce_of(address),
+        "pending_nonce": pending
+    }))
+}
+
+/// 按哈希查询交易，先查链上再查 mempool，参数为 [tx_hash]，都找不到时结果为 null
+fn handle_get_transaction(
+    params: &Params,
+    node: Option<&NetworkContext>,
+) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let hash = params.str(0)?;
+    let chain = ctx.chain.lock().unwrap();
+    for block in chain.chain.iter().rev() {
+        if let Some(tx) = block.transactions.iter().find(|tx| tx.hash() == hash) {
+            return Ok(json!({
+                "status": "confirmed",
+                "block_height": block.index,
+                "block_hash": block.hash,
+                "transaction": tx
+            }));
+        }
+    }
+    let result = ctx.mempool.lock().unwrap().get(hash).map(|tx| {
+        json!({
+            "status": "pending",
+            "block_height": null,
+            "block_hash": null,
+            "transaction": tx
+        })
+    });
+    Ok(json!(result))
+}
+
+/// 待打包交易按发送方与 nonce 排序后分页，参数为 [offset?, limit?]
+fn handle_get_mempool(params: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let offset = params.opt_u64(0)?.unwrap_or(0) as usize;
+    let limit = page_size(params, 1)?;
+    let mempool = ctx.mempool.lock().unwrap();
+    let mut txs: Vec<_> = mempool.transactions().collect();
+    txs.sort_by(|a, b| (&a.from, a.nonce).cmp(&(&b.from, b.nonce)));
+    let page: Vec<_> = txs
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|tx| json!({"hash": tx.hash(), "transaction": tx}))
+        .collect();
+    Ok(json!({"count": txs.len(), "offset": offset, "transactions": page}))
+}
+
+/// 验证者的公钥及其权益，按权益从高到低排列
+fn handle_get_validators(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let chain = ctx.chain.lock().unwrap();
+    let mut validators: Vec<_> = chain.validators.iter().collect();
+    validators.sort_by(|a, b| b.1.stake.cmp(&a.1.stake).then(a.0.cmp(b.0)));
+    let validators: Vec<_> = validators
+        .into_iter()
+        .map(|(address, v)| {
+            json!({"address": address, "public_key": v.public_key, "stake": v.stake})
+        })
+        .collect();
+    Ok(json!(validators))
+}
+
+/// 已建立的会话与地址簿中的已知节点
+fn handle_get_peers(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let ctx = live(node)?;
+    let sessions = ctx.sessions.lock().unwrap().list();
+    let peers = ctx.peers.lock().unwrap();
+    Ok(json!({"connected": sessions, "known": peers.records()}))
+}
+
+/// 订阅只能在 WebSocket 连接上建立，实际处理见 `ws` 模块
+fn handle_subscription_over_http(
+    _: &Params,
+    _: Option<&NetworkContext>,
+) -> Result<Value, RpcError> {
+    Err(RpcError::new(
+        WEBSOCKET_REQUIRED,
+        "subscriptions are only available over WebSocket at /ws",
+    ))
+}
+
+/// 节点运行时读取节点持有的 peers.db 连接，独立启动的 RPC 服务直接读取 peers.db
+fn handle_list_bans(_: &Params, node: Option<&NetworkContext>) -> Result<Value, RpcError> {
+    let bans = match node {
+        Some(ctx) => crate::scoring::active_bans(ctx),
+        None => {
+            let conn = rusqlite::Connection::open("peers.db")
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))?;
+            let now = chrono::Utc::now().timestamp() as u64;
+            crate::peers::load_bans(&conn, now).unwrap_or_default()
+        }
+    };
+    Ok(json!(bans))
+}
+
+/// 节点运行时交易进入节点的 mempool 并广播，独立启动的 RPC 服务只写入 chain.db 的 mempool 表；
+/// 参数为已签名的交易，服务端不持有任何账户的私钥
+fn handle_send_transaction(
+    params: &Params,
+    node: Option<&NetworkContext>,
+) -> Result<Value, RpcError> {
+    let tx = params.transaction(0)?;
+    if tx.public_key.is_none() || tx.signature.is_none() {
+        return Err(RpcError::invalid_params(
+            "`transaction` must carry public_key and signature".to_string(),
+        ));
+    }
+    println!(
+        "[JSON-RPC] 交易提交: {} -> {} [{}]",
+        tx.from, tx.to, tx.amount
+    );
+    let submitted = match node {
+        Some(ctx) => crate::node::submit_to_node(ctx, tx),
+        None => crate::node::submit_to_local_mempool(crate::network::DEFAULT_CHAIN_ID, |_, _| tx),
+    };
+    match submitted {
+        Ok((tx, admission)) => Ok(json!({
+            "status": "ok",
+            "tx_hash": admission.hash,
+            "nonce": tx.nonce,
+            "replaced": admission.replaced,
+            "queued": admission.queued
+        })),
+        Err(reason) => Err(RpcError::new(TX_REJECTED, reason.to_string())
+            .with_data(json!({"reason": reason.code()}))),
+    }
+}
+
+/// 不提交地模拟执行转账，参数为 [from, to, amount, fee?, nonce?, state?]；`state` 缺省为 pending，
+/// nonce 缺省时取该状态下的下一个 nonce，无法执行时结果中 `success` 为 false
+fn handle_simulate_transaction(
+    params: &Params,
+    node: Option<&NetworkContext>,
+) -> Result<Value, RpcError> {
+    let transfer = Transfer {
+        from: params.str(0)?.to_string(),
+        to: params.str(1)?.to_string(),
+        amount: params.u64(2)?,
+        fee: params.opt_u64(3)?.unwrap_or(0),
+        nonce: params.opt_u64(4)?,
+        chain_id: params.opt_str(6)?.map(str::to_string),
+        public_key: params.opt_str(7)?.map(str::to_string),
+        signature: params.opt_str(8)?.map(str::to_string),
+    };
+    let view = params
+        .opt_str(5)?
+        .and_then(StateView::parse)
+        .unwrap_or(StateView::Pending);
+    let receipt = match node {
+        Some(ctx) => crate::node::simulate_on_node(ctx, view, transfer),
+        None => crate::node::simulate_local(view, transfer),
+    };
+    Ok(json!(receipt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_transaction_rejects_unsigned_transactions() {
+        let tx = Transaction::new("a", "b", 1, 0, 0);
+        let err = call("send_transaction", Some(&json!([tx])), None, Access::Public).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+    }
+
+    #[test]
+    fn send_transaction_no_longer_accepts_plain_transfer_params() {
+        let err = call(
+            "send_transaction",
+            Some(&json!({"from": "a", "to": "b", "amount": 1})),
+            None,
+            Access::Public,
+        )
+        .unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+    }
+
+    fn live_node() -> NetworkContext {
+        crate::simulator::sim_node(0, crate::clock::Clock::new_virtual(), 0)
+    }
+
+    #[test]
+    fn send_transaction_goes_to_the_running_node() {
+        let ctx = live_node();
+        let mut events = ctx.events.subscribe();
+        let tx = Transaction::signed(&crate::keys::dev_key("admin"), "b", 5, 1, 0);
+        let params = json!([tx]);
+        let result = call(
+            "send_transaction",
+            Some(&params),
+            Some(&ctx),
+            Access::Public,
+        )
+        .unwrap();
+        assert_eq!(result["tx_hash"], json!(tx.hash()));
+        assert_eq!(result["queued"], json!(false));
+        assert!(ctx.mempool.lock().unwrap().get(&tx.hash()).is_some());
+        assert!(matches!(
+            events.try_recv(),
+            Ok(crate::events::ChainEvent::PendingTransaction(_))
+        ));
+
+        let err = call(
+            "send_transaction",
+            Some(&params),
+            Some(&ctx),
+            Access::Public,
+        )
+        .unwrap_err();
+        assert_eq!(err.code, TX_REJECTED);
+        assert_eq!(err.data, Some(json!({"reason": "duplicate"})));
+    }
+
+    #[test]
+    fn read_methods_see_chain_and_mempool_state() {
+        let ctx = live_node();
+        let admin = crate::keys::dev_address("admin");
+        let read = |method: &str, params: Value| {
+            call(method, Some(&params), Some(&ctx), Access::Public).unwrap()
+        };
+        assert_eq!(read("get_height", json!([])), json!(0));
+        assert_eq!(read("get_block", json!([0]))["index"], json!(0));
+        assert_eq!(read("get_block", json!([1])), Value::Null);
+        assert_eq!(
+            read("get_balance", json!([admin]))["balance"],
+            json!(1000000)
+        );
+
+        let tx = Transaction::signed(&crate::keys::dev_key("admin"), "b", 5, 1, 0);
+        crate::node::submit_to_node(&ctx, tx.clone()).unwrap();
+        let nonce = read("get_nonce", json!({"address": admin}));
+        assert_eq!(nonce["nonce"], json!(0));
+        assert_eq!(nonce["pending_nonce"], json!(1));
+        let pending = read("get_transaction", json!([tx.hash()]));
+        assert_eq!(pending["status"], json!("pending"));
+
+        ctx.chain.lock().unwrap().add_dev_block(vec![tx.clone()], 1);
+        let confirmed = read("get_transaction", json!([tx.hash()]));
+        assert_eq!(confirmed["status"], json!("confirmed"));
+        assert_eq!(confirmed["block_height"], json!(1));
+        assert_eq!(read("get_latest_block", json!([]))["index"], json!(1));
+        assert_eq!(read("get_transaction", json!(["00"])), Value::Null);
+    }
+
+    #[test]
+    fn simulate_transaction_checks_signatures_when_given() {
+        let ctx = live_node();
+        let tx = Transaction::signed(&crate::keys::dev_key("admin"), "b", 5, 1, 0);
+        let simulate = |signature: &str| {
+            let params = json!({
+                "from": tx.from, "to": tx.to, "amount": tx.amount, "fee": tx.fee, "nonce": tx.nonce,
+                "public_key": tx.public_key, "signature": signature,
+            });
+            call(
+                "simulate_transaction",
+                Some(&params),
+                Some(&ctx),
+                Access::Public,
+            )
+            .unwrap()
+        };
+        let valid = simulate(tx.signature.as_deref().unwrap());
+        assert_eq!(valid["signature"], json!("valid"));
+        assert_eq!(valid["success"], json!(true));
+        let invalid = simulate(&"00".repeat(64));
+        assert_eq!(invalid["signature"], json!("invalid"));
+        assert_eq!(invalid["error"]["code"], json!("invalid_signature"));
+
+        let unsigned = call(
+            "simulate_transaction",
+            Some(&json!([tx.from, "b", 5])),
+            Some(&ctx),
+            Access::Public,
+        )
+        .unwrap();
+        assert_eq!(unsigned["signature"], json!("not_checked"));
+        assert_eq!(unsigned["success"], json!(true));
+    }
+
+    fn error_code(resp: &Value) -> i64 {
+        resp["error"]["code"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn batch_replies_in_order_and_skips_notifications() {
+        let body = json!([
+            {"jsonrpc": "2.0", "method": "rpc_discover", "id": 1},
+            {"jsonrpc": "2.0", "method": "get_height"},
+            {"jsonrpc": "2.0", "method": "no_such_method", "id": "b"},
+            {"jsonrpc": "2.0", "method": "get_height", "id": null},
+        ]);
+        let resp = handle_jsonrpc_body(&body.to_string(), None, Access::Public).unwrap();
+        let resp = resp.as_array().unwrap();
+        assert_eq!(resp.len(), 3);
+        assert_eq!(resp[0]["id"], json!(1));
+        assert!(resp[0]["result"]["methods"].is_array());
+        assert_eq!(resp[1]["id"], json!("b"));
+        assert_eq!(error_code(&resp[1]), METHOD_NOT_FOUND);
+        assert_eq!(resp[2]["id"], Value::Null);
+        assert_eq!(error_code(&resp[2]), NODE_NOT_RUNNING);
+    }
+
+    #[test]
+    fn notifications_alone_get_no_reply() {
+        let single = json!({"jsonrpc": "2.0", "method": "get_height"});
+        assert!(handle_jsonrpc_body(&single.to_string(), None, Access::Public).is_none());
+        let batch = json!([single, {"jsonrpc": "2.0", "method": "no_such_method"}]);
+        assert!(handle_jsonrpc_body(&batch.to_string(), None, Access::Public).is_none());
+    }
+
+    #[test]
+    fn malformed_bodies_get_error_responses() {
+        let reply = |body: &str| handle_jsonrpc_body(body, None, Access::Public).unwrap();
+        assert_eq!(error_code(&reply("{")), PARSE_ERROR);
+        assert_eq!(error_code(&reply("[]")), INVALID_REQUEST);
+        let too_many = Value::Array(vec![json!(1); MAX_BATCH_SIZE + 1]);
+        assert_eq!(error_code(&reply(&too_many.to_string())), INVALID_REQUEST);
+
+        let bad_version = reply(r#"{"jsonrpc":"1.0","method":"get_height","id":7}"#);
+        assert_eq!(bad_version["id"], json!(7));
+        assert_eq!(error_code(&bad_version), INVALID_REQUEST);
+        let bad_id = reply(r#"{"jsonrpc":"2.0","method":"get_height","id":[1]}"#);
+        assert_eq!(bad_id["id"], Value::Null);
+        assert_eq!(error_code(&bad_id), INVALID_REQUEST);
+
+        let batch = reply("[1, {\"jsonrpc\":\"2.0\",\"id\":2}]");
+        let batch = batch.as_array().unwrap();
+        assert_eq!(batch.len(), 2);
+        assert!(batch.iter().all(|r| error_code(r) == INVALID_REQUEST));
+    }
+
+    #[test]
+    fn params_are_checked_before_the_node_is_needed() {
+        let err = call("get_block", Some(&json!(["x"])), None, Access::Public).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        let err = call("get_block", Some(&json!([1])), None, Access::Public).unwrap_err();
+        assert_eq!(err.code, NODE_NOT_RUNNING);
+    }
+
+    #[test]
+    fn admin_methods_need_admin_access() {
+        let err = call("admin_peers", None, None, Access::Public).unwrap_err();
+        assert_eq!(err.code, UNAUTHORIZED);
+        let err = call("admin_peers", None, None, Access::Admin).unwrap_err();
+        assert_eq!(err.code, NODE_NOT_RUNNING);
+    }
 }
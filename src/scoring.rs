@@ -0,0 +1,115 @@
+use crate::network::NetworkContext;
+use crate::peers::{self, BanEntry};
+
+/// 累计扣分达到该值的节点会被封禁
+pub const BAN_THRESHOLD: u32 = 100;
+pub const BAN_DURATION_SECS: u64 = 24 * 60 * 60;
+
+/// 对端的不当行为，竞争出块、nonce 过低等正常网络现象不计入
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    MalformedMessage,
+    InvalidBlock,
+    InvalidHeaders,
+    InvalidTransaction,
+    OversizedInventory,
+    /// 消息速率超过会话限制
+    Flooding,
+    /// 同步请求超时，或返回的区块头达不到其公告的高度
+    Stalling,
+}
+
+impl Misbehavior {
+    pub fn penalty(&self) -> u32 {
+        match self {
+            Misbehavior::MalformedMessage => 50,
+            Misbehavior::InvalidBlock => 50,
+            Misbehavior::InvalidHeaders => 50,
+            Misbehavior::InvalidTransaction => 10,
+            Misbehavior::OversizedInventory => 20,
+            Misbehavior::Flooding => 5,
+            Misbehavior::Stalling => 10,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Misbehavior::MalformedMessage => "malformed_message",
+            Misbehavior::InvalidBlock => "invalid_block",
+            Misbehavior::InvalidHeaders => "invalid_headers",
+            Misbehavior::InvalidTransaction => "invalid_transaction",
+            Misbehavior::OversizedInventory => "oversized_inventory",
+            Misbehavior::Flooding => "flooding",
+            Misbehavior::Stalling => "stalling",
+        }
+    }
+}
+
+/// 为对端扣分，累计达到阈值时写入封禁列表并断开会话
+pub fn report(ctx: &NetworkContext, peer_id: &str, what: Misbehavior) {
+    let Some(score) = ctx
+        .sessions
+        .lock()
+        .unwrap()
+        .penalize(peer_id, what.penalty())
+    else {
+        return;
+    };
+    println!(
+        "⚠️ 节点 {} 行为不当 [{}]，扣 {} 分，累计 {} 分",
+        peer_id,
+        what.code(),
+        what.penalty(),
+        score
+    );
+    // 自动封禁只针对 node id：同一 IP 上可能有其他正常节点（本机、NAT 之后），IP 封禁留给管理命令
+    if score >= BAN_THRESHOLD {
+        let _ = ban_peer(ctx, peer_id, "", what.code(), Some(BAN_DURATION_SECS));
+        ctx.sessions
+            .lock()
+            .unwrap()
+            .disconnect(peer_id, &format!("banned: {}", what.code()));
+    }
+}
+
+/// 封禁节点，`addr` 非空时同时封禁其 IP；`duration_secs` 为 None 时永久封禁；返回写入的封禁记录
+pub fn ban_peer(
+    ctx: &NetworkContext,
+    node_id: &str,
+    addr: &str,
+    reason: &str,
+    duration_secs: Option<u64>,
+) -> rusqlite::Result<BanEntry> {
+    let now = ctx.clock.now_secs();
+    let ban = BanEntry {
+        node_id: node_id.to_string(),
+        addr: if addr.is_empty() {
+            String::new()
+        } else {
+            peers::ban_host(addr)
+        },
+        reason: reason.to_string(),
+        banned_at: now,
+        banned_until: duration_secs.map(|secs| now.saturating_add(secs)),
+    };
+    let result = ctx
+        .bans
+        .lock()
+        .unwrap()
+        .insert(&ctx.peer_db.lock().unwrap(), ban.clone());
+    match &result {
+        Ok(()) => println!("⛔ 封禁节点 {} ({}): {}", node_id, ban.addr, reason),
+        Err(e) => println!("⚠️ 保存封禁记录失败: {}", e),
+    }
+    result.map(|()| ban)
+}
+
+/// 当前有效的封禁中是否包含该节点，`addr` 为连接的实际地址
+pub fn is_banned(ctx: &NetworkContext, node_id: &str, addr: &str) -> bool {
+    let now = ctx.clock.now_secs();
+    ctx.bans.lock().unwrap().is_banned(node_id, addr, now)
+}
+
+pub fn active_bans(ctx: &NetworkContext) -> Vec<BanEntry> {
+    ctx.bans.lock().unwrap().active(ctx.clock.now_secs())
+}
@@ -0,0 +1,408 @@
+use crate::network::{self, NetworkContext};
+use crate::noise::{NoiseReader, NoiseWriter};
+use crate::protocol::{Handshake, Message, ProtocolError};
+use crate::ratelimit::TokenBucket;
+use crate::scoring::{self, Misbehavior};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, Notify};
+
+pub const OUTBOUND_QUEUE_SIZE: usize = 256;
+pub const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// 超过该时间未收到任何消息（包括 Pong）即认为连接失效
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+/// 每个会话允许的消息突发量与持续速率（条/秒），超出的消息被丢弃并扣分
+pub const MESSAGE_BURST: u32 = 500;
+pub const MESSAGE_RATE: u32 = 100;
+const MIN_BACKOFF_SECS: u64 = 1;
+const MAX_BACKOFF_SECS: u64 = 300;
+
+fn now_secs() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+/// 已建立会话的对端信息
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerInfo {
+    pub node_id: String,
+    /// 对端的监听地址，入站连接取自对端自报的握手信息
+    pub addr: String,
+    /// 连接实际的套接字地址，封禁按它的 IP 判断
+    pub remote_addr: String,
+    pub outbound: bool,
+    pub best_height: u64,
+    pub best_hash: String,
+    pub connected_at: u64,
+    pub last_seen: u64,
+    /// 累计的不当行为扣分
+    pub score: u32,
+}
+
+struct Session {
+    info: PeerInfo,
+    sender: mpsc::Sender<Message>,
+    shutdown: Arc<Notify>,
+}
+
+/// 出站重连的退避状态
+struct Backoff {
+    failures: u32,
+    next_attempt: u64,
+    dialing: bool,
+}
+
+/// 以 node id 为键的会话表，节点其他部分通过它查询对端与发送消息
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: HashMap<String, Session>,
+    backoff: HashMap<String, Backoff>,
+    /// 按 node id 保存的扣分，断线重连后继续累计
+    scores: HashMap<String, u32>,
+}
+
+impl SessionRegistry {
+    /// 注册新会话，同一 node id 已有会话时返回 false
+    pub fn register(
+        &mut self,
+        remote: &Handshake,
+        addr: String,
+        remote_addr: String,
+        outbound: bool,
+        sender: mpsc::Sender<Message>,
+        shutdown: Arc<Notify>,
+    ) -> bool {
+        if self.sessions.contains_key(&remote.node_id) {
+            return false;
+        }
+        let now = now_secs();
+        let info = PeerInfo {
+            node_id: remote.node_id.clone(),
+            addr,
+            remote_addr,
+            outbound,
+            best_height: remote.best_height,
+            best_hash: remote.best_hash.clone(),
+            connected_at: now,
+            last_seen: now,
+            score: self.scores.get(&remote.node_id).copied().unwrap_or(0),
+        };
+        self.sessions.insert(
+            remote.node_id.clone(),
+            Session {
+                info,
+                sender,
+                shutdown,
+            },
+        );
+        true
+    }
+
+    pub fn unregister(&mut self, node_id: &str) {
+        self.sessions.remove(node_id);
+    }
+
+    pub fn touch(&mut self, node_id: &str) {
+        if let Some(session) = self.sessions.get_mut(node_id) {
+            session.info.last_seen = now_secs();
+        }
+    }
+
+    /// 为对端扣分，返回累计分数；对端未连接时返回 None
+    pub fn penalize(&mut self, node_id: &str, points: u32) -> Option<u32> {
+        let session = self.sessions.get_mut(node_id)?;
+        let score = self.scores.entry(node_id.to_string()).or_insert(0);
+        *score = score.saturating_add(points);
+        session.info.score = *score;
+        Some(*score)
+    }
+
+    /// 通知对端断开原因并关闭会话
+    pub fn disconnect(&self, node_id: &str, reason: &str) {
+        if let Some(session) = self.sessions.get(node_id) {
+            let _ = session
+                .sender
+                .try_send(Message::Disconnect(reason.to_string()));
+            session.shutdown.notify_one();
+        }
+    }
+
+    pub fn update_best(&mut self, node_id: &str, height: u64, hash: &str) {
+        if let Some(session) = self.sessions.get_mut(node_id) {
+            if height >= session.info.best_height {
+                session.info.best_height = height;
+                session.info.best_hash = hash.to_string();
+            }
+        }
+    }
+
+    /// 放入对端的发送队列，队列已满时丢弃消息
+    pub fn send(&self, node_id: &str, msg: Message) -> bool {
+        match self.sessions.get(node_id) {
+            Some(session) => match session.sender.try_send(msg) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    println!("⚠️ 节点 {} 的发送队列已满，丢弃消息", node_id);
+                    false
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            },
+            None => false,
+        }
+    }
+
+    /// 按 node id 排序的会话列表
+    pub fn list(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.sessions.values().map(|s| s.info.clone()).collect();
+        peers.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        peers
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// 入站或出站会话的数量
+    pub fn count(&self, outbound: bool) -> usize {
+        self.sessions
+            .values()
+            .filter(|s| s.info.outbound == outbound)
+            .count()
+    }
+
+    pub fn scores(&self) -> HashMap<String, u32> {
+        self.scores.clone()
+    }
+
+    pub fn is_connected_addr(&self, addr: &str) -> bool {
+        self.sessions.values().any(|s| s.info.addr == addr)
+    }
+
+    /// 按 `known` 的顺序选出应当拨号的地址并标记为拨号中，
+    /// 已连接与拨号中的出站连接合计不超过 `max_outbound`
+    pub fn take_dial_candidates(
+        &mut self,
+        known: &[String],
+        now: u64,
+        max_outbound: usize,
+    ) -> Vec<String> {
+        let dialing = self.backoff.values().filter(|b| b.dialing).count();
+        let mut slots = max_outbound.saturating_sub(self.count(true) + dialing);
+        let mut candidates = Vec::new();
+        for addr in known {
+            if slots == 0 {
+                break;
+            }
+            if self.is_connected_addr(addr) {
+                continue;
+            }
+            let state = self.backoff.entry(addr.clone()).or_insert(Backoff {
+                failures: 0,
+                next_attempt: 0,
+                dialing: false,
+            });
+            if !state.dialing && state.next_attempt <= now {
+                state.dialing = true;
+                candidates.push(addr.clone());
+                slots -= 1;
+            }
+        }
+        candidates
+    }
+
+    /// 记录一次拨号结果；失败时按指数退避推迟下一次尝试
+    pub fn dial_finished(&mut self, addr: &str, success: bool) {
+        if let Some(state) = self.backoff.get_mut(addr) {
+            state.dialing = false;
+            if success {
+                state.failures = 0;
+                state.next_attempt = now_secs() + MIN_BACKOFF_SECS;
+            } else {
+                state.failures = state.failures.saturating_add(1);
+                let delay = MIN_BACKOFF_SECS
+                    .saturating_mul(1 << state.failures.min(16))
+                    .min(MAX_BACKOFF_SECS);
+                state.next_attempt = now_secs() + delay;
+            }
+        }
+    }
+}
+
+/// 运行一个已完成握手的会话：写任务发送队列中的消息与定期 Ping，读循环分发收到的消息
+pub async fn run_session<R, W>(
+    ctx: NetworkContext,
+    remote: Handshake,
+    addr: String,
+    remote_addr: String,
+    outbound: bool,
+    mut reader: NoiseReader<R>,
+    mut writer: NoiseWriter<W>,
+) where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<Message>(OUTBOUND_QUEUE_SIZE);
+    let shutdown = Arc::new(Notify::new());
+    let node_id = remote.node_id.clone();
+    let registered = ctx.sessions.lock().unwrap().register(
+        &remote,
+        addr.clone(),
+        remote_addr,
+        outbound,
+        tx,
+        Arc::clone(&shutdown),
+    );
+    if !registered {
+        let _ = writer
+            .write_message(&Message::Disconnect("duplicate connection".to_string()))
+            .await;
+        return;
+    }
+    println!(
+        "🔗 会话建立: {} ({}) {}",
+        node_id,
+        addr,
+        if outbound { "出站" } else { "入站" }
+    );
+
+    let mut writer_task = tokio::spawn(async move {
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        ping.tick().await;
+        loop {
+            let msg = tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                _ = ping.tick() => Message::Ping(rand::random()),
+            };
+            let closing = matches!(msg, Message::Disconnect(_));
+            if writer.write_message(&msg).await.is_err() || closing {
+                break;
+            }
+        }
+    });
+
+    let mut closing = false;
+    let mut bucket = TokenBucket::new(MESSAGE_BURST, MESSAGE_RATE);
+    loop {
+        let read = tokio::select! {
+            _ = shutdown.notified() => {
+                closing = true;
+                break;
+            }
+            read = tokio::time::timeout(IDLE_TIMEOUT, reader.read_message()) => read,
+        };
+        let msg = match read {
+            Ok(Ok(Some(msg))) => msg,
+            Ok(Ok(None)) => break,
+            Ok(Err(e)) => {
+                println!("⚠️ 节点 {} 发送了无效消息: {}", node_id, e);
+                if !matches!(e, ProtocolError::Io(_)) {
+                    scoring::report(&ctx, &node_id, Misbehavior::MalformedMessage);
+                }
+                break;
+            }
+            Err(_) => {
+                println!("⌛ 节点 {} 长时间无响应", node_id);
+                break;
+            }
+        };
+        ctx.sessions.lock().unwrap().touch(&node_id);
+        if !bucket.try_take() {
+            scoring::report(&ctx, &node_id, Misbehavior::Flooding);
+            continue;
+        }
+        if let Message::Disconnect(reason) = &msg {
+            println!("👋 节点 {} 断开: {}", node_id, reason);
+            break;
+        }
+        network::handle_message(&ctx, &node_id, msg);
+    }
+
+    // 主动断开时等待写任务把 Disconnect 发送出去
+    if closing {
+        let _ = tokio::time::timeout(Duration::from_secs(1), &mut writer_task).await;
+    }
+    writer_task.abort();
+    ctx.sessions.lock().unwrap().unregister(&node_id);
+    println!("🔌 会话关闭: {} ({})", node_id, addr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(node_id: &str, height: u64) -> Handshake {
+        Handshake {
+            protocol_version: crate::protocol::PROTOCOL_VERSION,
+            chain_id: network::DEFAULT_CHAIN_ID.to_string(),
+            genesis_hash: String::new(),
+            node_id: node_id.to_string(),
+            listen_addr: String::new(),
+            best_height: height,
+            best_hash: format!("tip-{}", height),
+        }
+    }
+
+    fn register(sessions: &mut SessionRegistry, node_id: &str, addr: &str) -> bool {
+        let (tx, _rx) = mpsc::channel(1);
+        sessions.register(
+            &hello(node_id, 1),
+            addr.to_string(),
+            addr.to_string(),
+            true,
+            tx,
+            Arc::new(Notify::new()),
+        )
+    }
+
+    #[test]
+    fn one_session_per_node_id_and_scores_survive_reconnects() {
+        let mut sessions = SessionRegistry::default();
+        assert!(register(&mut sessions, "a", "127.0.0.1:1"));
+        assert!(!register(&mut sessions, "a", "127.0.0.1:2"));
+        assert_eq!(sessions.penalize("a", 10), Some(10));
+        sessions.unregister("a");
+        assert_eq!(sessions.penalize("a", 10), None);
+        assert!(register(&mut sessions, "a", "127.0.0.1:1"));
+        assert_eq!(sessions.list()[0].score, 10);
+    }
+
+    #[test]
+    fn best_height_only_moves_forward() {
+        let mut sessions = SessionRegistry::default();
+        register(&mut sessions, "a", "127.0.0.1:1");
+        sessions.update_best("a", 5, "five");
+        sessions.update_best("a", 3, "three");
+        let peer = &sessions.list()[0];
+        assert_eq!((peer.best_height, peer.best_hash.as_str()), (5, "five"));
+    }
+
+    #[test]
+    fn dial_candidates_respect_slots_and_backoff() {
+        let mut sessions = SessionRegistry::default();
+        let known: Vec<String> = (1..=4).map(|i| format!("127.0.0.1:{}", i)).collect();
+        register(&mut sessions, "a", &known[0]);
+
+        // 已连接的地址被跳过，拨号中的连接占用名额
+        let first = sessions.take_dial_candidates(&known, 0, 3);
+        assert_eq!(first, known[1..3].to_vec());
+        assert!(sessions.take_dial_candidates(&known, 0, 3).is_empty());
+
+        let now = now_secs();
+        // 连续两次失败，退避时间明显长于成功后的最短间隔
+        sessions.dial_finished(&known[1], false);
+        sessions.dial_finished(&known[1], false);
+        sessions.dial_finished(&known[2], true);
+        let retry = sessions.take_dial_candidates(&known, now, 4);
+        assert_eq!(retry, vec![known[3].clone()]);
+        // 失败的地址按指数退避，成功断开的地址只等待最短间隔
+        let later = sessions.take_dial_candidates(&known, now + MIN_BACKOFF_SECS + 1, 4);
+        assert_eq!(later, vec![known[2].clone()]);
+        let much_later = sessions.take_dial_candidates(&known, now + MAX_BACKOFF_SECS, 4);
+        assert_eq!(much_later, vec![known[1].clone()]);
+    }
+}
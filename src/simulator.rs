@@ -0,0 +1,864 @@
+use crate::clock::Clock;
+use crate::compact::{self, CompactRelay};
+use crate::discovery::Discovery;
+use crate::events::EventBus;
+use crate::gossip::{self, Gossip};
+use crate::keys;
+use crate::mempool::{Admission, Mempool, RejectReason};
+use crate::network::{self, ConnectionLimits, LocalNode, NetworkContext};
+use crate::node;
+use crate::noise::NodeKey;
+use crate::peers::{BanList, PeerManager};
+use crate::protocol::{self, InvItem, InvKind, Message};
+use crate::session::SessionRegistry;
+use crate::sync::SyncManager;
+use crate::transaction::Transaction;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rusqlite::Connection;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+
+// 模拟器在同一进程中运行多个节点：每个节点使用独立的内存数据库与虚拟时钟，
+// 节点之间不建立 TCP 连接，而是由模拟器从会话发送队列中取出消息，
+// 经过协议编解码后按配置的延迟、丢包与分区规则投递给对端的 `network::handle_message`。
+// 同一条链路上的消息按发送顺序到达；丢包以整条消息为单位。
+// 延迟、丢包与各节点的 gossip 目标选择由种子决定，区块时间戳、mempool 过期与封禁都使用虚拟时钟，
+// 提议者由链顶哈希决定，相同配置的运行得到相同的投递顺序与相同的链。
+
+/// 同步状态机的驱动间隔，与运行中节点一致
+const TICK_MS: u64 = 1000;
+/// 第 i 个节点持有 `SIM_VALIDATORS[i % 2]` 的验证者密钥，相邻两个节点即可轮流出块
+const SIM_VALIDATORS: &[&str] = &["Alice", "Bob"];
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub nodes: usize,
+    pub seed: u64,
+    /// 单向基础延迟
+    pub latency_ms: u64,
+    /// 在基础延迟上附加的 0..=jitter_ms 随机延迟
+    pub jitter_ms: u64,
+    /// 每条消息被丢弃的概率
+    pub loss: f64,
+    pub block_interval_secs: u64,
+    /// 负责出块的节点序号，被选为提议者时出块；运行中的节点配置了验证者密钥就会出块
+    pub producers: Vec<usize>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            nodes: 4,
+            seed: 1,
+            latency_ms: 50,
+            jitter_ms: 20,
+            loss: 0.0,
+            block_interval_secs: 3,
+            producers: vec![0, 1],
+        }
+    }
+}
+
+/// 消息投递统计
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SimStats {
+    pub sent: u64,
+    pub delivered: u64,
+    pub lost: u64,
+    /// 因网络分区而丢弃
+    pub partitioned: u64,
+}
+
+/// 单个节点在某一时刻的链状态
+#[derive(Debug, Clone)]
+pub struct NodeStatus {
+    pub node_id: String,
+    pub height: u64,
+    pub tip: String,
+    pub synced: bool,
+}
+
+/// 一条单向链路，`rx` 为 `from` 节点上对端 `to` 的会话发送队列
+struct Link {
+    from: usize,
+    to: usize,
+    rx: mpsc::Receiver<Message>,
+    /// 该链路上最后一条消息的到达时间，保证按发送顺序到达
+    last_arrival: u64,
+}
+
+struct Delivery {
+    from: usize,
+    to: usize,
+    msg: Message,
+}
+
+pub struct Simulator {
+    config: SimConfig,
+    clock: Clock,
+    now_ms: u64,
+    next_tick_ms: u64,
+    ticks: u64,
+    nodes: Vec<NetworkContext>,
+    links: Vec<Link>,
+    /// 以（到达时间，序号）排序的在途消息
+    in_flight: BinaryHeap<Reverse<(u64, u64)>>,
+    deliveries: HashMap<u64, Delivery>,
+    seq: u64,
+    /// 每个节点所在的分区，同一分区内的节点才能通信
+    groups: Vec<usize>,
+    rng: StdRng,
+    pub stats: SimStats,
+}
+
+impl Simulator {
+    pub fn new(config: SimConfig) -> Self {
+        let clock = Clock::new_virtual();
+        let nodes = (0..config.nodes)
+            .map(|i| sim_node(i, clock.clone(), config.seed))
+            .collect();
+        Simulator {
+            groups: vec![0; config.nodes],
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            clock,
+            now_ms: 0,
+            next_tick_ms: TICK_MS,
+            ticks: 0,
+            nodes,
+            links: Vec::new(),
+            in_flight: BinaryHeap::new(),
+            deliveries: HashMap::new(),
+            seq: 0,
+            stats: SimStats::default(),
+        }
+    }
+
+    pub fn now(&self) -> Duration {
+        Duration::from_millis(self.now_ms)
+    }
+
+    /// 在两个节点之间建立会话，`a` 为出站一方
+    pub fn connect(&mut self, a: usize, b: usize) {
+        if a == b || self.links.iter().any(|l| l.from == a && l.to == b) {
+            return;
+        }
+        let rx_ab = self.register(a, b, true);
+        let rx_ba = self.register(b, a, false);
+        for (from, to, rx) in [(a, b, rx_ab), (b, a, rx_ba)] {
+            self.links.push(Link {
+                from,
+                to,
+                rx,
+                last_arrival: 0,
+            });
+        }
+    }
+
+    /// 两两建立会话
+    pub fn connect_all(&mut self) {
+        for a in 0..self.nodes.len() {
+            for b in a + 1..self.nodes.len() {
+                self.connect(a, b);
+            }
+        }
+    }
+
+    fn register(&self, local: usize, remote: usize, outbound: bool) -> mpsc::Receiver<Message> {
+        let hello = self.nodes[remote].handshake();
+        let addr = self.nodes[remote].local.listen_addr.clone();
+        let (tx, rx) = mpsc::channel(crate::session::OUTBOUND_QUEUE_SIZE);
+        self.nodes[local].sessions.lock().unwrap().register(
+            &hello,
+            addr.clone(),
+            addr,
+            outbound,
+            tx,
+            Arc::new(Notify::new()),
+        );
+        rx
+    }
+
+    /// 把节点划分为互不连通的分区，未列出的节点归入第一个分区
+    pub fn partition(&mut self, groups: &[Vec<usize>]) {
+        self.groups = vec![0; self.nodes.len()];
+        for (g, members) in groups.iter().enumerate() {
+            for &i in members {
+                self.groups[i] = g;
+            }
+        }
+    }
+
+    pub fn heal(&mut self) {
+        self.groups = vec![0; self.nodes.len()];
+    }
+
+    /// 向节点的 mempool 提交交易并公告给对端
+    pub fn submit_transaction(
+        &mut self,
+        i: usize,
+        tx: Transaction,
+    ) -> Result<Admission, RejectReason> {
+        let ctx = &self.nodes[i];
+        let admission = {
+            let chain = ctx.chain.lock().unwrap();
+            let conn = ctx.db.lock().unwrap();
+            ctx.mempool
+                .lock()
+                .unwrap()
+                .add(tx, &chain.state, Some(&conn))?
+        };
+        gossip::announce(
+            ctx,
+            InvItem {
+                kind: InvKind::Tx,
+                hash: admission.hash.clone(),
+            },
+            None,
+        );
+        Ok(admission)
+    }
+
+    /// 推进虚拟时间，按时间顺序投递到期的消息并驱动各节点的同步与出块
+    pub fn run_for(&mut self, duration: Duration) {
+        let target = self.now_ms + duration.as_millis() as u64;
+        loop {
+            self.collect_outgoing();
+            let next_delivery = self.in_flight.peek().map(|Reverse((at, _))| *at);
+            let next = match next_delivery {
+                Some(at) if at <= self.next_tick_ms => at,
+                _ => self.next_tick_ms,
+            };
+            if next > target {
+                break;
+            }
+            self.advance(next);
+            if next_delivery == Some(next) {
+                let Reverse((_, seq)) = self.in_flight.pop().unwrap();
+                let delivery = self.deliveries.remove(&seq).unwrap();
+                self.deliver(delivery);
+            } else {
+                self.tick();
+            }
+        }
+        self.advance(target);
+    }
+
+    fn advance(&mut self, ms: u64) {
+        self.now_ms = ms;
+        self.clock.advance_to(ms);
+    }
+
+    fn tick(&mut self) {
+        self.next_tick_ms += TICK_MS;
+        self.ticks += 1;
+        for ctx in &self.nodes {
+            ctx.sync.lock().unwrap().tick(ctx);
+            compact::tick(ctx);
+        }
+        let interval = (self.config.block_interval_secs * 1000 / TICK_MS).max(1);
+        if !self.ticks.is_multiple_of(interval) {
+            return;
+        }
+        for &i in &self.config.producers {
+            let ctx = &self.nodes[i];
+            if ctx.sync.lock().unwrap().is_synced() {
+                node::produce_block(ctx);
+            }
+        }
+    }
+
+    /// 取出所有会话发送队列中的消息，按丢包率与延迟安排到达时间
+    fn collect_outgoing(&mut self) {
+        for l in 0..self.links.len() {
+            while let Ok(msg) = self.links[l].rx.try_recv() {
+                self.stats.sent += 1;
+                if self.rng.gen_bool(self.config.loss) {
+                    self.stats.lost += 1;
+                    continue;
+                }
+                let delay = self.config.latency_ms + self.rng.gen_range(0..=self.config.jitter_ms);
+                let link = &mut self.links[l];
+                let at = (self.now_ms + delay).max(link.last_arrival);
+                link.last_arrival = at;
+                self.seq += 1;
+                self.in_flight.push(Reverse((at, self.seq)));
+                self.deliveries.insert(
+                    self.seq,
+                    Delivery {
+                        from: link.from,
+                        to: link.to,
+                        msg,
+                    },
+                );
+            }
+        }
+    }
+
+    fn deliver(&mut self, delivery: Delivery) {
+        if self.groups[delivery.from] != self.groups[delivery.to] {
+            self.stats.partitioned += 1;
+            return;
+        }
+        // 与真实连接一样经过编解码，暴露序列化与大小限制问题
+        let msg = match protocol::encode(&delivery.msg).and_then(|f| protocol::decode(&f)) {
+            Ok(msg) => msg,
+            Err(e) => {
+                println!("⚠️ [模拟器] 消息编解码失败: {}", e);
+                return;
+            }
+        };
+        self.stats.delivered += 1;
+        let from_id = self.nodes[delivery.from].local.node_id.clone();
+        let ctx = &self.nodes[delivery.to];
+        ctx.sessions.lock().unwrap().touch(&from_id);
+        if let Message::Disconnect(reason) = &msg {
+            println!("👋 [模拟器] 节点 {} 断开: {}", from_id, reason);
+            self.disconnect(delivery.from, delivery.to);
+            return;
+        }
+        network::handle_message(ctx, &from_id, msg);
+    }
+
+    fn disconnect(&mut self, a: usize, b: usize) {
+        let id_a = self.nodes[a].local.node_id.clone();
+        let id_b = self.nodes[b].local.node_id.clone();
+        self.nodes[a].sessions.lock().unwrap().unregister(&id_b);
+        self.nodes[b].sessions.lock().unwrap().unregister(&id_a);
+        self.links
+            .retain(|l| !((l.from == a && l.to == b) || (l.from == b && l.to == a)));
+    }
+
+    pub fn status(&self) -> Vec<NodeStatus> {
+        self.nodes
+            .iter()
+            .map(|ctx| {
+                let chain = ctx.chain.lock().unwrap();
+                NodeStatus {
+                    node_id: ctx.local.node_id.clone(),
+                    height: chain.height(),
+                    tip: chain.get_last_hash(),
+                    synced: ctx.sync.lock().unwrap().is_synced(),
+                }
+            })
+            .collect()
+    }
+
+    /// 所有节点共同拥有的最高区块高度，之后的区块至少在一个节点上存在分歧或缺失
+    pub fn common_height(&self) -> u64 {
+        let chains: Vec<Vec<String>> = self
+            .nodes
+            .iter()
+            .map(|ctx| {
+                let chain = ctx.chain.lock().unwrap();
+                chain.chain.iter().map(|b| b.hash.clone()).collect()
+            })
+            .collect();
+        let shortest = chains.iter().map(|c| c.len()).min().unwrap_or(0);
+        let common = (0..shortest)
+            .take_while(|&h| chains.iter().all(|c| c[h] == chains[0][h]))
+            .count();
+        common.saturating_sub(1) as u64
+    }
+
+    /// 所有节点的链顶一致
+    pub fn converged(&self) -> bool {
+        let status = self.status();
+        status.windows(2).all(|w| w[0].tip == w[1].tip)
+    }
+}
+
+/// 第 `i` 个模拟节点，数据库都在内存中
+pub(crate) fn sim_node(i: usize, clock: Clock, seed: u64) -> NetworkContext {
+    let conn = Connection::open_in_memory().unwrap();
+    node::init_chain_db(&conn);
+    let db = Arc::new(Mutex::new(conn));
+    let chain = node::load_blockchain(&db);
+    let key = NodeKey::generate();
+    let local = LocalNode {
+        chain_id: network::DEFAULT_CHAIN_ID.to_string(),
+        node_id: format!("sim-{}", i),
+        listen_addr: format!("10.0.0.{}:8000", i + 1),
+        key,
+        validator: Some(keys::dev_key(SIM_VALIDATORS[i % SIM_VALIDATORS.len()])),
+    };
+    let mut peers = PeerManager::default();
+    peers.set_local_addrs(std::slice::from_ref(&local.listen_addr));
+    NetworkContext {
+        local: Arc::new(local),
+        chain: Arc::new(Mutex::new(chain)),
+        mempool: Arc::new(Mutex::new(Mempool::with_clock(clock.clone()))),
+        peers: Arc::new(Mutex::new(peers)),
+        sessions: Arc::new(Mutex::new(SessionRegistry::default())),
+        sync: Arc::new(Mutex::new(SyncManager::new(clock.now_secs()))),
+        gossip: Arc::new(Mutex::new(Gossip::with_seed(seed.wrapping_add(i as u64)))),
+        compact: Arc::new(Mutex::new(CompactRelay::default())),
+        discovery: Arc::new(Mutex::new(Discovery::default())),
+        limits: ConnectionLimits::default(),
+        db,
+        // 每个模拟节点有独立的内存 peers.db，封禁记录互不影响也不会写到磁盘上
+        peer_db: Arc::new(Mutex::new(Connection::open_in_memory().unwrap())),
+        bans: Arc::new(Mutex::new(BanList::default())),
+        events: EventBus::default(),
+        clock,
+        shutdown: Arc::new(Notify::new()),
+    }
+}
+
+/// 命令行运行的场景：全连接网络，可选地在一段时间内把节点分成两半
+pub struct Scenario {
+    pub duration_secs: u64,
+    pub partition_at: Option<u64>,
+    pub heal_at: Option<u64>,
+    /// 开始时从 admin 发出的转账数量，轮流提交到各个节点
+    pub txs: u64,
+}
+
+pub fn run_scenario(config: SimConfig, scenario: Scenario) {
+    let n = config.nodes;
+    println!(
+        "🧪 模拟 {} 个节点 | 延迟 {}±{}ms | 丢包率 {} | 种子 {}",
+        n, config.latency_ms, config.jitter_ms, config.loss, config.seed
+    );
+    let mut sim = Simulator::new(config);
+    sim.connect_all();
+    let (admin, bob) = (keys::dev_key("admin"), keys::dev_address("Bob"));
+    for k in 0..scenario.txs {
+        let tx = Transaction::signed(&admin, &bob, 1, 1, k);
+        if let Err(reason) = sim.submit_transaction(k as usize % n, tx) {
+            println!("❌ 交易被拒绝 [{}]: {}", reason.code(), reason);
+        }
+    }
+
+    let mut events: Vec<(u64, bool)> = Vec::new();
+    if let Some(at) = scenario.partition_at {
+        events.push((at, true));
+    }
+    if let Some(at) = scenario.heal_at {
+        events.push((at, false));
+    }
+    events.retain(|(at, _)| *at < scenario.duration_secs);
+    events.sort();
+    for (at, split) in events {
+        sim.run_for(Duration::from_secs(at).saturating_sub(sim.now()));
+        if split {
+            let half = n / 2;
+            sim.partition(&[(0..half).collect(), (half..n).collect()]);
+            println!("✂️ [{}s] 网络分区: 0..{} | {}..{}", at, half, half, n);
+        } else {
+            sim.heal();
+            println!("🩹 [{}s] 分区恢复", at);
+        }
+    }
+    sim.run_for(Duration::from_secs(scenario.duration_secs).saturating_sub(sim.now()));
+
+    println!("📋 模拟结束，虚拟时间 {}s", sim.now().as_secs());
+    for status in sim.status() {
+        println!(
+            " - {} 高度: {} 链顶: {} {}",
+            status.node_id,
+            status.height,
+            status.tip,
+            if status.synced {
+                "已同步"
+            } else {
+                "同步中"
+            }
+        );
+    }
+    println!(
+        "📨 消息: 发送 {} | 投递 {} | 丢失 {} | 分区丢弃 {}",
+        sim.stats.sent, sim.stats.delivered, sim.stats.lost, sim.stats.partitioned
+    );
+    println!(
+        "🔗 共同高度: {} | {}",
+        sim.common_height(),
+        if sim.converged() {
+            "所有节点链顶一致"
+        } else {
+            "节点链顶不一致"
+        }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compact::CompactBlock;
+    use crate::events::{ChainEvent, FINALITY_DEPTH};
+    use crate::scoring::{self, Misbehavior};
+    use tokio::sync::broadcast;
+
+    const RECIPIENT: &str = "sim-recipient";
+
+    fn transfer(nonce: u64) -> Transaction {
+        Transaction::signed(&keys::dev_key("admin"), RECIPIENT, 1, 1, nonce)
+    }
+
+    fn split_config(seed: u64) -> SimConfig {
+        SimConfig {
+            seed,
+            producers: vec![0, 1, 2, 3],
+            ..SimConfig::default()
+        }
+    }
+
+    /// 两个出块节点分区后各自打包不同的交易，恢复连通后再运行一段时间，结束前留出传播时间
+    fn split_and_heal(sim: &mut Simulator) {
+        sim.connect_all();
+        sim.run_for(Duration::from_secs(10));
+        sim.partition(&[vec![0, 1], vec![2, 3]]);
+        sim.submit_transaction(0, transfer(0)).unwrap();
+        sim.run_for(Duration::from_secs(30));
+        sim.heal();
+        sim.run_for(Duration::from_secs(31));
+    }
+
+    fn chain_summary(sim: &Simulator) -> Vec<(String, u64, String)> {
+        sim.status()
+            .into_iter()
+            .map(|s| (s.node_id, s.height, s.tip))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_produces_identical_runs() {
+        let mut first = Simulator::new(split_config(7));
+        split_and_heal(&mut first);
+        let mut second = Simulator::new(split_config(7));
+        split_and_heal(&mut second);
+        assert_eq!(chain_summary(&first), chain_summary(&second));
+        assert_eq!(first.stats.sent, second.stats.sent);
+        assert_eq!(first.stats.delivered, second.stats.delivered);
+    }
+
+    #[test]
+    fn partitioned_producers_converge_after_heal() {
+        let mut sim = Simulator::new(split_config(3));
+        split_and_heal(&mut sim);
+        assert!(sim.converged(), "{:?}", chain_summary(&sim));
+        assert!(sim.common_height() >= 20);
+        // 分区期间打包的交易在重组后仍然只执行一次，也不再留在任何节点的 mempool 中
+        for ctx in &sim.nodes {
+            assert_eq!(ctx.chain.lock().unwrap().state.balance_of(RECIPIENT), 1);
+            assert_eq!(ctx.mempool.lock().unwrap().transactions().count(), 0);
+        }
+    }
+
+    #[test]
+    fn reorg_returns_orphaned_transactions_to_the_mempool() {
+        let mut sim = Simulator::new(split_config(3));
+        sim.connect_all();
+        sim.run_for(Duration::from_secs(10));
+        sim.partition(&[vec![0, 1], vec![2, 3]]);
+        let mut hashes = vec![];
+        for nonce in 0..2 {
+            hashes.push(sim.submit_transaction(0, transfer(nonce)).unwrap().hash);
+        }
+        sim.run_for(Duration::from_secs(3));
+        let admin = keys::dev_address("admin");
+        assert_eq!(sim.nodes[0].chain.lock().unwrap().state.nonce_of(&admin), 2);
+        // 节点 0 停止出块，另一侧的链更长，恢复连通后节点 0 的区块被重组掉
+        sim.config.producers = vec![2, 3];
+        hashes.push(sim.submit_transaction(0, transfer(2)).unwrap().hash);
+        sim.run_for(Duration::from_secs(12));
+        sim.heal();
+        sim.run_for(Duration::from_secs(5));
+        {
+            let ctx = &sim.nodes[0];
+            let chain = ctx.chain.lock().unwrap();
+            assert_eq!(chain.state.balance_of(RECIPIENT), 0);
+            // 放回的交易从链上 nonce 起连续可执行
+            let next = ctx.mempool.lock().unwrap().next_nonce(&chain.state, &admin);
+            assert_eq!(next, 3);
+        }
+        sim.config.producers = vec![0, 1, 2, 3];
+        sim.run_for(Duration::from_secs(10));
+        assert!(sim.converged(), "{:?}", chain_summary(&sim));
+        for ctx in &sim.nodes {
+            assert_eq!(ctx.chain.lock().unwrap().state.balance_of(RECIPIENT), 3);
+            let mempool = ctx.mempool.lock().unwrap();
+            assert!(hashes.iter().all(|hash| mempool.get(hash).is_none()));
+        }
+    }
+
+    #[test]
+    fn late_node_syncs_to_the_tip() {
+        let mut sim = Simulator::new(SimConfig::default());
+        sim.connect(0, 1);
+        sim.connect(0, 2);
+        sim.connect(1, 2);
+        sim.run_for(Duration::from_secs(60));
+        assert_eq!(sim.status()[3].height, 0);
+        sim.connect(3, 0);
+        sim.run_for(Duration::from_millis(2500));
+        let status = sim.status();
+        assert!(status[0].height >= 20);
+        assert_eq!(status[3].tip, status[0].tip);
+        assert!(status[3].synced);
+    }
+
+    #[test]
+    fn peers_that_stall_or_overstate_their_height_are_penalized() {
+        let mut sim = Simulator::new(SimConfig::default());
+        sim.connect(0, 1);
+        sim.connect(0, 2);
+        sim.run_for(Duration::from_secs(30));
+        let score = |sim: &Simulator, i: usize, peer: &str| {
+            sim.nodes[i]
+                .sessions
+                .lock()
+                .unwrap()
+                .scores()
+                .get(peer)
+                .copied()
+        };
+        // 节点 2 收到一个无法导入的公告，对端给出的区块头达不到该高度
+        let tip = sim.status()[0].height;
+        sim.nodes[2]
+            .sync
+            .lock()
+            .unwrap()
+            .on_announced("sim-0", tip + 100, "unknown");
+        sim.run_for(Duration::from_secs(3));
+        assert_eq!(
+            score(&sim, 2, "sim-0"),
+            Some(Misbehavior::Stalling.penalty())
+        );
+        // 公告未经验证，不会计入对端的链顶
+        let peers = sim.nodes[2].sessions.lock().unwrap().list();
+        assert!(peers.iter().all(|p| p.best_height <= tip + 1));
+
+        // 节点 3 刚连上就与对端断开，区块头请求超时
+        sim.connect(3, 1);
+        sim.partition(&[vec![0, 1, 2], vec![3]]);
+        sim.run_for(Duration::from_secs(12));
+        assert_eq!(
+            score(&sim, 3, "sim-1"),
+            Some(Misbehavior::Stalling.penalty())
+        );
+        sim.heal();
+        sim.run_for(Duration::from_secs(35));
+        assert_eq!(sim.status()[3].tip, sim.status()[0].tip);
+    }
+
+    #[test]
+    fn finality_events_match_the_final_chain() {
+        let mut sim = Simulator::new(split_config(5));
+        let mut events = sim.nodes[1].events.subscribe();
+        split_and_heal(&mut sim);
+        let mut finalized: HashMap<u64, String> = HashMap::new();
+        loop {
+            match events.try_recv() {
+                Ok(ChainEvent::Finalized(block)) => {
+                    finalized.insert(block.index, block.hash);
+                }
+                Ok(_) => {}
+                Err(broadcast::error::TryRecvError::Empty) => break,
+                Err(e) => panic!("event stream broken: {:?}", e),
+            }
+        }
+        let chain = sim.nodes[1].chain.lock().unwrap();
+        let last = chain.height() - FINALITY_DEPTH;
+        assert_eq!(finalized.len() as u64, last + 1);
+        // 重组后同一高度重新发布的最终确定事件以新链为准
+        for height in 0..=last {
+            assert_eq!(
+                finalized.get(&height),
+                Some(&chain.chain[height as usize].hash)
+            );
+        }
+    }
+
+    #[test]
+    fn head_events_replay_to_the_final_chain() {
+        let mut sim = Simulator::new(split_config(5));
+        let mut subscribers: Vec<_> = sim.nodes.iter().map(|ctx| ctx.events.subscribe()).collect();
+        let genesis = sim.nodes[0].chain.lock().unwrap().chain[0].hash.clone();
+        split_and_heal(&mut sim);
+        let mut removed = 0;
+        for (ctx, events) in sim.nodes.iter().zip(&mut subscribers) {
+            let mut heads = vec![genesis.clone()];
+            loop {
+                match events.try_recv() {
+                    Ok(ChainEvent::NewBlock(block)) => {
+                        assert_eq!(block.index, heads.len() as u64);
+                        heads.push(block.hash);
+                    }
+                    // 移出的区块总是当前链顶
+                    Ok(ChainEvent::Removed(block)) => {
+                        assert_eq!(heads.pop(), Some(block.hash));
+                        removed += 1;
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::TryRecvError::Empty) => break,
+                    Err(e) => panic!("event stream broken: {:?}", e),
+                }
+            }
+            let chain = ctx.chain.lock().unwrap();
+            let hashes: Vec<String> = chain.chain.iter().map(|b| b.hash.clone()).collect();
+            assert_eq!(heads, hashes);
+        }
+        assert!(removed > 0, "the split should force a reorg");
+    }
+
+    #[test]
+    fn bans_are_per_node() {
+        let mut sim = Simulator::new(SimConfig {
+            nodes: 3,
+            ..SimConfig::default()
+        });
+        sim.connect_all();
+        sim.run_for(Duration::from_secs(5));
+        let addr = sim.nodes[1].local.listen_addr.clone();
+        for _ in 0..2 {
+            scoring::report(&sim.nodes[0], "sim-1", Misbehavior::InvalidBlock);
+        }
+        let bans = scoring::active_bans(&sim.nodes[0]);
+        assert_eq!(bans.len(), 1);
+        assert_eq!(
+            bans[0].banned_until,
+            Some(5 + scoring::BAN_DURATION_SECS),
+            "ban times come from the virtual clock"
+        );
+        assert!(scoring::is_banned(&sim.nodes[0], "sim-1", &addr));
+        // 自动封禁不涉及 IP，同一地址上的其他节点仍可连接
+        assert!(bans[0].addr.is_empty());
+        assert!(!scoring::is_banned(&sim.nodes[0], "sim-other", &addr));
+        for i in [1, 2] {
+            assert!(scoring::active_bans(&sim.nodes[i]).is_empty());
+            assert!(!scoring::is_banned(&sim.nodes[i], "sim-1", &addr));
+        }
+
+        sim.run_for(Duration::from_secs(1));
+        let connected = |i: usize, peer: &str| {
+            sim.nodes[i]
+                .sessions
+                .lock()
+                .unwrap()
+                .list()
+                .iter()
+                .any(|p| p.node_id == peer)
+        };
+        assert!(!connected(0, "sim-1"));
+        assert!(connected(2, "sim-1"));
+    }
+
+    #[test]
+    fn compact_blocks_fetch_transactions_missing_from_the_mempool() {
+        let mut sim = Simulator::new(SimConfig {
+            nodes: 3,
+            ..SimConfig::default()
+        });
+        sim.connect_all();
+        sim.run_for(Duration::from_secs(1));
+        // 分区期间的交易公告被丢弃，只有出块节点的 mempool 中有这笔交易
+        sim.partition(&[vec![0], vec![1, 2]]);
+        sim.submit_transaction(0, transfer(0)).unwrap();
+        sim.run_for(Duration::from_secs(1));
+        sim.heal();
+        sim.run_for(Duration::from_secs(2));
+        assert!(sim.converged(), "{:?}", chain_summary(&sim));
+        for ctx in &sim.nodes {
+            let chain = ctx.chain.lock().unwrap();
+            assert_eq!(chain.height(), 1);
+            assert_eq!(chain.state.balance_of(RECIPIENT), 1);
+        }
+    }
+
+    #[test]
+    fn unreconstructed_compact_blocks_are_fetched_whole_from_another_peer() {
+        let mut sim = Simulator::new(SimConfig {
+            nodes: 3,
+            block_interval_secs: 3600,
+            ..SimConfig::default()
+        });
+        sim.connect_all();
+        sim.run_for(Duration::from_secs(1));
+        let selected = sim.nodes[0].chain.lock().unwrap().select_proposer();
+        let proposer = if selected == keys::dev_address(SIM_VALIDATORS[0]) {
+            0
+        } else {
+            1
+        };
+        let holder = 1 - proposer;
+        sim.partition(&[vec![proposer, holder], vec![2]]);
+        sim.submit_transaction(proposer, transfer(0)).unwrap();
+        sim.run_for(Duration::from_millis(500));
+        let block = node::produce_block(&sim.nodes[proposer]).unwrap();
+        sim.run_for(Duration::from_secs(1));
+        assert_eq!(sim.nodes[holder].chain.lock().unwrap().height(), 1);
+
+        // 出块节点发出压缩区块后掉线，节点 2 请求的缺失交易没有回复
+        sim.partition(&[vec![proposer], vec![holder, 2]]);
+        let sender = format!("sim-{}", proposer);
+        compact::on_compact_block(&sim.nodes[2], &sender, CompactBlock::from_block(&block));
+        sim.run_for(Duration::from_secs(1));
+        let ctx = &sim.nodes[2];
+        assert_eq!(ctx.chain.lock().unwrap().height(), 0);
+        let sessions = ctx.sessions.lock().unwrap().list();
+        let peer = sessions.iter().find(|p| p.node_id == sender).unwrap();
+        assert_eq!(peer.best_height, 0);
+
+        // 超时后改向另一个对端请求完整区块
+        sim.run_for(Duration::from_secs(6));
+        let chain = sim.nodes[2].chain.lock().unwrap();
+        assert_eq!(chain.height(), 1);
+        assert_eq!(chain.state.balance_of(RECIPIENT), 1);
+    }
+
+    #[test]
+    fn gossiped_replacements_are_persisted() {
+        let mut sim = Simulator::new(SimConfig {
+            nodes: 2,
+            block_interval_secs: 3600,
+            ..SimConfig::default()
+        });
+        sim.connect_all();
+        sim.run_for(Duration::from_secs(1));
+        let original = sim.submit_transaction(0, transfer(0)).unwrap();
+        sim.run_for(Duration::from_secs(1));
+        let bump = Transaction::signed(&keys::dev_key("admin"), RECIPIENT, 1, 2, 0);
+        let replacement = sim.submit_transaction(0, bump).unwrap();
+        sim.run_for(Duration::from_secs(1));
+        // 对端重启后从 chain.db 恢复的 mempool 中只有替换后的交易
+        let ctx = &sim.nodes[1];
+        let chain = ctx.chain.lock().unwrap();
+        let mut reloaded = Mempool::default();
+        reloaded.load_from_db(&ctx.db.lock().unwrap(), &chain.state);
+        assert!(reloaded.get(&replacement.hash).is_some());
+        assert!(reloaded.get(&original.hash).is_none());
+        assert_eq!(reloaded.transactions().count(), 1);
+    }
+
+    #[test]
+    fn imported_blocks_prune_included_transactions() {
+        let mut sim = Simulator::new(SimConfig {
+            nodes: 2,
+            ..SimConfig::default()
+        });
+        sim.connect_all();
+        sim.run_for(Duration::from_secs(1));
+        let admission = sim.submit_transaction(0, transfer(0)).unwrap();
+        sim.run_for(Duration::from_millis(500));
+        assert!(sim.nodes[1]
+            .mempool
+            .lock()
+            .unwrap()
+            .get(&admission.hash)
+            .is_some());
+        sim.run_for(Duration::from_secs(3));
+        // 节点 1 不出块，交易只能随导入的区块从它的 mempool 与 mempool 表中移除
+        let ctx = &sim.nodes[1];
+        let chain = ctx.chain.lock().unwrap();
+        assert_eq!(chain.state.balance_of(RECIPIENT), 1);
+        assert_eq!(ctx.mempool.lock().unwrap().transactions().count(), 0);
+        let mut reloaded = Mempool::default();
+        reloaded.load_from_db(&ctx.db.lock().unwrap(), &chain.state);
+        assert_eq!(reloaded.transactions().count(), 0);
+    }
+}
@@ -1,8 +1,46 @@
+use crate::accounts::account::AccountState;
 use crate::block::block::Block;
 use crate::transaction::Transaction;
 use rusqlite::Result;
 use rusqlite::{params, Connection};
 
+/// chain.db 的格式版本，记录在 `PRAGMA user_version` 中。
+/// 版本 1 起区块带有状态根与提议者签名，版本 2 起交易签名绑定链 ID；
+/// 旧版本的区块无法通过校验，也不做迁移
+pub const CHAIN_DB_VERSION: u32 = 2;
+
+/// chain.db 由不兼容的版本创建
+#[derive(Debug)]
+pub struct IncompatibleDb {
+    pub found: u32,
+}
+
+impl std::fmt::Display for IncompatibleDb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "chain.db has format version {}, this build needs {}; delete chain.db and resync",
+            self.found, CHAIN_DB_VERSION
+        )
+    }
+}
+
+/// 检查 chain.db 的格式版本；新建的空库标记为当前版本
+pub fn check_version(conn: &Connection) -> Result<std::result::Result<(), IncompatibleDb>> {
+    let found: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if found == CHAIN_DB_VERSION {
+        return Ok(Ok(()));
+    }
+    let has_tables = conn
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table'")?
+        .exists([])?;
+    if found == 0 && !has_tables {
+        conn.pragma_update(None, "user_version", CHAIN_DB_VERSION)?;
+        return Ok(Ok(()));
+    }
+    Ok(Err(IncompatibleDb { found }))
+}
+
 pub fn init_db(conn: &Connection) -> Result<()> {
     conn.execute_batch(
         "
@@ -13,7 +51,10 @@ pub fn init_db(conn: &Connection) -> Result<()> {
             prev_hash TEXT,
             proposer TEXT,
             timestamp INTEGER,
-            transactions TEXT
+            transactions TEXT,
+            state_root TEXT NOT NULL,
+            proposer_key TEXT NOT NULL,
+            signature TEXT NOT NULL
         );
         ",
     )
@@ -22,7 +63,7 @@ pub fn init_db(conn: &Connection) -> Result<()> {
 pub fn save_block(conn: &Connection, block: &Block) -> Result<()> {
     let tx_json = serde_json::to_string(&block.transactions).unwrap();
     conn.execute(
-        "INSERT INTO blocks (idx, hash, prev_hash, proposer, timestamp, transactions) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        "INSERT INTO blocks (idx, hash, prev_hash, proposer, timestamp, transactions, state_root, proposer_key, signature) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
         (
             &block.index,
             &block.hash,
@@ -30,13 +71,21 @@ pub fn save_block(conn: &Connection, block: &Block) -> Result<()> {
             &block.proposer,
             &block.timestamp,
             &tx_json,
+            &block.state_root,
+            &block.proposer_key,
+            &block.signature,
         ),
     )?;
     Ok(())
 }
 
+/// 删除高于 `height` 的区块，链重组时使用
+pub fn delete_blocks_above(conn: &Connection, height: u64) -> Result<usize> {
+    conn.execute("DELETE FROM blocks WHERE idx > ?1", params![height])
+}
+
 pub fn get_block_by_index(conn: &Connection, idx: u64) -> Result<Option<Block>> {
-    let mut stmt = conn.prepare("SELECT idx, hash, prev_hash, proposer, timestamp, transactions FROM blocks WHERE idx = ?1 LIMIT 1")?;
+    let mut stmt = conn.prepare("SELECT idx, hash, prev_hash, proposer, timestamp, transactions, state_root, proposer_key, signature FROM blocks WHERE idx = ?1 LIMIT 1")?;
     let mut rows = stmt.query(params![idx])?;
     if let Some(row) = rows.next()? {
         let index: u64 = row.get(0)?;
@@ -45,6 +94,9 @@ pub fn get_block_by_index(conn: &Connection, idx: u64) -> Result<Option<Block>>
         let proposer: String = row.get(3)?;
         let timestamp: u64 = row.get(4)?;
         let tx_json: String = row.get(5)?;
+        let state_root: String = row.get(6)?;
+        let proposer_key: String = row.get(7)?;
+        let signature: String = row.get(8)?;
         let transactions: Vec<Transaction> = serde_json::from_str(&tx_json).unwrap_or_default();
         Ok(Some(Block {
             index,
@@ -53,6 +105,9 @@ pub fn get_block_by_index(conn: &Connection, idx: u64) -> Result<Option<Block>>
             proposer,
             timestamp,
             transactions,
+            state_root,
+            proposer_key,
+            signature,
         }))
     } else {
         Ok(None)
@@ -63,9 +118,18 @@ pub fn init_account_table(conn: &Connection) -> Result<()> {
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS accounts (
             address TEXT PRIMARY KEY,
-            balance INTEGER
+            balance INTEGER,
+            nonce INTEGER NOT NULL DEFAULT 0
         );",
-    )
+    )?;
+    // 旧版本的 accounts 表没有 nonce 列
+    let has_nonce = conn
+        .prepare("SELECT 1 FROM pragma_table_info('accounts') WHERE name = 'nonce'")?
+        .exists([])?;
+    if !has_nonce {
+        conn.execute_batch("ALTER TABLE accounts ADD COLUMN nonce INTEGER NOT NULL DEFAULT 0;")?;
+    }
+    Ok(())
 }
 
 pub fn add_account(conn: &Connection, address: &str, balance: u64) -> Result<()> {
@@ -87,31 +151,49 @@ pub fn get_balance(conn: &Connection, address: &str) -> Result<u64> {
     }
 }
 
-pub fn set_balance(conn: &Connection, address: &str, balance: u64) -> Result<()> {
-    conn.execute(
-        "UPDATE accounts SET balance = ?1 WHERE address = ?2",
-        (balance, address),
-    )?;
+pub fn load_account_state(conn: &Connection) -> Result<AccountState> {
+    let mut state = AccountState::new();
+    let mut stmt = conn.prepare("SELECT address, balance, nonce FROM accounts")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let address: String = row.get(0)?;
+        let balance: u64 = row.get(1)?;
+        let nonce: u64 = row.get(2)?;
+        state.balances.insert(address.clone(), balance);
+        state.nonces.insert(address, nonce);
+    }
+    Ok(state)
+}
+
+pub fn save_account_state(conn: &Connection, state: &AccountState) -> Result<()> {
+    for (address, balance) in &state.balances {
+        conn.execute(
+            "INSERT INTO accounts (address, balance, nonce) VALUES (?1, ?2, ?3)
+             ON CONFLICT(address) DO UPDATE SET balance = ?2, nonce = ?3",
+            (address, balance, state.nonce_of(address)),
+        )?;
+    }
     Ok(())
 }
 
+/// 以 `state` 整体替换账户表，链重组后旧链上出现过的账户也需要删除
+pub fn replace_account_state(conn: &Connection, state: &AccountState) -> Result<()> {
+    conn.execute("DELETE FROM accounts", [])?;
+    save_account_state(conn, state)
+}
+
 pub fn get_transaction_by_hash(
     conn: &Connection,
     tx_hash: &str,
 ) -> Result<Option<(u64, Transaction)>> {
     let mut stmt = conn.prepare("SELECT idx, transactions FROM blocks")?;
     let mut rows = stmt.query([])?;
-    use sha2::{Digest, Sha256};
     while let Some(row) = rows.next()? {
         let idx: u64 = row.get(0)?;
         let tx_json: String = row.get(1)?;
         let txs: Vec<Transaction> = serde_json::from_str(&tx_json).unwrap_or_default();
         for tx in txs {
-            let tx_str = format!("{}{}{}", tx.from, tx.to, tx.amount);
-            let mut hasher = Sha256::new();
-            hasher.update(tx_str.as_bytes());
-            let hash = format!("0x{:x}", hasher.finalize());
-            if hash == tx_hash {
+            if tx.hash() == tx_hash {
                 return Ok(Some((idx, tx)));
             }
         }
@@ -119,38 +201,111 @@ pub fn get_transaction_by_hash(
     Ok(None)
 }
 
-#[allow(dead_code)]
 pub fn init_mempool_table(conn: &Connection) -> Result<()> {
-    conn.execute_batch(
+    // 旧版本的 mempool 表以自增 id 为主键，没有交易哈希与接收时间，需要重建
+    let legacy = conn
+        .prepare("SELECT 1 FROM pragma_table_info('mempool') WHERE name = 'id'")?
+        .exists([])?;
+    let db_tx = conn.unchecked_transaction()?;
+    if legacy {
+        db_tx.execute_batch("ALTER TABLE mempool RENAME TO mempool_legacy;")?;
+    }
+    db_tx.execute_batch(
         "CREATE TABLE IF NOT EXISTS mempool (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            tx_json TEXT
+            tx_hash TEXT PRIMARY KEY,
+            tx_json TEXT,
+            received_at INTEGER
         );",
-    )
+    )?;
+    if legacy {
+        let jsons: Vec<String> = db_tx
+            .prepare("SELECT tx_json FROM mempool_legacy ORDER BY id")?
+            .query_map([], |row| row.get(0))?
+            .flatten()
+            .collect();
+        // 旧表没有接收时间，迁移的交易从现在起计算过期
+        let now = chrono::Utc::now().timestamp() as u64;
+        for json in jsons {
+            if let Ok(tx) = serde_json::from_str::<Transaction>(&json) {
+                insert_mempool_tx(&db_tx, &tx, now)?;
+            }
+        }
+        db_tx.execute_batch("DROP TABLE mempool_legacy;")?;
+    }
+    db_tx.commit()
 }
 
-pub fn insert_mempool_tx(conn: &Connection, tx: &Transaction) -> Result<()> {
+pub fn insert_mempool_tx(conn: &Connection, tx: &Transaction, received_at: u64) -> Result<()> {
     let tx_json = serde_json::to_string(tx).unwrap();
-    conn.execute("INSERT INTO mempool (tx_json) VALUES (?1)", (tx_json,))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO mempool (tx_hash, tx_json, received_at) VALUES (?1, ?2, ?3)",
+        (tx.hash(), tx_json, received_at),
+    )?;
     Ok(())
 }
 
-pub fn remove_mempool_tx(conn: &Connection, tx: &Transaction) -> Result<()> {
-    let tx_json = serde_json::to_string(tx).unwrap();
-    conn.execute("DELETE FROM mempool WHERE tx_json = ?1", (tx_json,))?;
+pub fn remove_mempool_tx(conn: &Connection, tx_hash: &str) -> Result<()> {
+    conn.execute("DELETE FROM mempool WHERE tx_hash = ?1", (tx_hash,))?;
     Ok(())
 }
 
-pub fn load_all_mempool_txs(conn: &Connection) -> Result<Vec<Transaction>> {
-    let mut stmt = conn.prepare("SELECT tx_json FROM mempool")?;
-    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+pub fn load_all_mempool_txs(conn: &Connection) -> Result<Vec<(Transaction, u64)>> {
+    let mut stmt = conn.prepare("SELECT tx_json, received_at FROM mempool ORDER BY received_at")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+    })?;
     let mut txs = Vec::new();
-    for row in rows {
-        if let Ok(json) = row {
-            if let Ok(tx) = serde_json::from_str(&json) {
-                txs.push(tx);
-            }
+    for (json, received_at) in rows.flatten() {
+        if let Ok(tx) = serde_json::from_str(&json) {
+            txs.push((tx, received_at));
         }
     }
     Ok(txs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_databases_are_stamped_and_old_ones_refused() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(check_version(&conn).unwrap().is_ok());
+        init_db(&conn).unwrap();
+        assert!(check_version(&conn).unwrap().is_ok());
+
+        // 旧版本创建的库没有格式版本
+        let old = Connection::open_in_memory().unwrap();
+        old.execute_batch("CREATE TABLE blocks (id INTEGER PRIMARY KEY);")
+            .unwrap();
+        let err = check_version(&old).unwrap().unwrap_err();
+        assert_eq!(err.found, 0);
+        assert!(err.to_string().contains("delete chain.db"));
+    }
+
+    #[test]
+    fn legacy_mempool_table_is_rebuilt() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE mempool (id INTEGER PRIMARY KEY AUTOINCREMENT, tx_json TEXT);",
+        )
+        .unwrap();
+        conn.execute_batch(
+            r#"INSERT INTO mempool (tx_json) VALUES ('{"from":"a","to":"b","amount":1}');"#,
+        )
+        .unwrap();
+        let tx = Transaction::new("a", "b", 1, 0, 0);
+
+        init_mempool_table(&conn).unwrap();
+        let txs = load_all_mempool_txs(&conn).unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].0.hash(), tx.hash());
+        assert!(txs[0].1 > 0);
+        remove_mempool_tx(&conn, &tx.hash()).unwrap();
+        assert!(load_all_mempool_txs(&conn).unwrap().is_empty());
+        // 重建后再次初始化不会改动新表
+        insert_mempool_tx(&conn, &tx, 1).unwrap();
+        init_mempool_table(&conn).unwrap();
+        assert_eq!(load_all_mempool_txs(&conn).unwrap().len(), 1);
+    }
+}
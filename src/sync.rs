@@ -0,0 +1,692 @@
+use crate::block::block::{Block, BlockHeader};
+use crate::blockchain::{self, Blockchain};
+use crate::compact;
+use crate::network::NetworkContext;
+use crate::protocol::{BlockRange, Message};
+use crate::scoring::{self, Misbehavior};
+use crate::session::PeerInfo;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+pub const MAX_HEADERS_PER_REQUEST: u64 = 512;
+pub const MAX_BLOCKS_PER_REQUEST: u64 = 32;
+/// 每个对端同时在途的区块请求数
+const MAX_REQUESTS_PER_PEER: usize = 2;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+/// 已知节点非空时，启动后等待建立会话的时间，超时仍无对端则直接开始出块
+const STARTUP_GRACE_SECS: u64 = 10;
+/// 落后超过该高度才重新进入同步，更小的差距由区块广播追平
+const SYNC_TRIGGER_LAG: u64 = 1;
+/// 已下载但尚未导入的区块最多领先本地链的高度
+const DOWNLOAD_WINDOW: u64 = 1024;
+/// 分叉或无效的对端在该时间后才会再次被选为同步来源
+const PEER_RETRY_SECS: u64 = 30;
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const PROGRESS_LOG_INTERVAL_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPhase {
+    WaitingForPeers,
+    Headers,
+    Blocks,
+    Synced,
+}
+
+/// 供日志与 RPC 展示的同步进度
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncStatus {
+    pub phase: SyncPhase,
+    pub current_height: u64,
+    pub start_height: u64,
+    pub target_height: u64,
+    pub pending_headers: usize,
+    pub downloaded: usize,
+    pub in_flight: usize,
+    pub progress_percent: f64,
+}
+
+struct HeaderRequest {
+    peer: String,
+    start: u64,
+    sent_at: u64,
+}
+
+struct BlockRequest {
+    peer: String,
+    count: u64,
+    sent_at: u64,
+}
+
+/// 先从一个对端下载并验证区块头，再按区块头向多个对端并行请求区块体，按高度顺序导入。
+/// 对端的链与本地分叉时向前回溯找到共同祖先，按 `blockchain::prefer` 判断是否切换到对端的链
+pub struct SyncManager {
+    phase: SyncPhase,
+    created_at: u64,
+    start_height: u64,
+    target_height: u64,
+    header_request: Option<HeaderRequest>,
+    /// 已验证但尚未导入的区块头，从 `fork_base` 或本地链顶的下一个高度开始连续
+    headers: BTreeMap<u64, BlockHeader>,
+    /// 对端的链在本地链顶以下分叉时的共同祖先高度，此时下载完整个分叉后一次性重组
+    fork_base: Option<u64>,
+    /// 寻找共同祖先时下一次回溯的步长，每次翻倍
+    backtrack: u64,
+    /// 提供当前区块头的对端，分叉区块无效时据此惩罚
+    source: Option<String>,
+    /// 在途的区块请求，以起始高度为键
+    requests: HashMap<u64, BlockRequest>,
+    downloaded: BTreeMap<u64, Block>,
+    /// 链不优于本地或返回了无效数据的对端，到期前不作为同步来源
+    retry_at: HashMap<String, u64>,
+    /// 对端发来但无法直接导入的区块（领先多个高度或在分叉上），只用于选择同步来源；
+    /// 区块导入本地链后才计入对端会话的链顶
+    announced: HashMap<String, (u64, String)>,
+    last_log: u64,
+}
+
+impl SyncManager {
+    pub fn new(now: u64) -> Self {
+        SyncManager {
+            phase: SyncPhase::WaitingForPeers,
+            created_at: now,
+            start_height: 0,
+            target_height: 0,
+            header_request: None,
+            headers: BTreeMap::new(),
+            fork_base: None,
+            backtrack: 1,
+            source: None,
+            requests: HashMap::new(),
+            downloaded: BTreeMap::new(),
+            retry_at: HashMap::new(),
+            announced: HashMap::new(),
+            last_log: 0,
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.phase == SyncPhase::Synced
+    }
+
+    pub fn status(&self, current_height: u64) -> SyncStatus {
+        let target = self.target_height.max(current_height);
+        let total = target.saturating_sub(self.start_height);
+        let done = current_height.saturating_sub(self.start_height);
+        let progress_percent = if total == 0 || self.is_synced() {
+            100.0
+        } else {
+            (done as f64 * 100.0 / total as f64).min(100.0)
+        };
+        SyncStatus {
+            phase: self.phase,
+            current_height,
+            start_height: self.start_height,
+            target_height: target,
+            pending_headers: self.headers.len(),
+            downloaded: self.downloaded.len(),
+            in_flight: self.requests.len() + usize::from(self.header_request.is_some()),
+            progress_percent,
+        }
+    }
+
+    fn usable(&self, peer: &str, now: u64) -> bool {
+        self.retry_at.get(peer).is_none_or(|at| now >= *at)
+    }
+
+    /// 按分叉选择规则排在最前的可用对端
+    fn best_peer<'a>(&self, peers: &'a [PeerInfo], now: u64) -> Option<&'a PeerInfo> {
+        peers
+            .iter()
+            .filter(|p| self.usable(&p.node_id, now))
+            .reduce(|best, p| {
+                if blockchain::prefer(
+                    (p.best_height, &p.best_hash),
+                    (best.best_height, &best.best_hash),
+                ) {
+                    p
+                } else {
+                    best
+                }
+            })
+    }
+
+    /// 对端的链顶优于本地链且不在本地链上时需要同步；只领先一个高度时先等待区块广播
+    fn should_sync(chain: &Blockchain, best: &PeerInfo) -> bool {
+        let height = chain.height();
+        blockchain::prefer(
+            (best.best_height, &best.best_hash),
+            (height, &chain.get_last_hash()),
+        ) && chain.block_by_hash(&best.best_hash).is_none()
+            && (best.best_height > height + SYNC_TRIGGER_LAG || best.best_height <= height)
+    }
+
+    /// 暂时不再从该对端同步，并丢弃它尚未验证的公告
+    fn defer(&mut self, peer: &str, now: u64) {
+        self.retry_at
+            .insert(peer.to_string(), now + PEER_RETRY_SECS);
+        self.announced.remove(peer);
+    }
+
+    /// 记录对端发来但无法直接导入的区块，由同步下载验证
+    pub fn on_announced(&mut self, peer: &str, height: u64, hash: &str) {
+        let entry = self
+            .announced
+            .entry(peer.to_string())
+            .or_insert((0, String::new()));
+        if height >= entry.0 {
+            *entry = (height, hash.to_string());
+        }
+    }
+
+    /// 会话中的对端信息叠加尚未验证的公告链顶，供选择同步来源与分配下载使用
+    fn sync_view(&self, peers: Vec<PeerInfo>) -> Vec<PeerInfo> {
+        peers
+            .into_iter()
+            .map(|mut p| {
+                if let Some((height, hash)) = self.announced.get(&p.node_id) {
+                    if blockchain::prefer((*height, hash), (p.best_height, &p.best_hash)) {
+                        p.best_height = *height;
+                        p.best_hash = hash.clone();
+                    }
+                }
+                p
+            })
+            .collect()
+    }
+
+    /// 公告的区块已导入本地链时才更新对端的链顶；断开的对端的公告直接丢弃
+    fn settle_announced(&mut self, ctx: &NetworkContext, peers: &[PeerInfo]) {
+        self.announced
+            .retain(|peer, _| peers.iter().any(|p| &p.node_id == peer));
+        let imported: Vec<(String, u64, String)> = {
+            let chain = ctx.chain.lock().unwrap();
+            self.announced
+                .iter()
+                .filter(|(_, (_, hash))| chain.block_by_hash(hash).is_some())
+                .map(|(peer, (height, hash))| (peer.clone(), *height, hash.clone()))
+                .collect()
+        };
+        let mut sessions = ctx.sessions.lock().unwrap();
+        for (peer, height, hash) in imported {
+            sessions.update_best(&peer, height, &hash);
+            self.announced.remove(&peer);
+        }
+    }
+
+    /// 请求超时的对端暂不作为同步来源并扣分；断开的对端不扣分
+    fn stalled(&mut self, ctx: &NetworkContext, peer: &str, now: u64) {
+        println!(
+            "⚠️ 节点 {} 的同步请求超时，{} 秒后重试",
+            peer, PEER_RETRY_SECS
+        );
+        self.defer(peer, now);
+        scoring::report(ctx, peer, Misbehavior::Stalling);
+    }
+
+    /// 周期性驱动状态机：检查是否落后、重发超时请求、调度下载并导入已就绪的区块
+    pub fn tick(&mut self, ctx: &NetworkContext) {
+        let now = ctx.clock.now_secs();
+        self.import_ready(ctx);
+        let height = ctx.chain.lock().unwrap().height();
+        let floor = self.fork_base.unwrap_or(height);
+        self.headers.retain(|h, _| *h > floor);
+        self.downloaded.retain(|h, _| *h > floor);
+        self.retry_at.retain(|_, at| now < *at);
+        let sessions = ctx.sessions.lock().unwrap().list();
+        self.settle_announced(ctx, &sessions);
+        let peers = self.sync_view(sessions);
+        let best = self.best_peer(&peers, now).cloned();
+        let needs_sync = best
+            .as_ref()
+            .is_some_and(|best| Self::should_sync(&ctx.chain.lock().unwrap(), best));
+
+        match self.phase {
+            SyncPhase::WaitingForPeers => match best {
+                Some(best) if needs_sync || best.best_height > height => {
+                    self.start(ctx, &best, height, now)
+                }
+                Some(_) => self.finish(height),
+                None if ctx.peers.lock().unwrap().list().is_empty()
+                    || now >= self.created_at + STARTUP_GRACE_SECS =>
+                {
+                    self.finish(height)
+                }
+                None => {}
+            },
+            SyncPhase::Synced => {
+                if let Some(best) = best.filter(|_| needs_sync) {
+                    self.start(ctx, &best, height, now);
+                }
+            }
+            SyncPhase::Headers => {
+                let stalled = match &self.header_request {
+                    Some(req) if !peers.iter().any(|p| p.node_id == req.peer) => true,
+                    Some(req) if now >= req.sent_at + REQUEST_TIMEOUT_SECS => {
+                        let peer = req.peer.clone();
+                        self.stalled(ctx, &peer, now);
+                        true
+                    }
+                    Some(_) => false,
+                    None => true,
+                };
+                if stalled {
+                    self.header_request = None;
+                    match self.best_peer(&peers, now).cloned() {
+                        Some(best) if self.headers.is_empty() => {
+                            self.backtrack = 1;
+                            self.request_headers(ctx, &best.node_id, height.max(1), now);
+                        }
+                        Some(best) => {
+                            let start = self.next_header_height(height);
+                            self.request_headers(ctx, &best.node_id, start, now);
+                        }
+                        None => self.reset(),
+                    }
+                }
+            }
+            SyncPhase::Blocks => {
+                let timed_out: Vec<String> = self
+                    .requests
+                    .values()
+                    .filter(|req| now >= req.sent_at + REQUEST_TIMEOUT_SECS)
+                    .map(|req| req.peer.clone())
+                    .collect();
+                for peer in timed_out {
+                    self.stalled(ctx, &peer, now);
+                }
+                self.requests.retain(|_, req| {
+                    now < req.sent_at + REQUEST_TIMEOUT_SECS
+                        && peers.iter().any(|p| p.node_id == req.peer)
+                });
+                self.schedule_blocks(ctx, &peers, height, now);
+                if self.headers.is_empty() && self.requests.is_empty() {
+                    match best {
+                        Some(best) if needs_sync => {
+                            self.target_height = self.target_height.max(best.best_height);
+                            self.phase = SyncPhase::Headers;
+                            self.backtrack = 1;
+                            self.request_headers(ctx, &best.node_id, height.max(1), now);
+                        }
+                        _ => self.finish(height),
+                    }
+                }
+            }
+        }
+
+        if matches!(self.phase, SyncPhase::Headers | SyncPhase::Blocks)
+            && now >= self.last_log + PROGRESS_LOG_INTERVAL_SECS
+        {
+            self.last_log = now;
+            let status = self.status(height);
+            println!(
+                "🔄 区块同步中: 高度 {}/{} ({:.1}%) | 待下载区块头 {} | 已下载 {} | 在途请求 {}",
+                status.current_height,
+                status.target_height,
+                status.progress_percent,
+                status.pending_headers,
+                status.downloaded,
+                status.in_flight
+            );
+        }
+    }
+
+    fn start(&mut self, ctx: &NetworkContext, best: &PeerInfo, height: u64, now: u64) {
+        self.reset();
+        println!(
+            "🔄 本地高度 {} 落后于节点 {} 的高度 {}，开始同步",
+            height, best.node_id, best.best_height
+        );
+        self.phase = SyncPhase::Headers;
+        self.start_height = height;
+        self.target_height = best.best_height;
+        self.last_log = now;
+        // 从本地链顶所在高度开始请求，对端在同一高度分叉时也能发现
+        self.request_headers(ctx, &best.node_id, height.max(1), now);
+    }
+
+    fn finish(&mut self, height: u64) {
+        if self.phase != SyncPhase::Synced {
+            println!("✅ 区块同步完成，当前高度 {}", height);
+        }
+        self.reset();
+        self.phase = SyncPhase::Synced;
+    }
+
+    fn reset(&mut self) {
+        self.phase = SyncPhase::WaitingForPeers;
+        self.header_request = None;
+        self.headers.clear();
+        self.fork_base = None;
+        self.backtrack = 1;
+        self.source = None;
+        self.requests.clear();
+        self.downloaded.clear();
+    }
+
+    fn next_header_height(&self, height: u64) -> u64 {
+        self.headers
+            .keys()
+            .next_back()
+            .map(|h| h + 1)
+            .unwrap_or(height + 1)
+    }
+
+    fn request_headers(&mut self, ctx: &NetworkContext, peer: &str, start: u64, now: u64) {
+        let range = BlockRange {
+            start,
+            count: MAX_HEADERS_PER_REQUEST,
+        };
+        ctx.sessions
+            .lock()
+            .unwrap()
+            .send(peer, Message::GetHeaders(range));
+        self.source = Some(peer.to_string());
+        self.header_request = Some(HeaderRequest {
+            peer: peer.to_string(),
+            start,
+            sent_at: now,
+        });
+    }
+
+    /// 处理区块头响应。第一批从本地链顶高度开始，跳过与本地相同的区块头后必须接在本地链上，
+    /// 否则继续向前回溯寻找共同祖先；之后的批次必须与上一批首尾相连
+    pub fn on_headers(&mut self, ctx: &NetworkContext, peer: &str, headers: Vec<BlockHeader>) {
+        let start = match &self.header_request {
+            Some(req) if req.peer == peer && self.phase == SyncPhase::Headers => req.start,
+            _ => return,
+        };
+        self.header_request = None;
+        let now = ctx.clock.now_secs();
+        let (height, tip_hash) = {
+            let chain = ctx.chain.lock().unwrap();
+            (chain.height(), chain.get_last_hash())
+        };
+        let received = headers.len() as u64;
+        for (offset, header) in headers.iter().enumerate() {
+            let expected = start + offset as u64;
+            let linked = offset == 0 || header.previous_hash == headers[offset - 1].hash;
+            if header.index != expected
+                || header.hash != header.calculate_hash()
+                || !header.verify_signature()
+                || !linked
+            {
+                println!("⚠️ 节点 {} 返回的区块头 {} 无效", peer, expected);
+                self.defer(peer, now);
+                self.reset();
+                scoring::report(ctx, peer, Misbehavior::InvalidHeaders);
+                return;
+            }
+        }
+
+        let peer_best = self
+            .sync_view(ctx.sessions.lock().unwrap().list())
+            .into_iter()
+            .find(|p| p.node_id == peer)
+            .map(|p| p.best_height)
+            .unwrap_or(0);
+        // 不足一批说明对端的链到此为止，却低于它公告的高度
+        if received < MAX_HEADERS_PER_REQUEST && start + received <= peer_best {
+            println!(
+                "⚠️ 节点 {} 公告高度 {}，区块头却只到 {}",
+                peer,
+                peer_best,
+                (start + received).saturating_sub(1)
+            );
+            self.defer(peer, now);
+            self.reset();
+            scoring::report(ctx, peer, Misbehavior::Stalling);
+            return;
+        }
+
+        let new_headers = if self.headers.is_empty() {
+            let chain = ctx.chain.lock().unwrap();
+            let known = headers
+                .iter()
+                .take_while(|h| {
+                    chain
+                        .chain
+                        .get(h.index as usize)
+                        .is_some_and(|b| b.hash == h.hash)
+                })
+                .count();
+            let mut headers = headers;
+            let new_headers = headers.split_off(known);
+            let Some(first) = new_headers.first() else {
+                drop(chain);
+                if received == MAX_HEADERS_PER_REQUEST {
+                    self.request_headers(ctx, peer, start + received, now);
+                } else {
+                    // 对端的链没有本地之外的区块
+                    self.finish(height);
+                }
+                return;
+            };
+            let base = first.index - 1;
+            if chain.chain[base as usize].hash != first.previous_hash {
+                drop(chain);
+                // 只有第一个区块头就与本地不同时才会走到这里，共同祖先在 `start` 之前
+                if start <= 1 {
+                    println!("⚠️ 节点 {} 的区块头无法接在创世区块上", peer);
+                    self.defer(peer, now);
+                    self.reset();
+                    scoring::report(ctx, peer, Misbehavior::InvalidHeaders);
+                } else {
+                    let back = start.saturating_sub(self.backtrack).max(1);
+                    self.backtrack *= 2;
+                    self.request_headers(ctx, peer, back, now);
+                }
+                return;
+            }
+            if base < height {
+                println!("🍴 与节点 {} 的链在高度 {} 之后分叉", peer, base);
+                self.fork_base = Some(base);
+            }
+            new_headers
+        } else {
+            let last_hash = self.headers.values().next_back().map(|h| h.hash.clone());
+            if headers
+                .first()
+                .is_some_and(|h| Some(&h.previous_hash) != last_hash.as_ref())
+            {
+                println!("⚠️ 节点 {} 在同步过程中切换了链，稍后重试", peer);
+                self.defer(peer, now);
+                self.reset();
+                return;
+            }
+            headers
+        };
+        for header in new_headers {
+            self.target_height = self.target_height.max(header.index);
+            self.headers.insert(header.index, header);
+        }
+
+        let next = self.next_header_height(height);
+        // 分叉需要整段下载后才能切换，区块头数量限制在下载窗口内
+        let within_window = self.fork_base.is_none() || next <= height + DOWNLOAD_WINDOW;
+        if received == MAX_HEADERS_PER_REQUEST && peer_best >= next && within_window {
+            self.request_headers(ctx, peer, next, now);
+            return;
+        }
+        if let Some(tip) = self.headers.values().next_back() {
+            if !blockchain::prefer((tip.index, &tip.hash), (height, &tip_hash)) {
+                println!(
+                    "🍴 节点 {} 的分叉链顶 {} 不优于本地链，{} 秒后重试",
+                    peer, tip.index, PEER_RETRY_SECS
+                );
+                self.defer(peer, now);
+                self.finish(height);
+                return;
+            }
+        }
+        self.phase = SyncPhase::Blocks;
+        let peers = self.sync_view(ctx.sessions.lock().unwrap().list());
+        self.schedule_blocks(ctx, &peers, height, now);
+    }
+
+    /// 将尚未请求的区块头高度切分为区间，分配给在途请求最少且高度足够的对端
+    fn schedule_blocks(&mut self, ctx: &NetworkContext, peers: &[PeerInfo], height: u64, now: u64) {
+        let mut in_flight: HashMap<&str, usize> = HashMap::new();
+        for req in self.requests.values() {
+            *in_flight.entry(req.peer.as_str()).or_insert(0) += 1;
+        }
+        let covered: HashSet<u64> = self
+            .requests
+            .iter()
+            .flat_map(|(start, req)| *start..start + req.count)
+            .collect();
+        let missing: Vec<u64> = self
+            .headers
+            .keys()
+            .copied()
+            .take_while(|h| *h <= height + DOWNLOAD_WINDOW)
+            .filter(|h| !self.downloaded.contains_key(h) && !covered.contains(h))
+            .collect();
+
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for h in missing {
+            match ranges.last_mut() {
+                Some((start, count)) if *start + *count == h && *count < MAX_BLOCKS_PER_REQUEST => {
+                    *count += 1
+                }
+                _ => ranges.push((h, 1)),
+            }
+        }
+
+        let mut new_requests = Vec::new();
+        for (start, count) in ranges {
+            let end = start + count - 1;
+            let peer = peers
+                .iter()
+                .filter(|p| self.usable(&p.node_id, now) && p.best_height >= end)
+                .filter(|p| {
+                    in_flight.get(p.node_id.as_str()).copied().unwrap_or(0) < MAX_REQUESTS_PER_PEER
+                })
+                .min_by_key(|p| in_flight.get(p.node_id.as_str()).copied().unwrap_or(0));
+            let Some(peer) = peer else { break };
+            *in_flight.entry(peer.node_id.as_str()).or_insert(0) += 1;
+            new_requests.push((peer.node_id.clone(), start, count));
+        }
+
+        let sessions = ctx.sessions.lock().unwrap();
+        for (peer, start, count) in new_requests {
+            if sessions.send(&peer, Message::GetBlocks(BlockRange { start, count })) {
+                self.requests.insert(
+                    start,
+                    BlockRequest {
+                        peer,
+                        count,
+                        sent_at: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// 处理区块响应：区块必须与已验证的区块头一致，随后按顺序导入
+    pub fn on_blocks(&mut self, ctx: &NetworkContext, peer: &str, blocks: Vec<Block>) {
+        let Some(first) = blocks.first().map(|b| b.index) else {
+            return;
+        };
+        match self.requests.get(&first) {
+            Some(req) if req.peer == peer => {}
+            _ => return,
+        }
+        self.requests.remove(&first);
+        for block in blocks {
+            let valid = self
+                .headers
+                .get(&block.index)
+                .map(|h| h.hash == block.hash && block.calculate_hash() == block.hash)
+                .unwrap_or(false);
+            if !valid {
+                println!("⚠️ 节点 {} 返回的区块 {} 与区块头不一致", peer, block.index);
+                scoring::report(ctx, peer, Misbehavior::InvalidBlock);
+                break;
+            }
+            self.downloaded.insert(block.index, block);
+        }
+        self.import_ready(ctx);
+    }
+
+    fn import_ready(&mut self, ctx: &NetworkContext) {
+        if let Some(base) = self.fork_base {
+            self.import_fork(ctx, base);
+            return;
+        }
+        loop {
+            let next = ctx.chain.lock().unwrap().height() + 1;
+            let Some(block) = self.downloaded.remove(&next) else {
+                break;
+            };
+            self.headers.remove(&next);
+            if let Err(e) = ctx.import_block(block) {
+                println!("❌ 同步的区块 {} 校验失败: {}", next, e);
+                self.reset();
+                break;
+            }
+        }
+    }
+
+    /// 分叉上的区块全部下载后一次性重组，避免本地链停在两条链之间
+    fn import_fork(&mut self, ctx: &NetworkContext, base: u64) {
+        let Some(last) = self.headers.keys().next_back().copied() else {
+            return;
+        };
+        if self.phase != SyncPhase::Blocks
+            || (base + 1..=last).any(|h| !self.downloaded.contains_key(&h))
+        {
+            return;
+        }
+        let blocks: Vec<Block> = std::mem::take(&mut self.downloaded).into_values().collect();
+        self.headers.clear();
+        self.fork_base = None;
+        match ctx.reorg(base, blocks) {
+            Ok(true) => {}
+            Ok(false) => println!("🍴 分叉链不再优于本地链，放弃重组"),
+            Err(e) => {
+                println!("❌ 分叉链上的区块校验失败: {}", e);
+                if let Some(peer) = self.source.take() {
+                    self.defer(&peer, ctx.clock.now_secs());
+                    scoring::report(ctx, &peer, Misbehavior::InvalidBlock);
+                }
+                self.reset();
+            }
+        }
+    }
+}
+
+/// 从 `range.start` 开始的区块头，数量不超过单次请求上限
+pub fn headers_in_range(chain: &Blockchain, range: BlockRange) -> Vec<BlockHeader> {
+    let count = range.count.min(MAX_HEADERS_PER_REQUEST) as usize;
+    chain
+        .chain
+        .iter()
+        .skip(range.start as usize)
+        .take(count)
+        .map(|b| b.header())
+        .collect()
+}
+
+pub fn blocks_in_range(chain: &Blockchain, range: BlockRange) -> Vec<Block> {
+    let count = range.count.min(MAX_BLOCKS_PER_REQUEST) as usize;
+    chain
+        .chain
+        .iter()
+        .skip(range.start as usize)
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+/// 定期驱动同步状态机，并检查等待交易超时的压缩区块
+pub fn spawn_sync(ctx: NetworkContext) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(TICK_INTERVAL).await;
+            ctx.sync.lock().unwrap().tick(&ctx);
+            compact::tick(&ctx);
+        }
+    });
+}
@@ -1,18 +1,169 @@
+use crate::keys;
+use crate::network::DEFAULT_CHAIN_ID;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// 签名内容的固定前缀，避免交易签名被当作其他用途的签名重放
+pub const SIGNING_DOMAIN: &str = "async-pos-chain/tx/v1";
+
+fn default_chain_id() -> String {
+    DEFAULT_CHAIN_ID.to_string()
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     pub from: String,
     pub to: String,
     pub amount: u64,
+    #[serde(default)]
+    pub fee: u64,
+    #[serde(default)]
+    pub nonce: u64,
+    /// 交易所属的链，签名覆盖该字段，不能在其他链上重放
+    #[serde(default = "default_chain_id")]
+    pub chain_id: String,
+    /// 十六进制编码的 ed25519 公钥，其地址必须等于 `from`；未签名交易为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 impl Transaction {
-    pub fn new(from: &str, to: &str, amount: u64) -> Self {
+    pub fn new(from: &str, to: &str, amount: u64, fee: u64, nonce: u64) -> Self {
         Transaction {
             from: from.to_string(),
             to: to.to_string(),
             amount,
+            fee,
+            nonce,
+            chain_id: default_chain_id(),
+            public_key: None,
+            signature: None,
+        }
+    }
+
+    /// 以发送方密钥创建并签名一笔交易，`from` 为密钥对应的地址
+    pub fn signed(key: &SigningKey, to: &str, amount: u64, fee: u64, nonce: u64) -> Self {
+        let from = keys::address_of(key.verifying_key().as_bytes());
+        let mut tx = Transaction::new(&from, to, amount, fee, nonce);
+        tx.sign(key);
+        tx
+    }
+
+    /// 指定交易所属的链，需在签名前调用
+    pub fn with_chain_id(mut self, chain_id: &str) -> Self {
+        self.chain_id = chain_id.to_string();
+        self
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) {
+        let signature = key.sign(self.signing_payload().as_bytes());
+        self.public_key = Some(hex::encode(key.verifying_key().as_bytes()));
+        self.signature = Some(hex::encode(signature.to_bytes()));
+    }
+
+    /// 参与签名与交易哈希计算的字段，以签名域和链 ID 开头
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}:{}",
+            SIGNING_DOMAIN, self.chain_id, self.from, self.to, self.amount, self.fee, self.nonce
+        )
+    }
+
+    /// 金额为 0 的自转账，用于以更高手续费取消同 nonce 的待打包交易
+    pub fn is_cancellation(&self) -> bool {
+        self.from == self.to && self.amount == 0
+    }
+
+    pub fn hash_bytes(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.signing_payload().as_bytes());
+        hasher.finalize().into()
+    }
+
+    pub fn hash(&self) -> String {
+        format!("0x{}", hex::encode(self.hash_bytes()))
+    }
+
+    /// 交易必须带有签名，签名能用附带的公钥验证，且公钥对应的地址就是 `from`
+    pub fn verify_signature(&self) -> bool {
+        let (Some(pk), Some(sig)) = (&self.public_key, &self.signature) else {
+            return false;
+        };
+        let pk: Option<[u8; 32]> = hex::decode(pk).ok().and_then(|b| b.try_into().ok());
+        let sig: Option<[u8; 64]> = hex::decode(sig).ok().and_then(|b| b.try_into().ok());
+        let (Some(pk), Some(sig)) = (pk, sig) else {
+            return false;
+        };
+        if keys::address_of(&pk) != self.from {
+            return false;
         }
+        VerifyingKey::from_bytes(&pk)
+            .map(|vk| {
+                vk.verify(
+                    self.signing_payload().as_bytes(),
+                    &Signature::from_bytes(&sig),
+                )
+                .is_ok()
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn encoded_size(&self) -> usize {
+        serde_json::to_string(self)
+            .map(|s| s.len())
+            .unwrap_or(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_transaction_verifies_and_binds_sender_to_key() {
+        let key = keys::dev_key("Alice");
+        let tx = Transaction::signed(&key, &keys::dev_address("Bob"), 5, 1, 0);
+        assert_eq!(tx.from, keys::dev_address("Alice"));
+        assert!(tx.verify_signature());
+    }
+
+    #[test]
+    fn unsigned_transaction_is_rejected() {
+        let tx = Transaction::new(&keys::dev_address("Alice"), "b", 5, 1, 0);
+        assert!(!tx.verify_signature());
+    }
+
+    #[test]
+    fn key_must_match_sender_address() {
+        // 用自己的密钥签名但把 from 写成别人的地址
+        let mut tx = Transaction::new(&keys::dev_address("admin"), "b", 5, 1, 0);
+        tx.sign(&keys::dev_key("Alice"));
+        assert!(!tx.verify_signature());
+    }
+
+    #[test]
+    fn tampered_fields_break_the_signature() {
+        let mut tx = Transaction::signed(&keys::dev_key("Alice"), "b", 5, 1, 0);
+        tx.amount = 50;
+        assert!(!tx.verify_signature());
+    }
+
+    #[test]
+    fn signature_is_bound_to_the_chain_id() {
+        let tx = Transaction::signed(&keys::dev_key("Alice"), "b", 5, 1, 0);
+        assert!(tx.signing_payload().starts_with(SIGNING_DOMAIN));
+        let replayed = tx.clone().with_chain_id("other-chain");
+        assert!(!replayed.verify_signature());
+        assert_ne!(replayed.hash(), tx.hash());
+    }
+
+    #[test]
+    fn hash_ignores_signature() {
+        let tx = Transaction::signed(&keys::dev_key("Alice"), "b", 5, 1, 0);
+        let unsigned = Transaction::new(&tx.from, "b", 5, 1, 0);
+        assert_eq!(tx.hash(), unsigned.hash());
     }
 }
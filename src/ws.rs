@@ -0,0 +1,322 @@
+use crate::block::block::Block;
+use crate::events::ChainEvent;
+use crate::network::NetworkContext;
+use crate::openrpc::{Param, Schema};
+use crate::ratelimit::ConnectionPermit;
+use crate::rpc::{self, Access, Params, RpcError};
+use crate::transaction::Transaction;
+use futures_util::{SinkExt, StreamExt};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message, Role, WebSocketConfig};
+use tokio_tungstenite::WebSocketStream;
+
+/// 单个连接最多同时保持的订阅数
+const MAX_SUBSCRIPTIONS: usize = 32;
+pub const SUBSCRIBE_PARAMS: &[Param] = &[
+    Param::required(
+        "kind",
+        Schema::Enum(&[
+            "new_heads",
+            "pending_transactions",
+            "finalized_blocks",
+            "address_activity",
+        ]),
+    ),
+    Param::optional("address", Schema::String),
+];
+pub const UNSUBSCRIBE_PARAMS: &[Param] = &[Param::required("subscription", Schema::String)];
+
+/// 订阅的事件类型，`address_activity` 只推送与该地址相关的交易
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Topic {
+    NewHeads,
+    PendingTransactions,
+    FinalizedBlocks,
+    AddressActivity(String),
+}
+
+impl Topic {
+    fn parse(params: &Params) -> Result<Self, RpcError> {
+        match params.str(0)? {
+            "new_heads" => Ok(Topic::NewHeads),
+            "pending_transactions" => Ok(Topic::PendingTransactions),
+            "finalized_blocks" => Ok(Topic::FinalizedBlocks),
+            "address_activity" => Ok(Topic::AddressActivity(params.str(1)?.to_string())),
+            other => Err(RpcError::invalid_params(format!(
+                "unknown subscription kind `{}`",
+                other
+            ))),
+        }
+    }
+
+    /// 事件在该订阅下应推送的内容，可能为零条或多条
+    fn payloads(&self, event: &ChainEvent) -> Vec<Value> {
+        match (self, event) {
+            (Topic::NewHeads, ChainEvent::NewBlock(block))
+            | (Topic::FinalizedBlocks, ChainEvent::Finalized(block)) => {
+                vec![json!(block.header())]
+            }
+            (Topic::NewHeads, ChainEvent::Removed(block)) => {
+                let mut header = json!(block.header());
+                header["removed"] = json!(true);
+                vec![header]
+            }
+            (Topic::PendingTransactions, ChainEvent::PendingTransaction(tx)) => {
+                vec![json!({"hash": tx.hash(), "transaction": tx})]
+            }
+            (Topic::AddressActivity(address), ChainEvent::PendingTransaction(tx)) => {
+                if touches(tx, address) {
+                    vec![activity("pending", tx, None)]
+                } else {
+                    Vec::new()
+                }
+            }
+            (Topic::AddressActivity(address), ChainEvent::NewBlock(block)) => {
+                block_activity("confirmed", address, block)
+            }
+            (Topic::AddressActivity(address), ChainEvent::Removed(block)) => {
+                block_activity("removed", address, block)
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn touches(tx: &Transaction, address: &str) -> bool {
+    tx.from == address || tx.to == address
+}
+
+fn block_activity(status: &str, address: &str, block: &Block) -> Vec<Value> {
+    block
+        .transactions
+        .iter()
+        .filter(|tx| touches(tx, address))
+        .map(|tx| activity(status, tx, Some(block)))
+        .collect()
+}
+
+fn activity(status: &str, tx: &Transaction, block: Option<&Block>) -> Value {
+    json!({
+        "status": status,
+        "block_height": block.map(|b| b.index),
+        "block_hash": block.map(|b| &b.hash),
+        "hash": tx.hash(),
+        "transaction": tx
+    })
+}
+
+/// 处理升级后的 WebSocket 连接：普通 RPC 调用照常回复，subscribe/unsubscribe 管理本连接的订阅
+pub async fn serve(
+    upgraded: Upgraded,
+    node: Option<NetworkContext>,
+    access: Access,
+    max_message_bytes: usize,
+    _permit: Arc<ConnectionPermit>,
+) {
+    let config = WebSocketConfig {
+        max_message_size: Some(max_message_bytes),
+        max_frame_size: Some(max_message_bytes),
+        ..Default::default()
+    };
+    let mut ws =
+        WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, Some(config)).await;
+    let mut events = node.as_ref().map(|ctx| ctx.events.subscribe());
+    let mut subscriptions: HashMap<String, Topic> = HashMap::new();
+    loop {
+        let outgoing: Vec<Value> = tokio::select! {
+            msg = ws.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    handle_text(&text, node.as_ref(), access, &mut subscriptions)
+                        .into_iter()
+                        .collect()
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            event = next_event(&mut events) => match event {
+                Ok(event) => notifications(&event, &subscriptions),
+                // 丢失的事件无法补发，有订阅的连接直接关闭，由客户端重新连接并订阅
+                Err(RecvError::Lagged(_)) if subscriptions.is_empty() => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    println!("⚠️ [WebSocket] 订阅方处理过慢，丢弃 {} 个事件，关闭连接", skipped);
+                    let _ = ws.send(Message::Close(Some(lagged(skipped)))).await;
+                    return;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+        for msg in outgoing {
+            if ws.send(Message::Text(msg.to_string())).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn lagged(skipped: u64) -> CloseFrame<'static> {
+    CloseFrame {
+        code: CloseCode::Again,
+        reason: format!("subscriber lagged behind, {} events dropped", skipped).into(),
+    }
+}
+
+/// 独立启动的 RPC 服务没有事件来源，永远等待
+async fn next_event(
+    events: &mut Option<broadcast::Receiver<ChainEvent>>,
+) -> Result<ChainEvent, RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn notifications(event: &ChainEvent, subscriptions: &HashMap<String, Topic>) -> Vec<Value> {
+    subscriptions
+        .iter()
+        .flat_map(|(id, topic)| {
+            topic.payloads(event).into_iter().map(move |result| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "subscription",
+                    "params": {"subscription": id, "result": result}
+                })
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn handle_text(
+    text: &str,
+    node: Option<&NetworkContext>,
+    access: Access,
+    subscriptions: &mut HashMap<String, Topic>,
+) -> Option<Value> {
+    let req: Value = serde_json::from_str(text).unwrap_or(Value::Null);
+    let method = req.get("method").and_then(|m| m.as_str());
+    let is_v2 = req.get("jsonrpc").and_then(|v| v.as_str()) == Some("2.0");
+    let outcome = match method {
+        Some("subscribe") if is_v2 => subscribe(req.get("params"), node, subscriptions),
+        Some("unsubscribe") if is_v2 => unsubscribe(req.get("params"), subscriptions),
+        // 其余请求（包括批量与格式错误的请求）交给普通的 RPC 处理
+        _ => return rpc::handle_jsonrpc_body(text, node, access),
+    };
+    req.get("id").cloned().map(|id| rpc::response(id, outcome))
+}
+
+fn subscribe(
+    params: Option<&Value>,
+    node: Option<&NetworkContext>,
+    subscriptions: &mut HashMap<String, Topic>,
+) -> Result<Value, RpcError> {
+    if node.is_none() {
+        return Err(RpcError::node_not_running());
+    }
+    let topic = Topic::parse(&Params::parse(params, SUBSCRIBE_PARAMS)?)?;
+    if subscriptions.len() >= MAX_SUBSCRIPTIONS {
+        return Err(RpcError::new(
+            rpc::TOO_MANY_SUBSCRIPTIONS,
+            format!("at most {} subscriptions per connection", MAX_SUBSCRIPTIONS),
+        ));
+    }
+    let id = format!("0x{:016x}", rand::random::<u64>());
+    subscriptions.insert(id.clone(), topic);
+    Ok(json!(id))
+}
+
+fn unsubscribe(
+    params: Option<&Value>,
+    subscriptions: &mut HashMap<String, Topic>,
+) -> Result<Value, RpcError> {
+    let params = Params::parse(params, UNSUBSCRIBE_PARAMS)?;
+    let id = params.str(0)?;
+    Ok(json!(subscriptions.remove(id).is_some()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+
+    fn request(method: &str, params: Value) -> String {
+        json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1}).to_string()
+    }
+
+    #[test]
+    fn subscriptions_are_per_connection_and_need_a_node() {
+        let ctx = crate::simulator::sim_node(0, crate::clock::Clock::new_virtual(), 0);
+        let mut subs = HashMap::new();
+        let text = request("subscribe", json!(["new_heads"]));
+        let reply = handle_text(&text, None, Access::Public, &mut subs).unwrap();
+        assert_eq!(reply["error"]["code"], json!(rpc::NODE_NOT_RUNNING));
+
+        let reply = handle_text(&text, Some(&ctx), Access::Public, &mut subs).unwrap();
+        let id = reply["result"].as_str().unwrap().to_string();
+        assert_eq!(subs.get(&id), Some(&Topic::NewHeads));
+        let missing = request("subscribe", json!(["address_activity"]));
+        let reply = handle_text(&missing, Some(&ctx), Access::Public, &mut subs).unwrap();
+        assert_eq!(reply["error"]["code"], json!(rpc::INVALID_PARAMS));
+
+        let unsub = request("unsubscribe", json!([id]));
+        let reply = handle_text(&unsub, Some(&ctx), Access::Public, &mut subs).unwrap();
+        assert_eq!(reply["result"], json!(true));
+        let reply = handle_text(&unsub, Some(&ctx), Access::Public, &mut subs).unwrap();
+        assert_eq!(reply["result"], json!(false));
+
+        let other = request("get_height", json!([]));
+        let reply = handle_text(&other, Some(&ctx), Access::Public, &mut subs).unwrap();
+        assert_eq!(reply["result"], json!(0));
+    }
+
+    #[test]
+    fn address_activity_only_pushes_matching_transactions() {
+        let alice = keys::dev_address("Alice");
+        let subs = HashMap::from([
+            ("a".to_string(), Topic::AddressActivity(alice.clone())),
+            ("p".to_string(), Topic::PendingTransactions),
+        ]);
+        let to_alice = Transaction::signed(&keys::dev_key("admin"), &alice, 1, 0, 0);
+        let to_other = Transaction::signed(&keys::dev_key("admin"), "b", 1, 0, 1);
+
+        let pushed = notifications(&ChainEvent::PendingTransaction(to_alice.clone()), &subs);
+        assert_eq!(pushed.len(), 2);
+        let pushed = notifications(&ChainEvent::PendingTransaction(to_other.clone()), &subs);
+        assert_eq!(pushed.len(), 1);
+        assert_eq!(pushed[0]["params"]["subscription"], json!("p"));
+
+        let mut chain = crate::blockchain::Blockchain::genesis();
+        chain.add_dev_block(vec![to_alice.clone(), to_other], 1);
+        let block = chain.chain.last().unwrap().clone();
+        let pushed = notifications(&ChainEvent::NewBlock(block.clone()), &subs);
+        assert_eq!(pushed.len(), 1);
+        let result = &pushed[0]["params"]["result"];
+        assert_eq!(result["status"], json!("confirmed"));
+        assert_eq!(result["hash"], json!(to_alice.hash()));
+
+        // 重组移出区块时，其中的交易以 removed 状态再推送一次
+        let pushed = notifications(&ChainEvent::Removed(block), &subs);
+        assert_eq!(pushed.len(), 1);
+        let result = &pushed[0]["params"]["result"];
+        assert_eq!(result["status"], json!("removed"));
+        assert_eq!(result["hash"], json!(to_alice.hash()));
+    }
+
+    #[test]
+    fn new_heads_flag_removed_blocks() {
+        let subs = HashMap::from([("h".to_string(), Topic::NewHeads)]);
+        let mut chain = crate::blockchain::Blockchain::genesis();
+        chain.add_dev_block(vec![], 1);
+        let block = chain.chain.last().unwrap().clone();
+        let added = notifications(&ChainEvent::NewBlock(block.clone()), &subs);
+        assert_eq!(added[0]["params"]["result"]["hash"], json!(block.hash));
+        assert!(added[0]["params"]["result"].get("removed").is_none());
+        let removed = notifications(&ChainEvent::Removed(block.clone()), &subs);
+        assert_eq!(removed[0]["params"]["result"]["hash"], json!(block.hash));
+        assert_eq!(removed[0]["params"]["result"]["removed"], json!(true));
+    }
+}